@@ -0,0 +1,323 @@
+use ckb_jsonrpc_types::Either;
+use ckb_types::{
+    core, packed,
+    prelude::{Entity, IntoTransactionView},
+    H256,
+};
+use eyre::{eyre, Result};
+
+use crate::{
+    fee::FeeEstimator,
+    instruction::Instruction,
+    operation::Log,
+    rpc::RPC,
+    skeleton::{ScriptEx, TransactionSkeleton},
+};
+
+/// Default number of blocks that a sent transaction stays pending before bumping its fee
+pub const DEFAULT_PENDING_BLOCKS: u64 = 10;
+
+/// Default additional fee rate in shannons/KW that stacked on each bump
+pub const DEFAULT_FEE_RATE_STEP: u64 = 1000;
+
+/// Default limit of replacements for one transaction
+pub const DEFAULT_MAX_BUMPS: usize = 3;
+
+pub type Signer<T> = Box<dyn Fn() -> Instruction<T> + Send + Sync>;
+
+/// Policy of replacing a pending transaction with higher fee, which follows the RBF rules of CKB tx pool
+///
+/// The replacement keeps all of the original inputs, the raised fee is deducted from the change cell of balancer
+/// if possible, otherwise new inputs of balancer are added, and then it's re-signed by `signer`
+///
+/// e.g. escalate the fee if pending for 5 blocks, at most twice:
+/// ```ignore
+/// let fee_bump = FeeBump::new(address.payload().into(), move || {
+///     Instruction::new(vec![Box::new(AddSecp256k1SighashSignatures {
+///         user_lock_scripts: vec![address.payload().into()],
+///         user_private_keys: vec![privkey],
+///     })])
+/// })
+/// .pending_blocks(5)
+/// .max_bumps(2);
+/// skeleton.send_and_wait_with_fee_bump(&rpc, 1, None, &fee_bump).await?;
+/// ```
+pub struct FeeBump<T: RPC> {
+    balancer: ScriptEx,
+    signer: Signer<T>,
+    estimator: FeeEstimator,
+    fee_rate_step: u64,
+    pub(crate) pending_blocks: u64,
+    pub(crate) max_bumps: usize,
+}
+
+impl<T: RPC> FeeBump<T> {
+    /// # Parameters
+    /// - `balancer`: who pays the raised fee, and whose change cell absorbs it
+    /// - `signer`: produce the instruction that re-signs the replacement, e.g. the signing part of original recipe
+    pub fn new<F>(balancer: ScriptEx, signer: F) -> Self
+    where
+        F: Fn() -> Instruction<T> + Send + Sync + 'static,
+    {
+        FeeBump {
+            balancer,
+            signer: Box::new(signer),
+            estimator: FeeEstimator::default(),
+            fee_rate_step: DEFAULT_FEE_RATE_STEP,
+            pending_blocks: DEFAULT_PENDING_BLOCKS,
+            max_bumps: DEFAULT_MAX_BUMPS,
+        }
+    }
+
+    /// Estimate the placeholder witnesses of the replacement, refer to `FeeEstimator`
+    pub fn estimator(mut self, estimator: FeeEstimator) -> Self {
+        self.estimator = estimator;
+        self
+    }
+
+    pub fn fee_rate_step(mut self, fee_rate_step: u64) -> Self {
+        self.fee_rate_step = fee_rate_step;
+        self
+    }
+
+    pub fn pending_blocks(mut self, pending_blocks: u64) -> Self {
+        self.pending_blocks = pending_blocks;
+        self
+    }
+
+    pub fn max_bumps(mut self, max_bumps: usize) -> Self {
+        self.max_bumps = max_bumps;
+        self
+    }
+
+    /// Rebuild the pending transaction with higher fee and re-sign it, the result is ready to be sent
+    ///
+    /// # Parameters
+    /// - `skeleton`: the skeleton that has been sent as `pending_hash`
+    /// - `bumps`: how many times it has been bumped, the additional fee rate is `fee_rate_step * (bumps + 1)`
+    pub async fn bump(
+        &self,
+        rpc: &T,
+        skeleton: &TransactionSkeleton,
+        pending_hash: &H256,
+        bumps: usize,
+    ) -> Result<TransactionSkeleton> {
+        let min_replace_fee: u64 = rpc
+            .get_transaction(pending_hash)
+            .await?
+            .and_then(|tx| tx.min_replace_fee)
+            .ok_or(eyre!("tx {pending_hash:#x} is not replaceable"))?
+            .into();
+        // the fee rate that makes the replacement pay at least `min_replace_fee`, weight only grows in rebalancing
+        let size = self.estimator.estimate_size(skeleton);
+        let min_fee_rate = u64::from(rpc.tx_pool_info().await?.min_fee_rate);
+        let replace_fee_rate = (min_replace_fee * 1000)
+            .div_ceil(size)
+            .saturating_sub(min_fee_rate);
+        let additional_fee_rate = replace_fee_rate.max(self.fee_rate_step * (bumps as u64 + 1));
+        let mut bumped = skeleton.clone();
+        bumped
+            .rebalance(rpc, additional_fee_rate, &self.estimator, &self.balancer)
+            .await?;
+        (self.signer)()
+            .run(rpc, &mut bumped, &mut Log::new())
+            .await?;
+        Ok(bumped)
+    }
+
+    /// Same as `bump`, but recover the skeleton from the pending transaction in tx pool, which is useful when only
+    /// the tx hash is kept, e.g. rebuilt from the original recipe in another process
+    pub async fn bump_by_hash(
+        &self,
+        rpc: &T,
+        pending_hash: &H256,
+        bumps: usize,
+    ) -> Result<TransactionSkeleton> {
+        let tx = rpc
+            .get_transaction(pending_hash)
+            .await?
+            .and_then(|v| v.transaction)
+            .ok_or(eyre!("no tx found: {pending_hash:#x}"))?;
+        let tx: core::TransactionView = match tx.inner {
+            Either::Left(view) => packed::Transaction::from(view.inner).into_view(),
+            Either::Right(bytes) => packed::Transaction::from_slice(bytes.as_bytes())
+                .map_err(|_| eyre!("invalid tx: {pending_hash:#x}"))?
+                .into_view(),
+        };
+        let skeleton = TransactionSkeleton::new_from_transaction_view(rpc, &tx).await?;
+        self.bump(rpc, &skeleton, pending_hash, bumps).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ckb_types::{
+        core::Capacity,
+        packed::{CellInput, CellOutput, OutPoint, Script},
+        prelude::{Builder, Pack, Unpack},
+    };
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{
+        selection::FirstFit,
+        simulation::FakeRpcClient,
+        skeleton::{CellInputEx, CellOutputEx, ChangePolicy, ChangeReceiver, Contribution, Payer},
+    };
+
+    const CKB: u64 = 100_000_000;
+
+    fn out_point(tx: u8) -> OutPoint {
+        OutPoint::new_builder().tx_hash([tx; 32].pack()).build()
+    }
+
+    fn lock(args: u8) -> Script {
+        ScriptEx::new_code(H256::default(), vec![args]).to_script_unchecked()
+    }
+
+    fn capacity_cell(capacity: u64, lock_args: u8) -> CellOutputEx {
+        let output = CellOutput::new_builder()
+            .capacity(Capacity::shannons(capacity).pack())
+            .lock(lock(lock_args))
+            .build();
+        CellOutputEx::new(output, vec![])
+    }
+
+    fn fee_bump(fee_rate_step: u64) -> FeeBump<FakeRpcClient> {
+        FeeBump::new(lock(1).into(), || Instruction::new(vec![])).fee_rate_step(fee_rate_step)
+    }
+
+    fn inputs(skeleton: &TransactionSkeleton) -> Vec<OutPoint> {
+        skeleton
+            .inputs
+            .iter()
+            .map(|v| v.input.previous_output())
+            .collect()
+    }
+
+    fn fee(skeleton: &TransactionSkeleton) -> u64 {
+        skeleton.exceeded_capacity().as_u64()
+    }
+
+    /// Transfer 500 CKB from a 1000 CKB cell of lock args 1 to lock args 2 under the minimal fee rate, and put it into
+    /// tx pool as pending, the change cell goes back to lock args 1
+    fn pending_transfer(
+        rpc: &mut FakeRpcClient,
+        min_replace_fee: u64,
+    ) -> (TransactionSkeleton, H256) {
+        rpc.fake_provider.fake_feerate = 1000;
+        rpc.insert_fake_cell(out_point(1), capacity_cell(1000 * CKB, 1), None);
+        let mut skeleton = TransactionSkeleton::default();
+        skeleton.output(capacity_cell(500 * CKB, 2));
+        let payer = Payer {
+            balancer: lock(1).into(),
+            change_receiver: ChangeReceiver::Script(lock(1).into()),
+            contribution: Contribution::Rest,
+            change_policy: ChangePolicy::AddInputs,
+        };
+        block_on(skeleton.balance_under_fee_rate(
+            rpc,
+            0,
+            &FeeEstimator::default(),
+            &FirstFit {},
+            &[payer],
+        ))
+        .unwrap();
+        let tx = skeleton.clone().into_transaction_view();
+        let hash = tx.hash().unpack();
+        rpc.insert_fake_pool_tx(tx, min_replace_fee);
+        (skeleton, hash)
+    }
+
+    #[test]
+    fn bump_pays_min_replace_fee() {
+        let mut rpc = FakeRpcClient::default();
+        let (skeleton, hash) = pending_transfer(&mut rpc, 100_000);
+        assert!(fee(&skeleton) < 100_000);
+        let bumped = block_on(fee_bump(1).bump(&rpc, &skeleton, &hash, 0)).unwrap();
+        let size = FeeEstimator::default().estimate_size(&bumped);
+        // the floor of tx pool dominates the tiny step, and is rounded up to the fee rate at most
+        assert!(fee(&bumped) >= 100_000);
+        assert!(fee(&bumped) < 100_000 + size);
+        // the raised fee is deducted from the change cell, others are untouched
+        assert_eq!(inputs(&bumped), inputs(&skeleton));
+        assert_eq!(bumped.outputs[0], skeleton.outputs[0]);
+        assert_eq!(
+            bumped.outputs[1].capacity().as_u64(),
+            skeleton.outputs[1].capacity().as_u64() - (fee(&bumped) - fee(&skeleton))
+        );
+    }
+
+    #[test]
+    fn bump_steps_fee_rate() {
+        let mut rpc = FakeRpcClient::default();
+        let (skeleton, hash) = pending_transfer(&mut rpc, 1);
+        let estimator = FeeEstimator::default();
+        let mut fees = vec![];
+        for bumps in 0..3 {
+            let bumped = block_on(fee_bump(2000).bump(&rpc, &skeleton, &hash, bumps)).unwrap();
+            let fee_rate = 1000 + 2000 * (bumps as u64 + 1);
+            let expected = block_on(estimator.estimate_fee(&rpc, &bumped, fee_rate)).unwrap();
+            assert_eq!(fee(&bumped), expected.as_u64());
+            assert!(fee(&bumped) >= 1);
+            assert_eq!(bumped.outputs[0], skeleton.outputs[0]);
+            fees.push(fee(&bumped));
+        }
+        assert!(fees.windows(2).all(|v| v[0] < v[1]));
+    }
+
+    #[test]
+    fn bump_adds_inputs_without_affordable_change() {
+        let mut rpc = FakeRpcClient::default();
+        rpc.fake_provider.fake_feerate = 1000;
+        rpc.insert_fake_cell(out_point(1), capacity_cell(1000 * CKB, 1), None)
+            .insert_fake_cell(out_point(2), capacity_cell(1000 * CKB, 1), None);
+        // the change cell has no spare capacity above its occupied one
+        let occupied = capacity_cell(0, 1).occupied_capacity().as_u64();
+        let input = CellInput::new_builder()
+            .previous_output(out_point(1))
+            .build();
+        let mut skeleton = TransactionSkeleton::default();
+        skeleton
+            .input(CellInputEx::new(
+                input,
+                capacity_cell(1000 * CKB, 1).output,
+                None,
+            ))
+            .unwrap()
+            .output(capacity_cell(1000 * CKB - occupied - 1000, 2))
+            .output(capacity_cell(occupied, 1));
+        let tx = skeleton.clone().into_transaction_view();
+        let hash = tx.hash().unpack();
+        rpc.insert_fake_pool_tx(tx, 100_000);
+
+        let bumped = block_on(fee_bump(1000).bump(&rpc, &skeleton, &hash, 0)).unwrap();
+        assert!(fee(&bumped) >= 100_000);
+        assert_eq!(inputs(&bumped), vec![out_point(1), out_point(2)]);
+        assert_eq!(bumped.outputs[0], skeleton.outputs[0]);
+        assert_eq!(bumped.outputs[1], skeleton.outputs[1]);
+    }
+
+    #[test]
+    fn bump_by_hash_rebuilds_from_tx_pool() {
+        let mut rpc = FakeRpcClient::default();
+        let (skeleton, hash) = pending_transfer(&mut rpc, 100_000);
+        let fee_bump = fee_bump(1000);
+        let expected = block_on(fee_bump.bump(&rpc, &skeleton, &hash, 1)).unwrap();
+        let bumped = block_on(fee_bump.bump_by_hash(&rpc, &hash, 1)).unwrap();
+        assert_eq!(inputs(&bumped), inputs(&expected));
+        assert_eq!(bumped.outputs, expected.outputs);
+        assert!(fee(&bumped) >= 100_000);
+    }
+
+    #[test]
+    fn bump_fails_if_not_replaceable() {
+        let mut rpc = FakeRpcClient::default();
+        let (skeleton, _) = pending_transfer(&mut rpc, 1);
+        // committed transactions report no min_replace_fee
+        let hash = H256::from([9u8; 32]);
+        rpc.insert_fake_tx_status(hash.clone(), H256::default(), 1);
+        let error = block_on(fee_bump(1000).bump(&rpc, &skeleton, &hash, 0)).unwrap_err();
+        assert!(error.to_string().contains("is not replaceable"));
+    }
+}
@@ -0,0 +1,976 @@
+use std::{
+    fs,
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use ckb_jsonrpc_types::{
+    BlockNumber, BlockView, CellWithStatus, HeaderView, JsonBytes, OutPoint, OutputsValidator,
+    Status, Transaction, TransactionWithStatusResponse, TxPoolInfo,
+};
+use ckb_sdk::rpc::ckb_indexer::{Cell, Pagination, SearchKey};
+use ckb_types::H256;
+use eyre::Result;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    locker::CellLocker,
+    rpc::{Network, Rpc, RpcError, RPC},
+};
+
+/// Default number of entries that kept in memory
+pub const DEFAULT_CACHE_CAPACITY: usize = 4096;
+
+/// Default number of blocks on top of which a block is treated as final, data queried by block number or in
+/// committed transaction is cached only after that
+pub const DEFAULT_CONFIRMATIONS: u64 = 24;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum CacheKey {
+    Header(H256),
+    Block(H256),
+    Transaction(H256),
+    HeaderByNumber(u64),
+    BlockByNumber(u64),
+    BlockHash(u64),
+    LiveCell(OutPoint, bool),
+}
+
+impl CacheKey {
+    /// File name in disk store, None means never persisted
+    fn file_name(&self) -> Option<String> {
+        let name = match self {
+            CacheKey::Header(hash) => format!("header-{hash:x}"),
+            CacheKey::Block(hash) => format!("block-{hash:x}"),
+            CacheKey::Transaction(hash) => format!("transaction-{hash:x}"),
+            CacheKey::HeaderByNumber(number) => format!("header-by-number-{number}"),
+            CacheKey::BlockByNumber(number) => format!("block-by-number-{number}"),
+            CacheKey::BlockHash(number) => format!("block-hash-{number}"),
+            CacheKey::LiveCell(..) => return None,
+        };
+        Some(name + ".json")
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum CacheValue {
+    Header(HeaderView),
+    Block(BlockView),
+    Transaction(TransactionWithStatusResponse),
+    BlockHash(H256),
+    LiveCell(CellWithStatus),
+}
+
+// `TransactionWithStatusResponse` is not `Clone`
+impl Clone for CacheValue {
+    fn clone(&self) -> Self {
+        match self {
+            CacheValue::Header(header) => CacheValue::Header(header.clone()),
+            CacheValue::Block(block) => CacheValue::Block(block.clone()),
+            CacheValue::Transaction(tx) => CacheValue::Transaction(clone_transaction(tx)),
+            CacheValue::BlockHash(hash) => CacheValue::BlockHash(hash.clone()),
+            CacheValue::LiveCell(cell) => CacheValue::LiveCell(cell.clone()),
+        }
+    }
+}
+
+fn clone_transaction(tx: &TransactionWithStatusResponse) -> TransactionWithStatusResponse {
+    TransactionWithStatusResponse {
+        transaction: tx.transaction.clone(),
+        cycles: tx.cycles,
+        time_added_to_pool: tx.time_added_to_pool,
+        tx_status: tx.tx_status.clone(),
+        fee: tx.fee,
+        min_replace_fee: tx.min_replace_fee,
+    }
+}
+
+/// Cached entries with the optional expiration
+type Entries = LruCache<CacheKey, (CacheValue, Option<Instant>)>;
+
+/// Persistent store of immutable entries, one json file per entry
+///
+/// note: failures of disk are ignored, which only degrade into cache misses
+struct DiskStore {
+    path: PathBuf,
+}
+
+impl DiskStore {
+    fn read(&self, key: &CacheKey) -> Option<CacheValue> {
+        let bytes = fs::read(self.path.join(key.file_name()?)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    fn write(&self, key: &CacheKey, value: &CacheValue) {
+        let (Some(file_name), Ok(bytes)) = (key.file_name(), serde_json::to_vec(value)) else {
+            return;
+        };
+        // write into a temporary file first, so that other processes never read a partial one
+        let temp_path = self
+            .path
+            .join(format!("{file_name}.{}.tmp", rand::random::<u64>()));
+        if fs::write(&temp_path, bytes).is_ok()
+            && fs::rename(&temp_path, self.path.join(file_name)).is_err()
+        {
+            let _ = fs::remove_file(temp_path);
+        }
+    }
+}
+
+/// RPC that caches immutable chain data of the wrapped one, e.g. headers and blocks by hash, committed transactions
+/// and the final blocks queried by number, which saves the round trips of repeatedly resolving the same data
+///
+/// Live cells are not cached unless `live_cell_ttl` is set, and the ones spent by transactions sent through this RPC
+/// are evicted, other queries like `get_cells` and tip are always forwarded
+///
+/// e.g. share the genesis block among recipes on custom network:
+/// ```ignore
+/// let rpc = CachedRpc::new(RpcClient::builder().endpoint(url, None).build()?)
+///     .capacity(1024)
+///     .disk_store("./cache")?;
+/// ```
+///
+/// note: cloned ones share the same cache
+#[derive(Clone)]
+pub struct CachedRpc<T: RPC> {
+    inner: T,
+    cache: Arc<Mutex<Entries>>,
+    store: Option<Arc<DiskStore>>,
+    live_cell_ttl: Option<Duration>,
+    confirmations: u64,
+    /// The highest tip observed, which only grows so that a stale one is conservative in checking finality
+    known_tip: Arc<AtomicU64>,
+}
+
+impl<T: RPC> CachedRpc<T> {
+    pub fn new(inner: T) -> Self {
+        let capacity = NonZeroUsize::new(DEFAULT_CACHE_CAPACITY).expect("non-zero capacity");
+        CachedRpc {
+            inner,
+            cache: Arc::new(Mutex::new(LruCache::new(capacity))),
+            store: None,
+            live_cell_ttl: None,
+            confirmations: DEFAULT_CONFIRMATIONS,
+            known_tip: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Maximum entries in memory, the least recently used ones are evicted
+    pub fn capacity(self, capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        self.lru().resize(capacity);
+        self
+    }
+
+    /// Persist immutable entries into directory, which are shared across processes and runs
+    ///
+    /// note: entries are separated by network in sub-directories, live cells are never persisted
+    pub fn disk_store<P: Into<PathBuf>>(mut self, path: P) -> Result<Self> {
+        let network = self
+            .inner
+            .network()
+            .to_string()
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+        let path = path.into().join(network);
+        fs::create_dir_all(&path)?;
+        self.store = Some(Arc::new(DiskStore { path }));
+        Ok(self)
+    }
+
+    /// Cache live cells for `ttl`, if None, live cells are always queried from the wrapped RPC
+    ///
+    /// note: cells may be spent by others within `ttl`, only enable it if stale cells are tolerable
+    pub fn live_cell_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.live_cell_ttl = ttl;
+        self
+    }
+
+    /// Number of blocks to treat a block as final, deeper reorgs are not expected
+    pub fn confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// Drop all of entries in memory, the disk store is untouched
+    pub fn clear(&self) {
+        self.lru().clear();
+    }
+
+    fn lru(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.cache.lock().expect("poisoned rpc cache")
+    }
+
+    fn lookup(&self, key: &CacheKey) -> Option<CacheValue> {
+        let mut lru = self.lru();
+        match lru.get(key) {
+            Some((_, Some(expiration))) if *expiration <= Instant::now() => {
+                lru.pop(key);
+                None
+            }
+            Some((value, _)) => Some(value.clone()),
+            None => {
+                let value = self.store.as_ref()?.read(key)?;
+                lru.put(key.clone(), (value.clone(), None));
+                Some(value)
+            }
+        }
+    }
+
+    fn store(&self, key: CacheKey, value: CacheValue) {
+        let expiration = match key {
+            CacheKey::LiveCell(..) => Some(Instant::now() + self.live_cell_ttl.unwrap_or_default()),
+            _ => {
+                if let Some(store) = &self.store {
+                    store.write(&key, &value);
+                }
+                None
+            }
+        };
+        self.lru().put(key, (value, expiration));
+    }
+
+    fn observe_tip(&self, tip_number: u64) {
+        self.known_tip.fetch_max(tip_number, Ordering::Relaxed);
+    }
+
+    fn is_final_known(&self, block_number: Option<u64>) -> bool {
+        let tip_number = self.known_tip.load(Ordering::Relaxed);
+        block_number.is_none_or(|v| v == 0 || v + self.confirmations <= tip_number)
+    }
+
+    /// Whether the block is deep enough to be cached, the tip is only fetched if the known one is not enough
+    async fn is_final(&self, block_number: Option<u64>) -> Result<bool, RpcError> {
+        if !self.is_final_known(block_number) {
+            let tip_number = self.inner.get_tip_block_number().await?.into();
+            self.observe_tip(tip_number);
+        }
+        Ok(self.is_final_known(block_number))
+    }
+}
+
+/// What to cache of a query result, `Some(block_number)` means cached only if the block is final
+type Cacheable = Option<(CacheValue, Option<u64>)>;
+
+impl<T: RPC + 'static> CachedRpc<T> {
+    /// Serve from cache if hit, otherwise query the wrapped RPC and cache the result
+    fn cached<R, U, C, W>(&self, key: CacheKey, unwrap: U, call: C, wrap: W) -> Rpc<R>
+    where
+        R: Send + 'static,
+        U: Fn(CacheValue) -> Option<R>,
+        C: FnOnce(&T) -> Rpc<R>,
+        W: Fn(&R) -> Cacheable + Send + 'static,
+    {
+        if let Some(value) = self.lookup(&key).and_then(unwrap) {
+            return Box::pin(async move { Ok(value) });
+        }
+        let call = call(&self.inner);
+        let rpc = self.clone();
+        Box::pin(async move {
+            let result = call.await?;
+            if let Some((value, block_number)) = wrap(&result) {
+                if rpc.is_final(block_number).await? {
+                    rpc.store(key, value);
+                }
+            }
+            Ok(result)
+        })
+    }
+
+    /// Batched version of `cached`, only the missed ones are queried in one batch from the wrapped RPC
+    fn cached_batch<R, U, C, W>(
+        &self,
+        keys: Vec<CacheKey>,
+        unwrap: U,
+        call: C,
+        wrap: W,
+    ) -> Rpc<Vec<R>>
+    where
+        R: Send + 'static,
+        U: Fn(CacheValue) -> Option<R>,
+        C: FnOnce(&T, &[usize]) -> Rpc<Vec<R>>,
+        W: Fn(&R) -> Cacheable + Send + 'static,
+    {
+        let hits = keys
+            .iter()
+            .map(|key| self.lookup(key).and_then(&unwrap))
+            .collect::<Vec<_>>();
+        let missed = hits
+            .iter()
+            .enumerate()
+            .filter_map(|(i, hit)| hit.is_none().then_some(i))
+            .collect::<Vec<_>>();
+        let call = (!missed.is_empty()).then(|| call(&self.inner, &missed));
+        let rpc = self.clone();
+        Box::pin(async move {
+            let mut results = match call {
+                Some(call) => call.await?.into_iter(),
+                None => vec![].into_iter(),
+            };
+            let mut outputs = Vec::with_capacity(hits.len());
+            let mut cacheables = vec![];
+            for (key, hit) in keys.into_iter().zip(hits) {
+                if let Some(hit) = hit {
+                    outputs.push(hit);
+                    continue;
+                }
+                let result = results
+                    .next()
+                    .ok_or(RpcError::Other("missing result in batch".to_string()))?;
+                if let Some(cacheable) = wrap(&result) {
+                    cacheables.push((key, cacheable));
+                }
+                outputs.push(result);
+            }
+            // the tip is fetched at most once for the whole batch
+            let highest = cacheables.iter().filter_map(|(_, (_, v))| *v).max();
+            rpc.is_final(highest).await?;
+            for (key, (value, block_number)) in cacheables {
+                if rpc.is_final_known(block_number) {
+                    rpc.store(key, value);
+                }
+            }
+            Ok(outputs)
+        })
+    }
+}
+
+fn header_cacheable(header: &Option<HeaderView>, by_number: bool) -> Cacheable {
+    let header = header.as_ref()?;
+    let block_number = by_number.then(|| header.inner.number.into());
+    Some((CacheValue::Header(header.clone()), block_number))
+}
+
+fn transaction_cacheable(tx: &Option<TransactionWithStatusResponse>) -> Cacheable {
+    // the body of transaction is immutable, but its status is final only if committed in a final block
+    let tx = tx.as_ref()?;
+    if tx.tx_status.status != Status::Committed {
+        return None;
+    }
+    let block_number = tx.tx_status.block_number?.into();
+    Some((
+        CacheValue::Transaction(clone_transaction(tx)),
+        Some(block_number),
+    ))
+}
+
+impl<T: RPC + 'static> RPC for CachedRpc<T> {
+    fn network(&self) -> Network {
+        self.inner.network()
+    }
+
+    fn url(&self) -> (String, String) {
+        self.inner.url()
+    }
+
+    fn cell_locker(&self) -> Option<&CellLocker> {
+        self.inner.cell_locker()
+    }
+
+    fn get_live_cell(&self, out_point: &OutPoint, with_data: bool) -> Rpc<CellWithStatus> {
+        if self.live_cell_ttl.is_none() {
+            return self.inner.get_live_cell(out_point, with_data);
+        }
+        self.cached(
+            CacheKey::LiveCell(out_point.clone(), with_data),
+            |v| match v {
+                CacheValue::LiveCell(cell) => Some(cell),
+                _ => None,
+            },
+            |inner| inner.get_live_cell(out_point, with_data),
+            |cell| {
+                let cell = cell.cell.is_some().then(|| cell.clone())?;
+                Some((CacheValue::LiveCell(cell), None))
+            },
+        )
+    }
+
+    fn get_cells(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Rpc<Pagination<Cell>> {
+        self.inner.get_cells(search_key, limit, cursor)
+    }
+
+    fn get_block_by_number(&self, number: BlockNumber) -> Rpc<Option<BlockView>> {
+        self.cached(
+            CacheKey::BlockByNumber(number.into()),
+            |v| match v {
+                CacheValue::Block(block) => Some(Some(block)),
+                _ => None,
+            },
+            |inner| inner.get_block_by_number(number),
+            move |block| {
+                let block = block.as_ref()?;
+                Some((CacheValue::Block(block.clone()), Some(number.into())))
+            },
+        )
+    }
+
+    fn get_block(&self, hash: &H256) -> Rpc<Option<BlockView>> {
+        self.cached(
+            CacheKey::Block(hash.clone()),
+            |v| match v {
+                CacheValue::Block(block) => Some(Some(block)),
+                _ => None,
+            },
+            |inner| inner.get_block(hash),
+            |block| Some((CacheValue::Block(block.as_ref()?.clone()), None)),
+        )
+    }
+
+    fn get_header(&self, hash: &H256) -> Rpc<Option<HeaderView>> {
+        self.cached(
+            CacheKey::Header(hash.clone()),
+            |v| match v {
+                CacheValue::Header(header) => Some(Some(header)),
+                _ => None,
+            },
+            |inner| inner.get_header(hash),
+            |header| header_cacheable(header, false),
+        )
+    }
+
+    fn get_header_by_number(&self, number: BlockNumber) -> Rpc<Option<HeaderView>> {
+        self.cached(
+            CacheKey::HeaderByNumber(number.into()),
+            |v| match v {
+                CacheValue::Header(header) => Some(Some(header)),
+                _ => None,
+            },
+            |inner| inner.get_header_by_number(number),
+            |header| header_cacheable(header, true),
+        )
+    }
+
+    fn get_block_hash(&self, number: BlockNumber) -> Rpc<Option<H256>> {
+        self.cached(
+            CacheKey::BlockHash(number.into()),
+            |v| match v {
+                CacheValue::BlockHash(hash) => Some(Some(hash)),
+                _ => None,
+            },
+            |inner| inner.get_block_hash(number),
+            move |hash| {
+                let hash = hash.as_ref()?;
+                Some((CacheValue::BlockHash(hash.clone()), Some(number.into())))
+            },
+        )
+    }
+
+    fn get_tip_block_number(&self) -> Rpc<BlockNumber> {
+        let rpc = self.clone();
+        Box::pin(async move {
+            let tip_number = rpc.inner.get_tip_block_number().await?;
+            rpc.observe_tip(tip_number.into());
+            Ok(tip_number)
+        })
+    }
+
+    fn get_tip_header(&self) -> Rpc<HeaderView> {
+        let rpc = self.clone();
+        Box::pin(async move {
+            let tip_header = rpc.inner.get_tip_header().await?;
+            rpc.observe_tip(tip_header.inner.number.into());
+            Ok(tip_header)
+        })
+    }
+
+    fn tx_pool_info(&self) -> Rpc<TxPoolInfo> {
+        self.inner.tx_pool_info()
+    }
+
+    fn get_transaction(&self, hash: &H256) -> Rpc<Option<TransactionWithStatusResponse>> {
+        self.cached(
+            CacheKey::Transaction(hash.clone()),
+            |v| match v {
+                CacheValue::Transaction(tx) => Some(Some(tx)),
+                _ => None,
+            },
+            |inner| inner.get_transaction(hash),
+            transaction_cacheable,
+        )
+    }
+
+    fn send_transaction(
+        &self,
+        tx: Transaction,
+        outputs_validator: Option<OutputsValidator>,
+    ) -> Rpc<H256> {
+        // the spent cells are not live anymore
+        {
+            let mut lru = self.lru();
+            for input in &tx.inputs {
+                lru.pop(&CacheKey::LiveCell(input.previous_output.clone(), true));
+                lru.pop(&CacheKey::LiveCell(input.previous_output.clone(), false));
+            }
+        }
+        self.inner.send_transaction(tx, outputs_validator)
+    }
+
+    fn get_live_cells(
+        &self,
+        out_points: Vec<OutPoint>,
+        with_data: bool,
+    ) -> Rpc<Vec<CellWithStatus>> {
+        if self.live_cell_ttl.is_none() {
+            return self.inner.get_live_cells(out_points, with_data);
+        }
+        let keys = out_points
+            .iter()
+            .map(|out_point| CacheKey::LiveCell(out_point.clone(), with_data))
+            .collect();
+        self.cached_batch(
+            keys,
+            |v| match v {
+                CacheValue::LiveCell(cell) => Some(cell),
+                _ => None,
+            },
+            |inner, missed| {
+                let out_points = missed.iter().map(|i| out_points[*i].clone()).collect();
+                inner.get_live_cells(out_points, with_data)
+            },
+            |cell| {
+                let cell = cell.cell.is_some().then(|| cell.clone())?;
+                Some((CacheValue::LiveCell(cell), None))
+            },
+        )
+    }
+
+    fn get_headers(&self, hashes: Vec<H256>) -> Rpc<Vec<Option<HeaderView>>> {
+        let keys = hashes.iter().cloned().map(CacheKey::Header).collect();
+        self.cached_batch(
+            keys,
+            |v| match v {
+                CacheValue::Header(header) => Some(Some(header)),
+                _ => None,
+            },
+            |inner, missed| inner.get_headers(missed.iter().map(|i| hashes[*i].clone()).collect()),
+            |header| header_cacheable(header, false),
+        )
+    }
+
+    fn get_headers_by_number(&self, numbers: Vec<BlockNumber>) -> Rpc<Vec<Option<HeaderView>>> {
+        let keys = numbers
+            .iter()
+            .map(|number| CacheKey::HeaderByNumber((*number).into()))
+            .collect();
+        self.cached_batch(
+            keys,
+            |v| match v {
+                CacheValue::Header(header) => Some(Some(header)),
+                _ => None,
+            },
+            |inner, missed| {
+                inner.get_headers_by_number(missed.iter().map(|i| numbers[*i]).collect())
+            },
+            |header| header_cacheable(header, true),
+        )
+    }
+
+    fn get_transactions(
+        &self,
+        hashes: Vec<H256>,
+    ) -> Rpc<Vec<Option<TransactionWithStatusResponse>>> {
+        let keys = hashes.iter().cloned().map(CacheKey::Transaction).collect();
+        self.cached_batch(
+            keys,
+            |v| match v {
+                CacheValue::Transaction(tx) => Some(Some(tx)),
+                _ => None,
+            },
+            |inner, missed| {
+                inner.get_transactions(missed.iter().map(|i| hashes[*i].clone()).collect())
+            },
+            transaction_cacheable,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ckb_jsonrpc_types::{CellInput, TxStatus};
+    use ckb_types::{
+        core::{self, EpochNumberWithFraction, HeaderBuilder},
+        packed,
+        prelude::*,
+    };
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{simulation::FakeRpcClient, skeleton::CellOutputEx};
+
+    /// Fake RPC that counts the forwarded queries by method, where the batched ones count by items
+    #[derive(Clone, Default)]
+    struct CountingRpc {
+        inner: FakeRpcClient,
+        tip: Arc<AtomicU64>,
+        calls: Arc<Mutex<HashMap<&'static str, usize>>>,
+    }
+
+    impl CountingRpc {
+        fn count(&self, method: &'static str, items: usize) {
+            *self.calls.lock().unwrap().entry(method).or_default() += items;
+        }
+
+        fn calls(&self, method: &'static str) -> usize {
+            self.calls
+                .lock()
+                .unwrap()
+                .get(method)
+                .copied()
+                .unwrap_or_default()
+        }
+
+        fn set_tip(&self, tip_number: u64) {
+            self.tip.store(tip_number, Ordering::SeqCst);
+        }
+    }
+
+    impl RPC for CountingRpc {
+        fn url(&self) -> (String, String) {
+            self.inner.url()
+        }
+
+        fn get_live_cell(&self, out_point: &OutPoint, with_data: bool) -> Rpc<CellWithStatus> {
+            self.count("get_live_cell", 1);
+            self.inner.get_live_cell(out_point, with_data)
+        }
+
+        fn get_cells(
+            &self,
+            search_key: SearchKey,
+            limit: u32,
+            cursor: Option<JsonBytes>,
+        ) -> Rpc<Pagination<Cell>> {
+            self.inner.get_cells(search_key, limit, cursor)
+        }
+
+        fn get_block_by_number(&self, number: BlockNumber) -> Rpc<Option<BlockView>> {
+            self.inner.get_block_by_number(number)
+        }
+
+        fn get_block(&self, hash: &H256) -> Rpc<Option<BlockView>> {
+            self.inner.get_block(hash)
+        }
+
+        fn get_header(&self, hash: &H256) -> Rpc<Option<HeaderView>> {
+            self.count("get_header", 1);
+            self.inner.get_header(hash)
+        }
+
+        fn get_header_by_number(&self, number: BlockNumber) -> Rpc<Option<HeaderView>> {
+            self.count("get_header_by_number", 1);
+            self.inner.get_header_by_number(number)
+        }
+
+        fn get_block_hash(&self, number: BlockNumber) -> Rpc<Option<H256>> {
+            self.count("get_block_hash", 1);
+            self.inner.get_block_hash(number)
+        }
+
+        fn get_tip_block_number(&self) -> Rpc<BlockNumber> {
+            self.count("get_tip_block_number", 1);
+            let tip_number = self.tip.load(Ordering::SeqCst);
+            Box::pin(async move { Ok(tip_number.into()) })
+        }
+
+        fn get_tip_header(&self) -> Rpc<HeaderView> {
+            self.inner.get_tip_header()
+        }
+
+        fn tx_pool_info(&self) -> Rpc<TxPoolInfo> {
+            self.inner.tx_pool_info()
+        }
+
+        fn get_transaction(&self, hash: &H256) -> Rpc<Option<TransactionWithStatusResponse>> {
+            self.count("get_transaction", 1);
+            self.inner.get_transaction(hash)
+        }
+
+        fn send_transaction(
+            &self,
+            _tx: Transaction,
+            _outputs_validator: Option<OutputsValidator>,
+        ) -> Rpc<H256> {
+            self.count("send_transaction", 1);
+            Box::pin(async move { Ok(H256::default()) })
+        }
+
+        fn get_live_cells(
+            &self,
+            out_points: Vec<OutPoint>,
+            with_data: bool,
+        ) -> Rpc<Vec<CellWithStatus>> {
+            self.count("get_live_cells", out_points.len());
+            self.inner.get_live_cells(out_points, with_data)
+        }
+
+        fn get_headers(&self, hashes: Vec<H256>) -> Rpc<Vec<Option<HeaderView>>> {
+            self.count("get_headers", hashes.len());
+            self.inner.get_headers(hashes)
+        }
+
+        fn get_headers_by_number(&self, numbers: Vec<BlockNumber>) -> Rpc<Vec<Option<HeaderView>>> {
+            self.count("get_headers_by_number", numbers.len());
+            self.inner.get_headers_by_number(numbers)
+        }
+    }
+
+    /// Fake chain at tip 100 with headers of `numbers`, and a live cell of tx hash [1; 32]
+    fn counting_rpc(numbers: &[u64]) -> CountingRpc {
+        let mut rpc = CountingRpc::default();
+        rpc.set_tip(100);
+        for number in numbers {
+            rpc.inner.insert_fake_header(header(*number));
+        }
+        rpc.inner.insert_fake_cell(
+            live_out_point().into(),
+            CellOutputEx::new(packed::CellOutput::default(), vec![]),
+            None,
+        );
+        rpc
+    }
+
+    fn header(number: u64) -> core::HeaderView {
+        HeaderBuilder::default()
+            .number(number.pack())
+            .epoch(EpochNumberWithFraction::new(0, number, 1000).pack())
+            .build()
+    }
+
+    fn header_hash(number: u64) -> H256 {
+        header(number).hash().unpack()
+    }
+
+    fn live_out_point() -> OutPoint {
+        OutPoint {
+            tx_hash: H256([1; 32]),
+            index: 0.into(),
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("cinnabar-cache-{}", rand::random::<u64>()))
+    }
+
+    #[test]
+    fn cache_only_final_blocks_by_number() {
+        let counting = counting_rpc(&[0, 50, 90]);
+        let rpc = CachedRpc::new(counting.clone());
+        for _ in 0..2 {
+            block_on(rpc.get_header_by_number(0.into()))
+                .unwrap()
+                .unwrap();
+        }
+        // genesis is final without knowing the tip
+        assert_eq!(counting.calls("get_header_by_number"), 1);
+        assert_eq!(counting.calls("get_tip_block_number"), 0);
+        for _ in 0..2 {
+            block_on(rpc.get_header_by_number(50.into()))
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(counting.calls("get_header_by_number"), 2);
+        assert_eq!(counting.calls("get_tip_block_number"), 1);
+        // not deep enough, the tip is fetched again in case it grows
+        for _ in 0..2 {
+            block_on(rpc.get_header_by_number(90.into()))
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(counting.calls("get_header_by_number"), 4);
+        assert_eq!(counting.calls("get_tip_block_number"), 3);
+        // headers by hash are immutable
+        let hash = header_hash(90);
+        for _ in 0..2 {
+            block_on(rpc.get_header(&hash)).unwrap().unwrap();
+        }
+        assert_eq!(counting.calls("get_header"), 1);
+        assert_eq!(counting.calls("get_tip_block_number"), 3);
+    }
+
+    #[test]
+    fn cache_transaction_committed_in_final_block() {
+        let mut counting = counting_rpc(&[]);
+        let committed = H256([2; 32]);
+        let pending = H256([3; 32]);
+        counting
+            .inner
+            .insert_fake_tx_status(committed.clone(), H256::default(), 90);
+        counting.inner.fake_provider.fake_transaction_status.insert(
+            pending.clone(),
+            TxStatus {
+                status: Status::Pending,
+                block_hash: None,
+                block_number: None,
+                reason: None,
+            },
+        );
+        let rpc = CachedRpc::new(counting.clone()).confirmations(20);
+        for _ in 0..2 {
+            block_on(rpc.get_transaction(&committed)).unwrap().unwrap();
+            block_on(rpc.get_transaction(&pending)).unwrap().unwrap();
+        }
+        assert_eq!(counting.calls("get_transaction"), 4);
+        // the observed tip grows, so the known one is enough to check finality
+        counting.set_tip(110);
+        block_on(rpc.get_tip_block_number()).unwrap();
+        for _ in 0..2 {
+            block_on(rpc.get_transaction(&committed)).unwrap().unwrap();
+        }
+        assert_eq!(counting.calls("get_transaction"), 5);
+        assert_eq!(counting.calls("get_tip_block_number"), 3);
+    }
+
+    #[test]
+    fn cache_live_cells_within_ttl() {
+        let counting = counting_rpc(&[]);
+        let rpc = CachedRpc::new(counting.clone());
+        for _ in 0..2 {
+            block_on(rpc.get_live_cell(&live_out_point(), true)).unwrap();
+        }
+        // never cached by default
+        assert_eq!(counting.calls("get_live_cell"), 2);
+
+        let rpc = rpc.live_cell_ttl(Some(Duration::from_millis(50)));
+        for _ in 0..2 {
+            block_on(rpc.get_live_cell(&live_out_point(), true)).unwrap();
+        }
+        assert_eq!(counting.calls("get_live_cell"), 3);
+        // cached separately with or without data
+        block_on(rpc.get_live_cell(&live_out_point(), false)).unwrap();
+        assert_eq!(counting.calls("get_live_cell"), 4);
+        std::thread::sleep(Duration::from_millis(60));
+        block_on(rpc.get_live_cell(&live_out_point(), true)).unwrap();
+        assert_eq!(counting.calls("get_live_cell"), 5);
+    }
+
+    #[test]
+    fn send_transaction_evicts_spent_live_cells() {
+        let counting = counting_rpc(&[]);
+        let rpc = CachedRpc::new(counting.clone()).live_cell_ttl(Some(Duration::from_secs(60)));
+        block_on(rpc.get_live_cell(&live_out_point(), true)).unwrap();
+        block_on(rpc.get_live_cell(&live_out_point(), false)).unwrap();
+        let tx = Transaction {
+            inputs: vec![CellInput {
+                previous_output: live_out_point(),
+                since: 0.into(),
+            }],
+            ..Default::default()
+        };
+        block_on(rpc.send_transaction(tx, None)).unwrap();
+        assert_eq!(counting.calls("send_transaction"), 1);
+        block_on(rpc.get_live_cell(&live_out_point(), true)).unwrap();
+        block_on(rpc.get_live_cell(&live_out_point(), false)).unwrap();
+        assert_eq!(counting.calls("get_live_cell"), 4);
+    }
+
+    #[test]
+    fn cached_batch_merges_hits_and_misses() {
+        let counting = counting_rpc(&[10, 20, 30, 90]);
+        let rpc = CachedRpc::new(counting.clone());
+        let hashes = [10, 20, 30].map(header_hash).to_vec();
+        block_on(rpc.get_header(&hashes[1])).unwrap();
+        let headers = block_on(rpc.get_headers(hashes.clone())).unwrap();
+        let batched = headers
+            .into_iter()
+            .map(|v| v.unwrap().hash)
+            .collect::<Vec<_>>();
+        assert_eq!(batched, hashes);
+        // only the missed ones are queried
+        assert_eq!(counting.calls("get_headers"), 2);
+        block_on(rpc.get_headers(hashes)).unwrap();
+        assert_eq!(counting.calls("get_headers"), 2);
+
+        // the tip is fetched once for the whole batch, and only final ones are cached
+        let numbers = vec![10.into(), 90.into(), 20.into()];
+        let headers = block_on(rpc.get_headers_by_number(numbers.clone())).unwrap();
+        let batched = headers
+            .into_iter()
+            .map(|v| u64::from(v.unwrap().inner.number))
+            .collect::<Vec<_>>();
+        assert_eq!(batched, vec![10, 90, 20]);
+        assert_eq!(counting.calls("get_tip_block_number"), 1);
+        block_on(rpc.get_headers_by_number(numbers)).unwrap();
+        assert_eq!(counting.calls("get_headers_by_number"), 4);
+    }
+
+    #[test]
+    fn disk_store_persists_immutable_entries() {
+        let dir = temp_dir();
+        let counting = counting_rpc(&[50]);
+        let hash = header_hash(50);
+        let rpc = CachedRpc::new(counting.clone())
+            .live_cell_ttl(Some(Duration::from_secs(60)))
+            .disk_store(&dir)
+            .unwrap();
+        block_on(rpc.get_header(&hash)).unwrap();
+        block_on(rpc.get_header_by_number(50.into())).unwrap();
+        block_on(rpc.get_block_hash(50.into())).unwrap();
+        block_on(rpc.get_live_cell(&live_out_point(), true)).unwrap();
+        // separated by network, and live cells are never persisted
+        let mut files = fs::read_dir(dir.join("fake"))
+            .unwrap()
+            .map(|v| v.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                "block-hash-50.json".to_string(),
+                format!("header-{}.json", hex::encode(hash.as_bytes())),
+                "header-by-number-50.json".to_string(),
+            ]
+        );
+
+        // served from disk in another instance
+        let other = counting_rpc(&[50]);
+        let rpc = CachedRpc::new(other.clone()).disk_store(&dir).unwrap();
+        let header = block_on(rpc.get_header(&hash)).unwrap().unwrap();
+        assert_eq!(header.hash, hash);
+        block_on(rpc.get_block_hash(50.into())).unwrap().unwrap();
+        assert_eq!(other.calls("get_header"), 0);
+        assert_eq!(other.calls("get_block_hash"), 0);
+        // broken files degrade into misses
+        fs::write(dir.join("fake").join("header-by-number-50.json"), b"broken").unwrap();
+        block_on(rpc.get_header_by_number(50.into()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(other.calls("get_header_by_number"), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let counting = counting_rpc(&[1, 2, 3]);
+        let rpc = CachedRpc::new(counting.clone()).capacity(2);
+        let [first, second, third] = [1, 2, 3].map(header_hash);
+        for hash in [&first, &second, &first, &third] {
+            block_on(rpc.get_header(hash)).unwrap();
+        }
+        assert_eq!(counting.calls("get_header"), 3);
+        // the second one is evicted by the third
+        block_on(rpc.get_header(&first)).unwrap();
+        assert_eq!(counting.calls("get_header"), 3);
+        block_on(rpc.get_header(&second)).unwrap();
+        assert_eq!(counting.calls("get_header"), 4);
+        rpc.clear();
+        block_on(rpc.get_header(&third)).unwrap();
+        assert_eq!(counting.calls("get_header"), 5);
+    }
+}
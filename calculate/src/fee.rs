@@ -0,0 +1,316 @@
+use std::{collections::HashMap, str::FromStr};
+
+use ckb_sdk::{
+    constants::{MULTISIG_TYPE_HASH, SIGHASH_TYPE_HASH},
+    unlock::MultisigConfig,
+    Address,
+};
+use ckb_types::{
+    core::{tx_pool::get_transaction_weight, Capacity, Cycle, FeeRate, ScriptHashType},
+    packed::Script,
+    prelude::Unpack,
+    H160, H256,
+};
+use eyre::{eyre, Result};
+
+use crate::{
+    operation::basic::ReprMultisigConfig, rpc::RPC, simulation::TransactionSimulator,
+    skeleton::TransactionSkeleton,
+};
+
+/// Size of a recoverable secp256k1 signature
+pub const SECP256K1_SIGNATURE_SIZE: usize = 65;
+
+/// Approximate upper bound of cycles to verify one secp256k1 signature in sighash or multisig lock
+pub const SECP256K1_SIGNATURE_CYCLES: Cycle = 2_000_000;
+
+/// Estimate the size of `lock` field in witness before the lock script is signed
+pub trait WitnessEstimator: Send + Sync {
+    /// Return the placeholder size of `lock` field for the lock script, None if not recognized
+    fn lock_size(&self, lock_script: &Script) -> Option<usize>;
+
+    /// Return the cycles of lock script, which is used when the unsigned lock group fails in local verification
+    fn lock_cycles(&self, _lock_script: &Script) -> Option<Cycle> {
+        None
+    }
+}
+
+fn is_type_script(script: &Script, code_hash: &H256) -> bool {
+    let script_code_hash: H256 = script.code_hash().unpack();
+    &script_code_hash == code_hash && script.hash_type() == ScriptHashType::Type.into()
+}
+
+/// Placeholder of secp256k1_sighash_all lock, which is also signed by `ckb-cli` in sighash mode
+pub struct Secp256k1SighashEstimator;
+
+impl WitnessEstimator for Secp256k1SighashEstimator {
+    fn lock_size(&self, lock_script: &Script) -> Option<usize> {
+        is_type_script(lock_script, &SIGHASH_TYPE_HASH).then_some(SECP256K1_SIGNATURE_SIZE)
+    }
+
+    fn lock_cycles(&self, lock_script: &Script) -> Option<Cycle> {
+        is_type_script(lock_script, &SIGHASH_TYPE_HASH).then_some(SECP256K1_SIGNATURE_CYCLES)
+    }
+}
+
+/// Placeholder of secp256k1_multisig_all lock, which requires the multisig configs to recover the lock args
+#[derive(Default)]
+pub struct Secp256k1MultisigEstimator {
+    configs: HashMap<H160, MultisigConfig>,
+}
+
+impl Secp256k1MultisigEstimator {
+    pub fn new(configs: Vec<MultisigConfig>) -> Self {
+        let configs = configs
+            .into_iter()
+            .map(|config| (config.hash160(), config))
+            .collect();
+        Secp256k1MultisigEstimator { configs }
+    }
+
+    /// Recover multisig configs from the `multisig_configs` field in tx file of `ckb-cli`
+    pub fn from_ckb_cli(configs: &HashMap<H160, ReprMultisigConfig>) -> Result<Self> {
+        let configs = configs
+            .values()
+            .map(|config| {
+                let sighash_addresses = config
+                    .sighash_addresses
+                    .iter()
+                    .map(|address| {
+                        let address = Address::from_str(address).map_err(|e| eyre!(e))?;
+                        H160::from_slice(&address.payload().args())
+                            .map_err(|_| eyre!("invalid sighash address: {address}"))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                MultisigConfig::new_with(
+                    sighash_addresses,
+                    config.require_first_n,
+                    config.threshold,
+                )
+                .map_err(|e| eyre!(e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::new(configs))
+    }
+
+    fn config(&self, lock_script: &Script) -> Option<&MultisigConfig> {
+        if !is_type_script(lock_script, &MULTISIG_TYPE_HASH) {
+            return None;
+        }
+        // args is blake160 of multisig config, optionally followed by since
+        let args = lock_script.args().raw_data();
+        let hash160 = H160::from_slice(args.get(..20)?).ok()?;
+        self.configs.get(&hash160)
+    }
+}
+
+impl WitnessEstimator for Secp256k1MultisigEstimator {
+    fn lock_size(&self, lock_script: &Script) -> Option<usize> {
+        self.config(lock_script).map(|config| {
+            config.to_witness_data().len() + SECP256K1_SIGNATURE_SIZE * config.threshold() as usize
+        })
+    }
+
+    fn lock_cycles(&self, lock_script: &Script) -> Option<Cycle> {
+        self.config(lock_script)
+            .map(|config| SECP256K1_SIGNATURE_CYCLES * config.threshold() as Cycle)
+    }
+}
+
+/// Estimate transaction size and fee by filling placeholder witnesses for unsigned lock groups
+///
+/// note: estimators are tried in order, the first one that recognizes the lock script wins
+pub struct FeeEstimator {
+    estimators: Vec<Box<dyn WitnessEstimator>>,
+    simulator: Option<(TransactionSimulator, Cycle)>,
+}
+
+impl Default for FeeEstimator {
+    fn default() -> Self {
+        FeeEstimator {
+            estimators: vec![Box::new(Secp256k1SighashEstimator)],
+            simulator: None,
+        }
+    }
+}
+
+impl FeeEstimator {
+    pub fn new() -> Self {
+        FeeEstimator::default()
+    }
+
+    /// Attach estimator for custom lock script, which takes precedence over the attached ones
+    pub fn estimator<E: WitnessEstimator + 'static>(mut self, estimator: E) -> Self {
+        self.estimators.insert(0, Box::new(estimator));
+        self
+    }
+
+    /// Charge fee on the larger of serialized size and cycles, which are collected by running scripts locally
+    ///
+    /// # Parameters
+    /// - `simulator`: the local runner of scripts, refer to `TransactionSimulator::estimate_cycles`
+    /// - `max_cycles`: the cycles limit of all script groups
+    pub fn cycle_aware(mut self, simulator: TransactionSimulator, max_cycles: Cycle) -> Self {
+        self.simulator = Some((simulator, max_cycles));
+        self
+    }
+
+    pub fn lock_size(&self, lock_script: &Script) -> Option<usize> {
+        self.estimators
+            .iter()
+            .find_map(|estimator| estimator.lock_size(lock_script))
+    }
+
+    pub fn lock_cycles(&self, lock_script: &Script) -> Option<Cycle> {
+        self.estimators
+            .iter()
+            .find_map(|estimator| estimator.lock_cycles(lock_script))
+    }
+
+    /// Clone transaction skeleton with placeholder witnesses, the already signed or customized witnesses are kept
+    pub fn placeholder(&self, skeleton: &TransactionSkeleton) -> TransactionSkeleton {
+        let mut skeleton = skeleton.clone();
+        (skeleton.witnesses.len()..skeleton.inputs.len()).for_each(|_| {
+            skeleton.witness(Default::default());
+        });
+        let mut lock_hashes = vec![];
+        for (index, input) in skeleton.inputs.iter().enumerate() {
+            let lock_hash = input.output.calc_lock_hash();
+            if lock_hashes.contains(&lock_hash) {
+                continue;
+            }
+            lock_hashes.push(lock_hash);
+            let witness = &mut skeleton.witnesses[index];
+            if !witness.traditional || !witness.lock.is_empty() {
+                continue;
+            }
+            if let Some(size) = self.lock_size(&input.output.lock_script()) {
+                witness.lock = vec![0u8; size];
+            }
+        }
+        skeleton
+    }
+
+    /// Estimate the serialized size in block of transaction after signing
+    pub fn estimate_size(&self, skeleton: &TransactionSkeleton) -> u64 {
+        self.placeholder(skeleton)
+            .into_transaction_view()
+            .data()
+            .serialized_size_in_block() as u64
+    }
+
+    /// Estimate transaction fee under the fee rate in shannons/KW, the weight follows the consensus formula
+    pub async fn estimate_fee<T: RPC>(
+        &self,
+        rpc: &T,
+        skeleton: &TransactionSkeleton,
+        fee_rate: u64,
+    ) -> Result<Capacity> {
+        let size = self.estimate_size(skeleton);
+        let cycles = match &self.simulator {
+            Some((simulator, max_cycles)) => {
+                simulator
+                    .estimate_cycles(rpc, &self.placeholder(skeleton), self, *max_cycles)
+                    .await?
+            }
+            None => 0,
+        };
+        let weight = get_transaction_weight(size as usize, cycles);
+        Ok(FeeRate::from_u64(fee_rate).fee(weight))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ckb_sdk::{
+        transaction::signer::{SignContexts, TransactionSigner},
+        types::transaction_with_groups::TransactionWithScriptGroupsBuilder,
+        util::blake160,
+        NetworkInfo,
+    };
+    use ckb_types::{
+        core::Capacity,
+        packed::{CellInput, CellOutput, OutPoint},
+        prelude::{Builder, Entity, Pack},
+    };
+    use futures::executor::block_on;
+    use secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+    use super::*;
+    use crate::{
+        operation::{basic::AddSecp256k1SighashSignatures, Log, Operation},
+        simulation::FakeRpcClient,
+        skeleton::{CellInputEx, CellOutputEx, ScriptEx},
+    };
+
+    fn secret_key(seed: u8) -> SecretKey {
+        SecretKey::from_slice(&[seed; 32]).unwrap()
+    }
+
+    fn sighash_args(key: &SecretKey) -> H160 {
+        blake160(&PublicKey::from_secret_key(&Secp256k1::new(), key).serialize())
+    }
+
+    fn skeleton(lock_script: Script) -> TransactionSkeleton {
+        let input = CellInput::new_builder()
+            .previous_output(OutPoint::new_builder().tx_hash([1u8; 32].pack()).build())
+            .build();
+        let output = CellOutput::new_builder()
+            .capacity(Capacity::shannons(1000).pack())
+            .lock(lock_script)
+            .build();
+        let mut skeleton = TransactionSkeleton::default();
+        skeleton
+            .input(CellInputEx::new(input, output.clone(), None))
+            .unwrap()
+            .output(CellOutputEx::new(output, vec![]));
+        skeleton
+    }
+
+    #[test]
+    fn sighash_placeholder_matches_signed_size() {
+        let key = secret_key(1);
+        let lock_script = ScriptEx::new_type(SIGHASH_TYPE_HASH, sighash_args(&key).0.to_vec());
+        let mut skeleton = skeleton(lock_script.clone().to_script_unchecked());
+        let estimated = FeeEstimator::default().estimate_size(&skeleton);
+
+        let operation = Box::new(AddSecp256k1SighashSignatures {
+            user_lock_scripts: vec![lock_script],
+            user_private_keys: vec![key],
+        });
+        let rpc = FakeRpcClient::default();
+        block_on(operation.run(&rpc, &mut skeleton, &mut Log::new())).unwrap();
+        let signed = skeleton.into_transaction_view();
+        assert_eq!(estimated, signed.data().serialized_size_in_block() as u64);
+    }
+
+    #[test]
+    fn multisig_placeholder_matches_signed_size() {
+        let keys = [secret_key(1), secret_key(2), secret_key(3)];
+        let config =
+            MultisigConfig::new_with(keys.iter().map(sighash_args).collect(), 0, 2).unwrap();
+        let lock_script = Script::from(&config);
+        let skeleton = skeleton(lock_script.clone());
+        // not recognized without the multisig config
+        assert_eq!(FeeEstimator::default().lock_size(&lock_script), None);
+        let estimator =
+            FeeEstimator::new().estimator(Secp256k1MultisigEstimator::new(vec![config.clone()]));
+        let estimated = estimator.estimate_size(&skeleton);
+
+        let mut tx_groups = TransactionWithScriptGroupsBuilder::default()
+            .set_tx_view(skeleton.into_transaction_view())
+            .add_lock_script_group(&lock_script, &[0])
+            .build();
+        let signer = TransactionSigner::new(&NetworkInfo::mainnet());
+        for key in &keys[..2] {
+            signer
+                .sign_transaction(
+                    &mut tx_groups,
+                    &SignContexts::new_multisig(*key, config.clone()),
+                )
+                .unwrap();
+        }
+        let signed = tx_groups.get_tx_view();
+        assert_eq!(estimated, signed.data().serialized_size_in_block() as u64);
+    }
+}
@@ -0,0 +1,349 @@
+use std::{
+    fmt::Display,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use ckb_types::{prelude::Unpack, H256};
+
+use crate::{
+    operation::Log,
+    skeleton::{CellDepEx, CellInputEx, CellOutputEx, HeaderDepEx, TransactionSkeleton},
+};
+
+/// Location of the running operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OperationContext {
+    /// Index of instruction in calculator, None if instruction runs alone
+    pub instruction: Option<usize>,
+    /// Index of operation in instruction
+    pub index: usize,
+    /// Short name of operation, e.g. `BalanceTransaction`
+    pub name: &'static str,
+}
+
+impl Display for OperationContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.instruction {
+            Some(instruction) => write!(f, "#{instruction}.{} {}", self.index, self.name),
+            None => write!(f, "#{} {}", self.index, self.name),
+        }
+    }
+}
+
+/// Hooks that run before and after every operation in instruction
+///
+/// note: middlewares of calculator wrap around the ones of instruction
+pub trait Middleware: Send {
+    fn before(&mut self, _context: &OperationContext, _skeleton: &TransactionSkeleton, _log: &Log) {
+    }
+
+    fn after(
+        &mut self,
+        _context: &OperationContext,
+        _skeleton: &TransactionSkeleton,
+        _log: &Log,
+        _error: Option<&eyre::Report>,
+    ) {
+    }
+}
+
+/// Share middleware with the caller, so that the collected data can be read after running
+impl<M: Middleware> Middleware for Arc<Mutex<M>> {
+    fn before(&mut self, context: &OperationContext, skeleton: &TransactionSkeleton, log: &Log) {
+        self.lock()
+            .expect("poisoned middleware")
+            .before(context, skeleton, log)
+    }
+
+    fn after(
+        &mut self,
+        context: &OperationContext,
+        skeleton: &TransactionSkeleton,
+        log: &Log,
+        error: Option<&eyre::Report>,
+    ) {
+        self.lock()
+            .expect("poisoned middleware")
+            .after(context, skeleton, log, error)
+    }
+}
+
+/// Record the elapsed time of each operation
+#[derive(Default)]
+pub struct Timing {
+    start: Option<Instant>,
+    records: Vec<(OperationContext, Duration)>,
+}
+
+impl Timing {
+    pub fn new() -> Self {
+        Timing::default()
+    }
+
+    pub fn records(&self) -> &[(OperationContext, Duration)] {
+        &self.records
+    }
+
+    pub fn total(&self) -> Duration {
+        self.records.iter().map(|(_, elapsed)| *elapsed).sum()
+    }
+}
+
+impl Middleware for Timing {
+    fn before(&mut self, _: &OperationContext, _: &TransactionSkeleton, _: &Log) {
+        self.start = Some(Instant::now());
+    }
+
+    fn after(
+        &mut self,
+        context: &OperationContext,
+        _: &TransactionSkeleton,
+        _: &Log,
+        _: Option<&eyre::Report>,
+    ) {
+        if let Some(start) = self.start.take() {
+            self.records.push((*context, start.elapsed()));
+        }
+    }
+}
+
+impl Display for Timing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (context, elapsed) in &self.records {
+            writeln!(f, "[calculate timing] {context}: {elapsed:?}")?;
+        }
+        write!(f, "[calculate timing] total: {:?}", self.total())
+    }
+}
+
+/// Items added into or removed from a field of transaction skeleton
+#[derive(Debug, Clone)]
+pub struct FieldDiff<V> {
+    pub added: Vec<V>,
+    pub removed: Vec<V>,
+}
+
+impl<V: PartialEq + Clone> FieldDiff<V> {
+    /// Compare two fields as multisets, so the moved items are not counted in
+    pub fn between(before: &[V], after: &[V]) -> Self {
+        let mut removed = before.to_vec();
+        let mut added = vec![];
+        for item in after {
+            match removed.iter().position(|v| v == item) {
+                Some(index) => {
+                    removed.remove(index);
+                }
+                None => added.push(item.clone()),
+            }
+        }
+        FieldDiff { added, removed }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Difference of transaction skeleton between two moments, witnesses are compared in packed bytes
+#[derive(Debug, Clone)]
+pub struct SkeletonDiff {
+    pub inputs: FieldDiff<CellInputEx>,
+    pub outputs: FieldDiff<CellOutputEx>,
+    pub celldeps: FieldDiff<CellDepEx>,
+    pub headerdeps: FieldDiff<HeaderDepEx>,
+    pub witnesses: FieldDiff<Vec<u8>>,
+}
+
+impl SkeletonDiff {
+    pub fn between(before: &TransactionSkeleton, after: &TransactionSkeleton) -> Self {
+        let packed_witnesses = |skeleton: &TransactionSkeleton| {
+            skeleton
+                .witnesses
+                .iter()
+                .map(|v| v.clone().into_packed_bytes().raw_data().to_vec())
+                .collect::<Vec<_>>()
+        };
+        SkeletonDiff {
+            inputs: FieldDiff::between(&before.inputs, &after.inputs),
+            outputs: FieldDiff::between(&before.outputs, &after.outputs),
+            celldeps: FieldDiff::between(&before.celldeps, &after.celldeps),
+            headerdeps: FieldDiff::between(&before.headerdeps, &after.headerdeps),
+            witnesses: FieldDiff::between(&packed_witnesses(before), &packed_witnesses(after)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+            && self.outputs.is_empty()
+            && self.celldeps.is_empty()
+            && self.headerdeps.is_empty()
+            && self.witnesses.is_empty()
+    }
+}
+
+fn write_field<V>(
+    f: &mut std::fmt::Formatter<'_>,
+    field: &str,
+    diff: &FieldDiff<V>,
+    describe: impl Fn(&V) -> String,
+) -> std::fmt::Result {
+    for item in &diff.added {
+        writeln!(f, "+ {field} {}", describe(item))?;
+    }
+    for item in &diff.removed {
+        writeln!(f, "- {field} {}", describe(item))?;
+    }
+    Ok(())
+}
+
+impl Display for SkeletonDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_field(f, "input", &self.inputs, |input| {
+            let out_point = input.input.previous_output();
+            let tx_hash: H256 = out_point.tx_hash().unpack();
+            let index: u32 = out_point.index().unpack();
+            format!("{tx_hash:#x}:{index} ({})", input.output.capacity())
+        })?;
+        write_field(f, "output", &self.outputs, |output| {
+            let type_hash = output
+                .calc_type_hash()
+                .map(|v| format!(", type {v:#x}"))
+                .unwrap_or_default();
+            format!(
+                "lock {:#x}{type_hash} ({})",
+                output.calc_lock_hash(),
+                output.capacity()
+            )
+        })?;
+        write_field(f, "celldep", &self.celldeps, |celldep| celldep.name.clone())?;
+        write_field(f, "headerdep", &self.headerdeps, |headerdep| {
+            format!("{:#x}", headerdep.block_hash)
+        })?;
+        write_field(f, "witness", &self.witnesses, |witness| {
+            format!("0x{}", hex::encode(witness))
+        })
+    }
+}
+
+/// Print the skeleton diff after each operation, the unchanged operations are skipped
+#[derive(Default)]
+pub struct PrintSkeletonDiff {
+    checkpoint: Option<TransactionSkeleton>,
+}
+
+impl PrintSkeletonDiff {
+    pub fn new() -> Self {
+        PrintSkeletonDiff::default()
+    }
+}
+
+impl Middleware for PrintSkeletonDiff {
+    fn before(&mut self, _: &OperationContext, skeleton: &TransactionSkeleton, _: &Log) {
+        self.checkpoint = Some(skeleton.clone());
+    }
+
+    fn after(
+        &mut self,
+        context: &OperationContext,
+        skeleton: &TransactionSkeleton,
+        _: &Log,
+        error: Option<&eyre::Report>,
+    ) {
+        let Some(checkpoint) = self.checkpoint.take() else {
+            return;
+        };
+        if let Some(error) = error {
+            println!("[calculate diff] {context} failed: {error}");
+        }
+        let diff = SkeletonDiff::between(&checkpoint, skeleton);
+        if !diff.is_empty() {
+            print!("[calculate diff] {context}\n{diff}");
+        }
+    }
+}
+
+/// Wrap operation into a tracing span that carries its location
+#[cfg(feature = "tracing")]
+pub(crate) fn instrument<F: Future>(
+    context: &OperationContext,
+    future: F,
+) -> impl Future<Output = F::Output> {
+    use tracing::Instrument;
+    future.instrument(tracing::info_span!(
+        "operation",
+        instruction = ?context.instruction,
+        index = context.index,
+        name = context.name
+    ))
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn instrument<F: Future>(_: &OperationContext, future: F) -> F {
+    future
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::WitnessEx;
+
+    #[test]
+    fn field_diff_counts_added_and_removed() {
+        let diff = FieldDiff::between(&[1, 2, 3], &[2, 4]);
+        assert_eq!(diff.added, vec![4]);
+        assert_eq!(diff.removed, vec![1, 3]);
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn field_diff_ignores_moved_items() {
+        let diff = FieldDiff::between(&[1, 2, 3], &[3, 1, 2]);
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn field_diff_compares_as_multiset() {
+        let diff = FieldDiff::between(&[1, 1], &[1]);
+        assert!(diff.added.is_empty());
+        assert_eq!(diff.removed, vec![1]);
+
+        let diff = FieldDiff::between(&[1], &[1, 1]);
+        assert_eq!(diff.added, vec![1]);
+        assert!(diff.removed.is_empty());
+    }
+
+    #[test]
+    fn skeleton_diff_between_steps() {
+        let before = TransactionSkeleton::default();
+        let mut after = before.clone();
+        after
+            .output(CellOutputEx::new(Default::default(), vec![1]))
+            .witness(WitnessEx::new(vec![1], vec![], vec![]));
+        let diff = SkeletonDiff::between(&before, &after);
+        assert_eq!(diff.outputs.added.len(), 1);
+        assert_eq!(diff.witnesses.added.len(), 1);
+        assert!(diff.inputs.is_empty() && diff.celldeps.is_empty() && diff.headerdeps.is_empty());
+        assert!(diff.to_string().contains("+ output"));
+        assert!(SkeletonDiff::between(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn timing_records_each_operation() {
+        let mut timing = Timing::new();
+        let skeleton = TransactionSkeleton::default();
+        let log = Log::new();
+        for index in 0..2 {
+            let context = OperationContext {
+                instruction: None,
+                index,
+                name: "Test",
+            };
+            timing.before(&context, &skeleton, &log);
+            timing.after(&context, &skeleton, &log, None);
+        }
+        assert_eq!(timing.records().len(), 2);
+        assert_eq!(timing.records()[1].0.index, 1);
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use eyre::Result;
 
 use crate::{
@@ -6,26 +8,101 @@ use crate::{
     skeleton::TransactionSkeleton,
 };
 
+pub mod middleware;
+pub mod plan;
 pub mod predefined;
+pub mod recipe;
+
+use middleware::{instrument, Middleware, OperationContext};
+use plan::{Plan, PlanStep};
 
 pub type DefaultInstruction = Instruction<RpcClient>;
 
+/// Decide how to restore transaction skeleton and log when an operation fails
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Rollback {
+    /// Leave the half-built skeleton as it is
+    #[default]
+    Disabled,
+    /// Restore to the checkpoint that made before the failing operation
+    Operation,
+    /// Restore to the checkpoint that made before the whole instruction
+    Instruction,
+}
+
+/// Error of running an instruction, which indicates the index of the failing operation
+#[derive(Debug)]
+pub struct OperationFailure {
+    pub index: usize,
+    pub error: eyre::Report,
+}
+
+impl Display for OperationFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "operation #{} failed: {}", self.index, self.error)
+    }
+}
+
+impl std::error::Error for OperationFailure {}
+
+/// Error of running a calculator, which indicates the index of the failing instruction and operation
+#[derive(Debug)]
+pub struct InstructionFailure {
+    pub index: usize,
+    pub operation_index: usize,
+    pub error: eyre::Report,
+    /// Log collected until failure, which is restored along with the skeleton in atomic mode
+    pub log: Log,
+}
+
+impl Display for InstructionFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "instruction #{} failed at operation #{}: {}",
+            self.index, self.operation_index, self.error
+        )
+    }
+}
+
+impl std::error::Error for InstructionFailure {}
+
 /// Instruction is a collection of operations that can be executed in sequence, to assemble transaction skeleton
 pub struct Instruction<T: RPC> {
     operations: Vec<Box<dyn Operation<T>>>,
+    rollback: Rollback,
+    middlewares: Vec<Box<dyn Middleware>>,
 }
 
 impl<T: RPC> Default for Instruction<T> {
     fn default() -> Self {
         Instruction {
             operations: Vec::new(),
+            rollback: Rollback::Disabled,
+            middlewares: Vec::new(),
         }
     }
 }
 
 impl<T: RPC> Instruction<T> {
     pub fn new(operations: Vec<Box<dyn Operation<T>>>) -> Self {
-        Instruction { operations }
+        Instruction {
+            operations,
+            rollback: Rollback::Disabled,
+            middlewares: Vec::new(),
+        }
+    }
+
+    /// Enable atomic mode, the skeleton and log will be restored according to `rollback` if any operation fails
+    pub fn atomic(&mut self, rollback: Rollback) -> &mut Self {
+        self.rollback = rollback;
+        self
+    }
+
+    /// Attach middleware that runs before and after every operation
+    pub fn middleware<M: Middleware + 'static>(&mut self, middleware: M) -> &mut Self {
+        self.middlewares.push(Box::new(middleware));
+        self
     }
 
     pub fn push(&mut self, operation: Box<dyn Operation<T>>) -> &mut Self {
@@ -51,6 +128,21 @@ impl<T: RPC> Instruction<T> {
         self
     }
 
+    /// Render the plan of operations without running them
+    pub fn plan(&self) -> Plan {
+        self.plan_under(None)
+    }
+
+    fn plan_under(&self, instruction: Option<usize>) -> Plan {
+        let steps = self
+            .operations
+            .iter()
+            .enumerate()
+            .map(|(index, operation)| PlanStep::new(instruction, index, operation.as_ref()))
+            .collect();
+        Plan { steps }
+    }
+
     /// Execute all operations in sequence to assemble transaction skeleton
     pub async fn run(
         self,
@@ -58,17 +150,95 @@ impl<T: RPC> Instruction<T> {
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
     ) -> Result<()> {
-        for operation in self.operations {
-            operation.run(rpc, skeleton, log).await?;
+        self.try_run(rpc, skeleton, log)
+            .await
+            .map_err(|failure| failure.error)
+    }
+
+    /// Execute all operations in sequence, and return the index of the failing operation if any
+    pub async fn try_run(
+        self,
+        rpc: &T,
+        skeleton: &mut TransactionSkeleton,
+        log: &mut Log,
+    ) -> Result<(), OperationFailure> {
+        self.try_run_with(rpc, skeleton, log, None, &mut []).await
+    }
+
+    /// Execute all operations under the location of `instruction` in calculator, which wrapped by `outer` middlewares
+    async fn try_run_with(
+        mut self,
+        rpc: &T,
+        skeleton: &mut TransactionSkeleton,
+        log: &mut Log,
+        instruction: Option<usize>,
+        outer: &mut [Box<dyn Middleware>],
+    ) -> Result<(), OperationFailure> {
+        let mut instruction_checkpoint = if self.rollback == Rollback::Instruction {
+            Some((skeleton.clone(), log.clone()))
+        } else {
+            None
+        };
+        for (index, operation) in self.operations.into_iter().enumerate() {
+            let operation_checkpoint = if self.rollback == Rollback::Operation {
+                Some((skeleton.clone(), log.clone()))
+            } else {
+                None
+            };
+            let context = OperationContext {
+                instruction,
+                index,
+                name: operation.name(),
+            };
+            outer
+                .iter_mut()
+                .chain(self.middlewares.iter_mut())
+                .for_each(|middleware| middleware.before(&context, skeleton, log));
+            let result = instrument(&context, operation.run(rpc, skeleton, log)).await;
+            self.middlewares
+                .iter_mut()
+                .rev()
+                .chain(outer.iter_mut().rev())
+                .for_each(|middleware| {
+                    middleware.after(&context, skeleton, log, result.as_ref().err())
+                });
+            if let Err(error) = result {
+                if let Some((old_skeleton, old_log)) =
+                    operation_checkpoint.or_else(|| instruction_checkpoint.take())
+                {
+                    *skeleton = old_skeleton;
+                    *log = old_log;
+                }
+                return Err(OperationFailure { index, error });
+            }
         }
         Ok(())
     }
 }
 
+/// Instruction can also be treated as a composed operation, e.g. a candidate in `FirstSuccess`
+#[async_trait::async_trait]
+impl<T: RPC> Operation<T> for Instruction<T> {
+    fn params(&self) -> serde_json::Value {
+        serde_json::to_value(self.plan().steps).unwrap_or_default()
+    }
+
+    async fn run(
+        self: Box<Self>,
+        rpc: &T,
+        skeleton: &mut TransactionSkeleton,
+        log: &mut Log,
+    ) -> Result<()> {
+        Instruction::run(*self, rpc, skeleton, log).await
+    }
+}
+
 /// Take responsibility for executing instructions and then assemble transaction skeleton
 pub struct TransactionCalculator<T: RPC> {
     instructions: Vec<Instruction<T>>,
     log: Log,
+    rollback: Rollback,
+    middlewares: Vec<Box<dyn Middleware>>,
 }
 
 impl<T: RPC> Default for TransactionCalculator<T> {
@@ -76,6 +246,8 @@ impl<T: RPC> Default for TransactionCalculator<T> {
         TransactionCalculator {
             instructions: Vec::new(),
             log: Log::new(),
+            rollback: Rollback::Disabled,
+            middlewares: Vec::new(),
         }
     }
 }
@@ -85,6 +257,8 @@ impl<T: RPC> TransactionCalculator<T> {
         TransactionCalculator {
             instructions,
             log: Log::new(),
+            rollback: Rollback::Disabled,
+            middlewares: Vec::new(),
         }
     }
 
@@ -93,6 +267,31 @@ impl<T: RPC> TransactionCalculator<T> {
         self
     }
 
+    /// Enable atomic mode, the skeleton and log will be restored according to `rollback` if any operation fails
+    ///
+    /// note: `Rollback::Operation` only applies to the instructions that haven't enabled their own atomic mode
+    pub fn atomic(mut self, rollback: Rollback) -> Self {
+        self.rollback = rollback;
+        self
+    }
+
+    /// Attach middleware that runs before and after every operation of all instructions
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+
+    /// Render the plan of all instructions without running them
+    pub fn plan(&self) -> Plan {
+        let steps = self
+            .instructions
+            .iter()
+            .enumerate()
+            .flat_map(|(index, instruction)| instruction.plan_under(Some(index)).steps)
+            .collect();
+        Plan { steps }
+    }
+
     pub async fn new_skeleton(self, rpc: &T) -> Result<(TransactionSkeleton, Log)> {
         let mut skeleton = TransactionSkeleton::default();
         let log = self.apply_skeleton(rpc, &mut skeleton).await?;
@@ -100,10 +299,137 @@ impl<T: RPC> TransactionCalculator<T> {
     }
 
     pub async fn apply_skeleton(self, rpc: &T, skeleton: &mut TransactionSkeleton) -> Result<Log> {
+        self.try_apply_skeleton(rpc, skeleton)
+            .await
+            .map_err(|failure| failure.error)
+    }
+
+    /// Execute all instructions in sequence, and return the indices of the failing instruction and operation if any
+    pub async fn try_apply_skeleton(
+        self,
+        rpc: &T,
+        skeleton: &mut TransactionSkeleton,
+    ) -> Result<Log, InstructionFailure> {
         let mut log = self.log;
-        for instruction in self.instructions {
-            instruction.run(rpc, skeleton, &mut log).await?;
+        let mut middlewares = self.middlewares;
+        for (index, mut instruction) in self.instructions.into_iter().enumerate() {
+            let checkpoint = if self.rollback == Rollback::Instruction {
+                Some((skeleton.clone(), log.clone()))
+            } else {
+                None
+            };
+            if self.rollback == Rollback::Operation && instruction.rollback == Rollback::Disabled {
+                instruction.rollback = Rollback::Operation;
+            }
+            if let Err(failure) = instruction
+                .try_run_with(rpc, skeleton, &mut log, Some(index), &mut middlewares)
+                .await
+            {
+                if let Some((old_skeleton, old_log)) = checkpoint {
+                    *skeleton = old_skeleton;
+                    log = old_log;
+                }
+                return Err(InstructionFailure {
+                    index,
+                    operation_index: failure.index,
+                    error: failure.error,
+                    log,
+                });
+            }
         }
         Ok(log)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{simulation::FakeRpcClient, skeleton::CellOutputEx};
+
+    /// Push an empty output and log it, fail afterwards if `fail` is set
+    struct PushOutput {
+        fail: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl Operation<FakeRpcClient> for PushOutput {
+        async fn run(
+            self: Box<Self>,
+            _: &FakeRpcClient,
+            skeleton: &mut TransactionSkeleton,
+            log: &mut Log,
+        ) -> Result<()> {
+            skeleton.output(CellOutputEx::new(Default::default(), vec![]));
+            log.emit_custom::<Self>(skeleton, "output", vec![]);
+            if self.fail {
+                return Err(eyre::eyre!("failed on purpose"));
+            }
+            Ok(())
+        }
+    }
+
+    fn instruction(fails: &[bool]) -> Instruction<FakeRpcClient> {
+        Instruction::new(
+            fails
+                .iter()
+                .map(|fail| Box::new(PushOutput { fail: *fail }) as Box<dyn Operation<_>>)
+                .collect(),
+        )
+    }
+
+    fn run_instruction(rollback: Rollback) -> (usize, usize, usize) {
+        let mut instruction = instruction(&[false, false, true]);
+        instruction.atomic(rollback);
+        let (mut skeleton, mut log) = (TransactionSkeleton::default(), Log::new());
+        let failure =
+            block_on(instruction.try_run(&FakeRpcClient::default(), &mut skeleton, &mut log))
+                .unwrap_err();
+        (failure.index, skeleton.outputs.len(), log.len())
+    }
+
+    #[test]
+    fn instruction_rollback() {
+        assert_eq!(run_instruction(Rollback::Disabled), (2, 3, 3));
+        assert_eq!(run_instruction(Rollback::Operation), (2, 2, 2));
+        assert_eq!(run_instruction(Rollback::Instruction), (2, 0, 0));
+    }
+
+    fn run_calculator(rollback: Rollback) -> (usize, usize, usize) {
+        let mut skeleton = TransactionSkeleton::default();
+        let failure = block_on(
+            TransactionCalculator::default()
+                .instruction(instruction(&[false]))
+                .instruction(instruction(&[false, true]))
+                .atomic(rollback)
+                .try_apply_skeleton(&FakeRpcClient::default(), &mut skeleton),
+        )
+        .unwrap_err();
+        assert_eq!(failure.operation_index, 1);
+        (failure.index, skeleton.outputs.len(), failure.log.len())
+    }
+
+    #[test]
+    fn calculator_rollback() {
+        assert_eq!(run_calculator(Rollback::Disabled), (1, 3, 3));
+        assert_eq!(run_calculator(Rollback::Operation), (1, 2, 2));
+        assert_eq!(run_calculator(Rollback::Instruction), (1, 1, 1));
+    }
+
+    #[test]
+    fn calculator_keeps_rollback_of_instruction() {
+        let mut atomic = instruction(&[false, true]);
+        atomic.atomic(Rollback::Instruction);
+        let mut skeleton = TransactionSkeleton::default();
+        let failure = block_on(
+            TransactionCalculator::default()
+                .instruction(instruction(&[false]))
+                .instruction(atomic)
+                .atomic(Rollback::Operation)
+                .try_apply_skeleton(&FakeRpcClient::default(), &mut skeleton),
+        )
+        .unwrap_err();
+        assert_eq!((skeleton.outputs.len(), failure.log.len()), (1, 1));
+    }
+}
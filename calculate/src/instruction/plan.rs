@@ -0,0 +1,143 @@
+use std::fmt::Display;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{operation::Operation, rpc::RPC};
+
+/// Describe an operation in form of `{ "name": ..., "params": ... }`, which is used to nest operations in plan
+pub fn describe<T: RPC>(operation: &dyn Operation<T>) -> Value {
+    json!({
+        "name": operation.name(),
+        "params": operation.params(),
+    })
+}
+
+/// A step of plan, which describes an operation before running
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanStep {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instruction: Option<usize>,
+    pub index: usize,
+    pub name: &'static str,
+    pub params: Value,
+}
+
+impl PlanStep {
+    pub fn new<T: RPC>(
+        instruction: Option<usize>,
+        index: usize,
+        operation: &dyn Operation<T>,
+    ) -> Self {
+        PlanStep {
+            instruction,
+            index,
+            name: operation.name(),
+            params: operation.params(),
+        }
+    }
+}
+
+impl Display for PlanStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.instruction {
+            Some(instruction) => write!(f, "#{instruction}.{} {}", self.index, self.name)?,
+            None => write!(f, "#{} {}", self.index, self.name)?,
+        }
+        if !self.params.is_null() {
+            write!(f, " {}", self.params)?;
+        }
+        Ok(())
+    }
+}
+
+/// Human-readable plan of what instruction or calculator will build, secrets are redacted by operations
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Plan {
+    pub steps: Vec<PlanStep>,
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let steps = self
+            .steps
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        write!(f, "{}", steps.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::SecretKey;
+
+    use super::*;
+    use crate::{
+        instruction::Instruction,
+        operation::{
+            basic::{AddOutputCell, AddSecp256k1SighashSignatures},
+            control::If,
+        },
+        serde_ext::secret_keys::REDACTED,
+        simulation::FakeRpcClient,
+        skeleton::ScriptEx,
+    };
+
+    fn secret_key() -> SecretKey {
+        SecretKey::from_slice(&[7u8; 32]).unwrap()
+    }
+
+    fn signatures() -> AddSecp256k1SighashSignatures {
+        AddSecp256k1SighashSignatures {
+            user_lock_scripts: vec![ScriptEx::default()],
+            user_private_keys: vec![secret_key()],
+        }
+    }
+
+    #[test]
+    fn plan_redacts_secret_keys() {
+        let mut instruction = Instruction::<FakeRpcClient>::default();
+        instruction
+            .push(Box::new(AddOutputCell {
+                capacity: 100,
+                ..Default::default()
+            }))
+            .push(Box::new(signatures()));
+        let plan = instruction.plan();
+        assert_eq!(plan.steps.len(), 2);
+        assert_eq!(plan.steps[0].name, "AddOutputCell");
+        assert_eq!(plan.steps[0].params["capacity"], 100);
+        assert_eq!(plan.steps[1].name, "AddSecp256k1SighashSignatures");
+        assert_eq!(plan.steps[1].params["user_private_keys"][0], REDACTED);
+        let secret = hex::encode(secret_key().secret_bytes());
+        assert!(!plan.to_string().contains(&secret));
+        assert!(plan.to_string().starts_with("#0 AddOutputCell"));
+    }
+
+    #[test]
+    fn serialization_redacts_secret_keys() {
+        let json = serde_json::to_string(&signatures()).unwrap();
+        assert!(!json.contains(&hex::encode(secret_key().secret_bytes())));
+        assert!(serde_json::from_str::<AddSecp256k1SighashSignatures>(&json).is_err());
+        let json = json.replace(
+            REDACTED,
+            &format!("0x{}", hex::encode(secret_key().secret_bytes())),
+        );
+        let operation: AddSecp256k1SighashSignatures = serde_json::from_str(&json).unwrap();
+        assert_eq!(operation.user_private_keys, vec![secret_key()]);
+    }
+
+    #[test]
+    fn plan_describes_nested_operations() {
+        let operation = If::<FakeRpcClient>::new(|_, _| true, Box::new(signatures()), None);
+        let step = PlanStep::new(Some(1), 2, &operation);
+        assert_eq!(step.name, "If");
+        assert_eq!(step.params["then"]["name"], "AddSecp256k1SighashSignatures");
+        assert_eq!(
+            step.params["then"]["params"]["user_private_keys"][0],
+            REDACTED
+        );
+        assert!(step.to_string().starts_with("#1.2 If"));
+    }
+}
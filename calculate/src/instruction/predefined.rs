@@ -1,12 +1,14 @@
-use std::{path::PathBuf, usize};
+use std::path::PathBuf;
 
 use ckb_sdk::{Address, HumanCapacity};
 use ckb_types::H256;
 use secp256k1::SecretKey;
 
 use crate::{
-    instruction::DefaultInstruction,
+    instruction::Instruction,
     operation::{basic::*, dao::*, spore::*},
+    rpc::RPC,
+    skeleton::{Contribution, Payer},
 };
 
 /// Transfer CKB from one address to another
@@ -18,12 +20,12 @@ use crate::{
 /// - `sign`:
 ///     - 0: privkey => The private key to sign the transaction, if not provided, transaction won't balance and sign
 ///     - 1: additional_fee_rate => The additional fee rate to add
-pub fn secp256k1_sighash_transfer(
+pub fn secp256k1_sighash_transfer<T: RPC>(
     from: &Address,
     to: &Address,
     ckb: HumanCapacity,
-) -> DefaultInstruction {
-    DefaultInstruction::new(vec![
+) -> Instruction<T> {
+    Instruction::new(vec![
         Box::new(AddSecp256k1SighashCellDep {}),
         Box::new(AddInputCellByAddress {
             address: from.clone(),
@@ -45,17 +47,17 @@ pub fn secp256k1_sighash_transfer(
 /// - `signer`: The address who is supposed to provide capacity to balance, in the meantime, receive the change
 /// - `privkey`: The private key to sign the transaction
 /// - `additional_fee_rate`: The additional fee rate to add
-pub fn balance_and_sign(
+pub fn balance_and_sign<T: RPC>(
     signer: &Address,
     privkey: SecretKey,
     additional_fee_rate: u64,
-) -> DefaultInstruction {
-    DefaultInstruction::new(vec![
-        Box::new(BalanceTransaction {
-            balancer: signer.payload().into(),
-            change_receiver: signer.clone().into(),
+) -> Instruction<T> {
+    Instruction::new(vec![
+        Box::new(BalanceTransaction::new(
+            signer.payload().into(),
+            signer.clone().into(),
             additional_fee_rate,
-        }),
+        )),
         Box::new(AddSecp256k1SighashSignatures {
             user_lock_scripts: vec![signer.payload().into()],
             user_private_keys: vec![privkey],
@@ -63,23 +65,62 @@ pub fn balance_and_sign(
     ])
 }
 
+/// Balance transaction by several payers and then sign it by all of them
+///
+/// # Parameters
+/// - `payers`: The addresses and their contributions, each one receives its own change
+/// - `privkeys`: The private keys of payers in the same order
+/// - `additional_fee_rate`: The additional fee rate to add
+pub fn balance_by_payers_and_sign<T: RPC>(
+    payers: Vec<(Address, Contribution)>,
+    privkeys: Vec<SecretKey>,
+    additional_fee_rate: u64,
+) -> Instruction<T> {
+    let user_lock_scripts = payers
+        .iter()
+        .map(|(address, _)| address.payload().into())
+        .collect();
+    let payers = payers
+        .into_iter()
+        .map(|(address, contribution)| Payer {
+            balancer: address.payload().into(),
+            change_receiver: address.into(),
+            contribution,
+            change_policy: Default::default(),
+        })
+        .collect();
+    Instruction::new(vec![
+        Box::new(BalanceTransactionByPayers {
+            payers,
+            additional_fee_rate,
+            multisig_configs: Default::default(),
+            max_cycles: None,
+            coin_selection: Default::default(),
+        }),
+        Box::new(AddSecp256k1SighashSignatures {
+            user_lock_scripts,
+            user_private_keys: privkeys,
+        }),
+    ])
+}
+
 /// Balance transaction with capacity and then sign it with native CKB-CLI
 ///
 /// # Parameters
 /// - `signer`: The address who is supposed to provide capacity to balance, in the meantime, receive the change
 /// - `additional_fee_rate`: The additional fee rate to add
 /// - `cache_path`: The path to store the transaction cache file, default is `/tmp`
-pub fn balance_and_sign_with_ckb_cli(
+pub fn balance_and_sign_with_ckb_cli<T: RPC>(
     signer: &Address,
     additional_fee_rate: u64,
     cache_path: Option<PathBuf>,
-) -> DefaultInstruction {
-    DefaultInstruction::new(vec![
-        Box::new(BalanceTransaction {
-            balancer: signer.payload().into(),
-            change_receiver: signer.clone().into(),
+) -> Instruction<T> {
+    Instruction::new(vec![
+        Box::new(BalanceTransaction::new(
+            signer.payload().into(),
+            signer.clone().into(),
             additional_fee_rate,
-        }),
+        )),
         Box::new(AddSecp256k1SighashSignaturesWithCkbCli {
             signer_address: signer.clone(),
             cache_path: cache_path.unwrap_or_else(|| PathBuf::from("/tmp")),
@@ -101,12 +142,12 @@ pub struct Spore {
 /// - `minter`: The address to mint Spore
 /// - `spores`: The Spores to mint
 /// - `cluster_lock_proxy`: Whether to use cluster lock proxy
-pub fn mint_spores(
+pub fn mint_spores<T: RPC>(
     minter: &Address,
     spores: Vec<Spore>,
     cluster_lock_proxy: bool,
-) -> DefaultInstruction {
-    let mut mint = DefaultInstruction::new(vec![
+) -> Instruction<T> {
+    let mut mint = Instruction::new(vec![
         Box::new(AddSecp256k1SighashCellDep {}),
         // Used to calculate the spore unique id
         Box::new(AddInputCellByAddress {
@@ -144,8 +185,8 @@ pub fn mint_spores(
 /// - `spores`: The Spores to transfer
 ///     - `0`: The address to transfer Spore to
 ///     - `1`: The Spore ID to transfer
-pub fn transfer_spores(from: &Address, spores: Vec<(Address, H256)>) -> DefaultInstruction {
-    let mut transfer = DefaultInstruction::new(vec![Box::new(AddSecp256k1SighashCellDep {})]);
+pub fn transfer_spores<T: RPC>(from: &Address, spores: Vec<(Address, H256)>) -> Instruction<T> {
+    let mut transfer = Instruction::new(vec![Box::new(AddSecp256k1SighashCellDep {})]);
     for (to, spore_id) in spores {
         transfer
             .push(Box::new(AddSporeInputCellBySporeId {
@@ -169,8 +210,8 @@ pub fn transfer_spores(from: &Address, spores: Vec<(Address, H256)>) -> DefaultI
 /// # Parameters
 /// - `owner`: The address to burn Spore from
 /// - `spores`: The Spores to burn
-pub fn burn_spores(owner: &Address, spores: Vec<H256>) -> DefaultInstruction {
-    let mut burn = DefaultInstruction::new(vec![Box::new(AddSecp256k1SighashCellDep {})]);
+pub fn burn_spores<T: RPC>(owner: &Address, spores: Vec<H256>) -> Instruction<T> {
+    let mut burn = Instruction::new(vec![Box::new(AddSecp256k1SighashCellDep {})]);
     spores.into_iter().for_each(|spore_id| {
         burn.push(Box::new(AddSporeInputCellBySporeId {
             spore_id,
@@ -192,8 +233,8 @@ pub struct Cluster {
 /// # Parameters
 /// - `minter`: The address to mint Cluster
 /// - `clusters`: The Clusters to mint
-pub fn mint_clusters(minter: &Address, clusters: Vec<Cluster>) -> DefaultInstruction {
-    let mut mint = DefaultInstruction::new(vec![
+pub fn mint_clusters<T: RPC>(minter: &Address, clusters: Vec<Cluster>) -> Instruction<T> {
+    let mut mint = Instruction::new(vec![
         Box::new(AddSecp256k1SighashCellDep {}),
         Box::new(AddInputCellByAddress {
             address: minter.clone(),
@@ -220,8 +261,8 @@ pub fn mint_clusters(minter: &Address, clusters: Vec<Cluster>) -> DefaultInstruc
 /// # Parameters
 /// - `from`: The address to transfer Cluster from
 /// - `clusters`: The Clusters to transfer
-pub fn transfer_clusters(from: &Address, clusters: Vec<(Address, H256)>) -> DefaultInstruction {
-    let mut transfer = DefaultInstruction::new(vec![
+pub fn transfer_clusters<T: RPC>(from: &Address, clusters: Vec<(Address, H256)>) -> Instruction<T> {
+    let mut transfer = Instruction::new(vec![
         Box::new(AddSecp256k1SighashCellDep {}),
         Box::new(AddInputCellByAddress {
             address: from.clone(),
@@ -247,8 +288,8 @@ pub fn transfer_clusters(from: &Address, clusters: Vec<(Address, H256)>) -> Defa
 /// # Parameters
 /// - `depositer`: The address to deposit capacity
 /// - `ckb`: The amount of CKB to deposit, e.g. "100.5 CKB"
-pub fn dao_deposit(depositer: &Address, ckb: HumanCapacity) -> DefaultInstruction {
-    DefaultInstruction::new(vec![
+pub fn dao_deposit<T: RPC>(depositer: &Address, ckb: HumanCapacity) -> Instruction<T> {
+    Instruction::new(vec![
         Box::new(AddSecp256k1SighashCellDep {}),
         Box::new(AddDaoDepositOutputCell {
             owner: depositer.clone().into(),
@@ -264,13 +305,13 @@ pub fn dao_deposit(depositer: &Address, ckb: HumanCapacity) -> DefaultInstructio
 /// - `upperbound_capacity`: The maximum capacity to withdraw from Nervos DAO
 /// - `upperbound_timestamp`: The upperbound timestamp that only choose cells before it
 /// - `transfer_to`: if provided, the capacity will be transferred to this address
-pub fn dao_withdraw_phase_one(
+pub fn dao_withdraw_phase_one<T: RPC>(
     depositer: &Address,
     upperbound_capacity: Option<HumanCapacity>,
     upperbound_timestamp: Option<u64>,
     transfer_to: Option<&Address>,
-) -> DefaultInstruction {
-    DefaultInstruction::new(vec![
+) -> Instruction<T> {
+    Instruction::new(vec![
         Box::new(AddSecp256k1SighashCellDep {}),
         Box::new(AddDaoWithdrawPhaseOneCells {
             maximal_withdraw_capacity: upperbound_capacity.map(Into::into).unwrap_or(u64::MAX),
@@ -288,12 +329,12 @@ pub fn dao_withdraw_phase_one(
 /// - `withdrawer`: The address to withdraw capacity
 /// - `upperbound_capacity`: The maximum capacity to withdraw from Nervos DAO
 /// - `transfer_to`: if provided, the capacity will be transferred to this address
-pub fn dao_withdraw_phase_two(
+pub fn dao_withdraw_phase_two<T: RPC>(
     withdrawer: &Address,
     upperbound_capacity: Option<HumanCapacity>,
     transfer_to: Option<&Address>,
-) -> DefaultInstruction {
-    DefaultInstruction::new(vec![
+) -> Instruction<T> {
+    Instruction::new(vec![
         Box::new(AddSecp256k1SighashCellDep {}),
         Box::new(AddDaoWithdrawPhaseTwoCells {
             maximal_withdraw_capacity: upperbound_capacity.map(Into::into).unwrap_or(u64::MAX),
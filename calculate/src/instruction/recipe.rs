@@ -0,0 +1,361 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use eyre::{eyre, Result};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+
+use crate::{
+    instruction::Instruction,
+    operation::{basic::*, component::*, dao::*, spore::*, Operation},
+    rpc::RPC,
+};
+
+/// Format of the recipe file
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecipeFormat {
+    Json,
+    Toml,
+}
+
+impl RecipeFormat {
+    /// Guess recipe format from file extension
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|v| v.to_str()) {
+            Some("json") => Ok(RecipeFormat::Json),
+            Some("toml") => Ok(RecipeFormat::Toml),
+            _ => Err(eyre!("unknown recipe format: {}", path.display())),
+        }
+    }
+}
+
+/// A declarative operation in recipe, the `params` field follows the same layout of operation struct
+#[derive(Deserialize)]
+pub struct RecipeOperation {
+    pub name: String,
+    #[serde(default = "empty_params")]
+    pub params: Value,
+}
+
+fn empty_params() -> Value {
+    Value::Object(Default::default())
+}
+
+/// Declarative form of Instruction, which can be written in JSON or TOML
+///
+/// e.g. in TOML:
+/// ```toml
+/// [[operations]]
+/// name = "AddSecp256k1SighashCellDep"
+///
+/// [[operations]]
+/// name = "AddInputCellByAddress"
+/// params = { address = "${from_address}" }
+/// ```
+#[derive(Deserialize)]
+pub struct Recipe {
+    pub operations: Vec<RecipeOperation>,
+}
+
+impl Recipe {
+    /// Parse recipe content and then fill in all of `${name}` placeholders in string values with `variables`
+    pub fn parse(
+        content: &str,
+        format: RecipeFormat,
+        variables: &HashMap<String, String>,
+    ) -> Result<Self> {
+        let mut recipe: Value = match format {
+            RecipeFormat::Json => serde_json::from_str(content)?,
+            RecipeFormat::Toml => toml::from_str(content)?,
+        };
+        fill_placeholders(&mut recipe, variables)?;
+        Ok(serde_json::from_value(recipe)?)
+    }
+
+    /// Load recipe from file, the format is decided by file extension
+    pub fn load<P: AsRef<Path>>(path: P, variables: &HashMap<String, String>) -> Result<Self> {
+        let format = RecipeFormat::from_path(path.as_ref())?;
+        let content = fs::read_to_string(path)?;
+        Self::parse(&content, format, variables)
+    }
+
+    /// Turn into Instruction by looking up operations in registry
+    pub fn into_instruction<T: RPC + 'static>(
+        self,
+        registry: &OperationRegistry<T>,
+    ) -> Result<Instruction<T>> {
+        let operations = self
+            .operations
+            .into_iter()
+            .enumerate()
+            .map(|(i, operation)| {
+                registry
+                    .build(&operation.name, operation.params)
+                    .map_err(|e| eyre!("operation #{i} ({}): {e}", operation.name))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Instruction::new(operations))
+    }
+}
+
+/// Replace `${name}` inside all of string values with the value in `variables`, the parsed structure is never changed
+fn fill_placeholders(value: &mut Value, variables: &HashMap<String, String>) -> Result<()> {
+    match value {
+        Value::String(content) => *content = fill_string(content, variables)?,
+        Value::Array(items) => items
+            .iter_mut()
+            .try_for_each(|v| fill_placeholders(v, variables))?,
+        Value::Object(fields) => fields
+            .values_mut()
+            .try_for_each(|v| fill_placeholders(v, variables))?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replace `${name}` with the value in `variables`, throw error if any placeholder is left
+fn fill_string(content: &str, variables: &HashMap<String, String>) -> Result<String> {
+    let mut filled = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("${") {
+        filled.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or(eyre!("unclosed placeholder in recipe"))?;
+        let name = &rest[start + 2..start + end];
+        let value = variables
+            .get(name)
+            .ok_or(eyre!("placeholder ${{{name}}} not provided"))?;
+        filled.push_str(value);
+        rest = &rest[start + end + 1..];
+    }
+    filled.push_str(rest);
+    Ok(filled)
+}
+
+pub type OperationBuilder<T> = fn(Value) -> Result<Box<dyn Operation<T>>>;
+
+fn build_operation<T, O>(params: Value) -> Result<Box<dyn Operation<T>>>
+where
+    T: RPC,
+    O: Operation<T> + DeserializeOwned + 'static,
+{
+    let operation: O = serde_json::from_value(params)?;
+    Ok(Box::new(operation))
+}
+
+/// Registry that maps operation names to the concrete operation structs
+pub struct OperationRegistry<T: RPC> {
+    builders: HashMap<String, OperationBuilder<T>>,
+}
+
+impl<T: RPC + 'static> Default for OperationRegistry<T> {
+    fn default() -> Self {
+        let mut registry = OperationRegistry::empty();
+        // basic
+        registry
+            .register::<AddCellDep>("AddCellDep")
+            .register::<AddCellDepByType>("AddCellDepByType")
+            .register::<AddSecp256k1SighashCellDep>("AddSecp256k1SighashCellDep")
+            .register::<AddHeaderDep>("AddHeaderDep")
+            .register::<AddHeaderDepByBlockNumber>("AddHeaderDepByBlockNumber")
+            .register::<AddHeaderDepByInputIndex>("AddHeaderDepByInputIndex")
+            .register::<AddInputCell>("AddInputCell")
+            .register::<AddInputCellByOutPoint>("AddInputCellByOutPoint")
+            .register::<AddInputCellByAddress>("AddInputCellByAddress")
+            .register::<AddInputCellByType>("AddInputCellByType")
+            .register::<AddOutputCell>("AddOutputCell")
+            .register::<AddOutputCellByAddress>("AddOutputCellByAddress")
+            .register::<AddOutputCellByInputIndex>("AddOutputCellByInputIndex")
+            .register::<AddWitnessArgs>("AddWitnessArgs")
+            .register::<AddSecp256k1SighashSignatures>("AddSecp256k1SighashSignatures")
+            .register::<AddSecp256k1SighashSignaturesWithCkbCli>(
+                "AddSecp256k1SighashSignaturesWithCkbCli",
+            )
+            .register::<BalanceTransaction>("BalanceTransaction")
+            .register::<BalanceTransactionByPayers>("BalanceTransactionByPayers")
+            .register::<NormalizeTransaction>("NormalizeTransaction")
+            .register::<ValidateTransaction>("ValidateTransaction");
+        // dao
+        registry
+            .register::<AddDaoCelldep>("AddDaoCelldep")
+            .register::<AddDaoDepositOutputCell>("AddDaoDepositOutputCell")
+            .register::<AddDaoWithdrawPhaseOneCells>("AddDaoWithdrawPhaseOneCells")
+            .register::<AddDaoWithdrawPhaseTwoCells>("AddDaoWithdrawPhaseTwoCells");
+        // spore
+        registry
+            .register::<AddSporeCelldep>("AddSporeCelldep")
+            .register::<AddClusterCelldep>("AddClusterCelldep")
+            .register::<AddClusterCelldepByClusterId>("AddClusterCelldepByClusterId")
+            .register::<AddSporeInputCellByClusterId>("AddSporeInputCellByClusterId")
+            .register::<AddSporeInputCellBySporeId>("AddSporeInputCellBySporeId")
+            .register::<AddSporeOutputCell>("AddSporeOutputCell")
+            .register::<AddClusterInputCellByClusterId>("AddClusterInputCellByClusterId")
+            .register::<AddClusterOutputCell>("AddClusterOutputCell")
+            .register::<AddSporeActions>("AddSporeActions");
+        // component
+        registry
+            .register::<AddComponentCelldep>("AddComponentCelldep")
+            .register::<AddTypeBurnOutputCell>("AddTypeBurnOutputCell")
+            .register::<AddTypeBurnInputCell>("AddTypeBurnInputCell")
+            .register::<AddTypeBurnInputCellByInputIndex>("AddTypeBurnInputCellByInputIndex")
+            .register::<AddLockProxyOutputCell>("AddLockProxyOutputCell")
+            .register::<AddLockProxyInputCell>("AddLockProxyInputCell");
+        registry
+    }
+}
+
+impl<T: RPC + 'static> OperationRegistry<T> {
+    /// Create a registry without any builtin operations
+    pub fn empty() -> Self {
+        OperationRegistry {
+            builders: HashMap::new(),
+        }
+    }
+
+    /// Register a deserializable operation under `name`, the old one with the same name will be overridden
+    pub fn register<O>(&mut self, name: &str) -> &mut Self
+    where
+        O: Operation<T> + DeserializeOwned + 'static,
+    {
+        self.builders
+            .insert(name.to_string(), build_operation::<T, O>);
+        self
+    }
+
+    /// Register a custom builder under `name`
+    pub fn register_builder(&mut self, name: &str, builder: OperationBuilder<T>) -> &mut Self {
+        self.builders.insert(name.to_string(), builder);
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.builders.contains_key(name)
+    }
+
+    /// Build operation from its name and params
+    pub fn build(&self, name: &str, params: Value) -> Result<Box<dyn Operation<T>>> {
+        let builder = self
+            .builders
+            .get(name)
+            .ok_or(eyre!("operation {name} not registered"))?;
+        builder(params)
+    }
+}
+
+impl<T: RPC + 'static> Instruction<T> {
+    /// Load Instruction from recipe content with the default operation registry
+    pub fn from_recipe(
+        content: &str,
+        format: RecipeFormat,
+        variables: &HashMap<String, String>,
+    ) -> Result<Self> {
+        Recipe::parse(content, format, variables)?.into_instruction(&OperationRegistry::default())
+    }
+
+    /// Load Instruction from recipe file with the default operation registry
+    pub fn from_recipe_file<P: AsRef<Path>>(
+        path: P,
+        variables: &HashMap<String, String>,
+    ) -> Result<Self> {
+        Recipe::load(path, variables)?.into_instruction(&OperationRegistry::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::FakeRpcClient;
+
+    fn variables(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn fill_placeholders_in_nested_strings() {
+        let mut value = serde_json::json!({
+            "name": "${name}",
+            "list": ["prefix-${a}-${b}", 1, null],
+            "nested": { "keep": "no placeholder" },
+        });
+        let variables = variables(&[("name", "alice"), ("a", "1"), ("b", "2")]);
+        fill_placeholders(&mut value, &variables).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "name": "alice",
+                "list": ["prefix-1-2", 1, null],
+                "nested": { "keep": "no placeholder" },
+            })
+        );
+    }
+
+    #[test]
+    fn fill_placeholders_keeps_special_characters_verbatim() {
+        let content = r#"{ "operations": [{ "name": "AddHeaderDep", "params": { "block_hash": "${hash}" } }] }"#;
+        let injected = r#"0x01", "extra": "field"#;
+        let recipe = Recipe::parse(
+            content,
+            RecipeFormat::Json,
+            &variables(&[("hash", injected)]),
+        )
+        .unwrap();
+        let params = &recipe.operations[0].params;
+        assert_eq!(params.as_object().unwrap().len(), 1);
+        assert_eq!(params["block_hash"], injected);
+
+        let multiline = "line1\nline2 \\ \"quoted\"";
+        let recipe = Recipe::parse(
+            content,
+            RecipeFormat::Json,
+            &variables(&[("hash", multiline)]),
+        )
+        .unwrap();
+        assert_eq!(recipe.operations[0].params["block_hash"], multiline);
+    }
+
+    #[test]
+    fn fill_placeholders_fails_on_missing_or_unclosed() {
+        let mut value = serde_json::json!(["${missing}"]);
+        assert!(fill_placeholders(&mut value, &HashMap::new()).is_err());
+        let mut value = serde_json::json!(["${unclosed"]);
+        assert!(fill_placeholders(&mut value, &variables(&[("unclosed", "")])).is_err());
+    }
+
+    #[test]
+    fn parse_toml_recipe_into_instruction() {
+        let content = r#"
+            [[operations]]
+            name = "AddHeaderDepByBlockNumber"
+            params = { block_number = 100 }
+
+            [[operations]]
+            name = "AddCellDep"
+            params = { name = "${celldep}", tx_hash = "0x0000000000000000000000000000000000000000000000000000000000000001", index = 0, dep_type = "code" }
+        "#;
+        let recipe = Recipe::parse(
+            content,
+            RecipeFormat::Toml,
+            &variables(&[("celldep", "my \"dep\"")]),
+        )
+        .unwrap();
+        assert_eq!(recipe.operations[1].params["name"], "my \"dep\"");
+        let instruction = recipe
+            .into_instruction(&OperationRegistry::<FakeRpcClient>::default())
+            .unwrap();
+        let plan = instruction.plan();
+        assert_eq!(plan.steps.len(), 2);
+        assert_eq!(plan.steps[1].params["name"], "my \"dep\"");
+    }
+
+    #[test]
+    fn unregistered_operation_is_rejected() {
+        let content = r#"{ "operations": [{ "name": "Unknown" }] }"#;
+        let recipe = Recipe::parse(content, RecipeFormat::Json, &HashMap::new()).unwrap();
+        assert!(recipe
+            .into_instruction(&OperationRegistry::<FakeRpcClient>::default())
+            .is_err());
+    }
+}
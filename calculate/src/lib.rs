@@ -1,10 +1,23 @@
+pub mod bump;
+pub mod cache;
+pub mod fee;
 pub mod instruction;
+pub mod locker;
 pub mod operation;
+pub mod overlay;
+pub mod replay;
 pub mod rpc;
+pub mod selection;
+pub mod serde_ext;
 pub mod simulation;
 pub mod skeleton;
+pub mod validation;
+pub mod watch;
 pub use instruction::TransactionCalculator;
 
+// Allow `#[derive(Operation)]` to refer this crate by name from inside
+extern crate self as ckb_cinnabar_calculator;
+
 // Re-exports to eliminate the need for downstream dependencies to specify the version of ckb_* crates
 pub mod re_exports {
     pub use async_trait;
@@ -14,5 +27,6 @@ pub mod re_exports {
     pub use ckb_types;
     pub use eyre;
     pub use secp256k1;
+    pub use serde_json;
     pub use tokio;
 }
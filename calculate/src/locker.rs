@@ -0,0 +1,291 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use ckb_types::packed::OutPoint;
+
+use crate::skeleton::TransactionSkeleton;
+
+/// Default time that a reservation keeps alive if not released explicitly
+pub const DEFAULT_RESERVATION_TTL: Duration = Duration::from_secs(120);
+
+/// Shared reservation store of out points, which prevents concurrently building skeletons from picking the same
+/// live cells
+///
+/// Attach it to rpc client, e.g. `RpcClientBuilder::cell_locker`, then `GetCellsIter` skips the reserved cells,
+/// balancing reserves the selected cells, and `send_and_wait` releases them once the transaction is committed,
+/// rejected or timeout
+///
+/// note: the cells reserved while building a skeleton are released once it's dropped without sending, refer to
+/// `Reservations`
+///
+/// note: cloned lockers share the same store
+#[derive(Clone)]
+pub struct CellLocker {
+    /// Reserved out points with the expiration, None means in-flight until released
+    reservations: Arc<Mutex<HashMap<OutPoint, Option<Instant>>>>,
+    ttl: Duration,
+}
+
+impl Default for CellLocker {
+    fn default() -> Self {
+        CellLocker::new(DEFAULT_RESERVATION_TTL)
+    }
+}
+
+impl CellLocker {
+    pub fn new(ttl: Duration) -> Self {
+        CellLocker {
+            reservations: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+        }
+    }
+
+    fn reservations(&self) -> std::sync::MutexGuard<'_, HashMap<OutPoint, Option<Instant>>> {
+        let mut reservations = self.reservations.lock().expect("poisoned cell locker");
+        let now = Instant::now();
+        reservations.retain(|_, expiration| expiration.is_none_or(|v| v > now));
+        reservations
+    }
+
+    pub fn is_locked(&self, out_point: &OutPoint) -> bool {
+        self.reservations().contains_key(out_point)
+    }
+
+    /// Reserve out points until ttl passes, the existing reservations are refreshed
+    pub fn lock<I: IntoIterator<Item = OutPoint>>(&self, out_points: I) {
+        let expiration = Instant::now() + self.ttl;
+        let mut reservations = self.reservations();
+        out_points.into_iter().for_each(|out_point| {
+            reservations.insert(out_point, Some(expiration));
+        });
+    }
+
+    /// Reserve out point only if it's not reserved yet, return false if failed
+    pub fn try_lock(&self, out_point: OutPoint) -> bool {
+        let expiration = Instant::now() + self.ttl;
+        let mut reservations = self.reservations();
+        if reservations.contains_key(&out_point) {
+            return false;
+        }
+        reservations.insert(out_point, Some(expiration));
+        true
+    }
+
+    /// Reserve out points that spent by a sent transaction, which never expire until released
+    pub fn lock_in_flight<I: IntoIterator<Item = OutPoint>>(&self, out_points: I) {
+        let mut reservations = self.reservations();
+        out_points.into_iter().for_each(|out_point| {
+            reservations.insert(out_point, None);
+        });
+    }
+
+    pub fn release<I: IntoIterator<Item = OutPoint>>(&self, out_points: I) {
+        let mut reservations = self.reservations();
+        out_points.into_iter().for_each(|out_point| {
+            reservations.remove(&out_point);
+        });
+    }
+
+    /// Reserve all of input cells in skeleton, e.g. the ones added by operations other than balancing
+    pub fn lock_skeleton(&self, skeleton: &TransactionSkeleton) {
+        self.lock(input_out_points(skeleton));
+    }
+
+    pub fn release_skeleton(&self, skeleton: &TransactionSkeleton) {
+        self.release(input_out_points(skeleton));
+    }
+
+    /// All of alive reservations
+    pub fn reserved(&self) -> Vec<OutPoint> {
+        self.reservations().keys().cloned().collect()
+    }
+}
+
+/// Cells that reserved for a skeleton, which are shared by its clones and released once all of them are dropped, so
+/// that a failed building doesn't hold its cells until ttl passes
+///
+/// note: `send_and_wait` hands the reservations over to the locker, call `TransactionSkeleton::keep_reservations` if
+/// the skeleton is sent or exported by other means
+#[derive(Clone, Default)]
+pub struct Reservations(Arc<ReservationGuard>);
+
+#[derive(Default)]
+struct ReservationGuard {
+    reserved: Mutex<Option<(CellLocker, Vec<OutPoint>)>>,
+}
+
+impl Drop for ReservationGuard {
+    fn drop(&mut self) {
+        if let Ok(Some((locker, out_points))) = self.reserved.get_mut().map(Option::take) {
+            locker.release(out_points);
+        }
+    }
+}
+
+impl std::fmt::Debug for Reservations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.out_points()).finish()
+    }
+}
+
+impl Reservations {
+    fn reserved(&self) -> std::sync::MutexGuard<'_, Option<(CellLocker, Vec<OutPoint>)>> {
+        self.0.reserved.lock().expect("poisoned reservations")
+    }
+
+    /// Reserve out point in `locker` only if it's not reserved yet, and track it until released or kept
+    pub(crate) fn try_lock(&self, locker: &CellLocker, out_point: OutPoint) -> bool {
+        if !locker.try_lock(out_point.clone()) {
+            return false;
+        }
+        let mut reserved = self.reserved();
+        let (_, out_points) = reserved.get_or_insert_with(|| (locker.clone(), vec![]));
+        out_points.push(out_point);
+        true
+    }
+
+    /// Release the tracked out points right away
+    pub(crate) fn release<I: IntoIterator<Item = OutPoint>>(&self, out_points: I) {
+        let mut reserved = self.reserved();
+        let Some((locker, tracked)) = reserved.as_mut() else {
+            return;
+        };
+        let released = out_points
+            .into_iter()
+            .filter(|v| tracked.contains(v))
+            .collect::<Vec<_>>();
+        tracked.retain(|v| !released.contains(v));
+        locker.release(released);
+    }
+
+    /// Stop tracking, the reservations are left to the locker, e.g. in-flight or expired by ttl
+    pub fn keep(&self) {
+        self.reserved().take();
+    }
+
+    /// Out points that are still tracked
+    pub fn out_points(&self) -> Vec<OutPoint> {
+        self.reserved()
+            .as_ref()
+            .map(|(_, out_points)| out_points.clone())
+            .unwrap_or_default()
+    }
+}
+
+pub(crate) fn input_out_points(skeleton: &TransactionSkeleton) -> Vec<OutPoint> {
+    skeleton
+        .inputs
+        .iter()
+        .map(|v| v.input.previous_output())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+
+    use ckb_types::{
+        core::{Capacity, ScriptHashType},
+        packed::{CellOutput, Script},
+        prelude::{Builder, Entity, Pack},
+    };
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{simulation::FakeRpcClient, skeleton::CellOutputEx};
+
+    fn out_point(index: u32) -> OutPoint {
+        OutPoint::new_builder().index(index.pack()).build()
+    }
+
+    #[test]
+    fn lock_and_release() {
+        let locker = CellLocker::default();
+        assert!(locker.try_lock(out_point(0)));
+        assert!(!locker.try_lock(out_point(0)));
+        locker.lock([out_point(1)]);
+        assert!(locker.is_locked(&out_point(1)));
+        locker.release([out_point(0)]);
+        assert!(!locker.is_locked(&out_point(0)));
+        // cloned lockers share the same store
+        let cloned = locker.clone();
+        cloned.release([out_point(1)]);
+        assert!(locker.reserved().is_empty());
+    }
+
+    #[test]
+    fn reservations_expire_but_in_flight_stays() {
+        let locker = CellLocker::new(Duration::from_millis(10));
+        locker.lock([out_point(0)]);
+        locker.lock_in_flight([out_point(1)]);
+        sleep(Duration::from_millis(20));
+        assert!(!locker.is_locked(&out_point(0)));
+        assert!(locker.is_locked(&out_point(1)));
+        assert!(locker.try_lock(out_point(0)));
+    }
+
+    #[test]
+    fn reservations_released_once_all_clones_dropped() {
+        let locker = CellLocker::default();
+        let reservations = Reservations::default();
+        assert!(reservations.try_lock(&locker, out_point(0)));
+        assert!(!reservations.try_lock(&locker, out_point(0)));
+        let cloned = reservations.clone();
+        assert!(cloned.try_lock(&locker, out_point(1)));
+        drop(cloned);
+        assert_eq!(reservations.out_points(), vec![out_point(0), out_point(1)]);
+        // only the tracked ones are released
+        locker.lock([out_point(2)]);
+        reservations.release([out_point(1), out_point(2)]);
+        assert!(!locker.is_locked(&out_point(1)));
+        assert!(locker.is_locked(&out_point(2)));
+        drop(reservations);
+        assert!(!locker.is_locked(&out_point(0)));
+        assert!(locker.is_locked(&out_point(2)));
+    }
+
+    #[test]
+    fn kept_reservations_survive_drop() {
+        let locker = CellLocker::default();
+        let reservations = Reservations::default();
+        reservations.try_lock(&locker, out_point(0));
+        reservations.keep();
+        assert!(reservations.out_points().is_empty());
+        drop(reservations);
+        assert!(locker.is_locked(&out_point(0)));
+    }
+
+    #[test]
+    fn skeleton_releases_reserved_inputs_on_drop() {
+        let lock = Script::new_builder()
+            .hash_type(ScriptHashType::Type.into())
+            .build();
+        let locker = CellLocker::default();
+        let mut rpc = FakeRpcClient::default().with_cell_locker(locker.clone());
+        for index in 0..2 {
+            let output = CellOutput::new_builder()
+                .capacity(Capacity::shannons(100).pack())
+                .lock(lock.clone())
+                .build();
+            rpc.insert_fake_cell(out_point(index), CellOutputEx::new(output, vec![]), None);
+        }
+        let mut first = TransactionSkeleton::default();
+        block_on(first.input_from_script(&rpc, lock.clone().into())).unwrap();
+        // the reserved cell is skipped by another skeleton
+        let mut second = TransactionSkeleton::default();
+        block_on(second.input_from_script(&rpc, lock.clone().into())).unwrap();
+        assert_eq!(
+            input_out_points(&second),
+            vec![out_point(1)],
+            "reserved by the first skeleton"
+        );
+        drop(first);
+        assert_eq!(locker.reserved(), vec![out_point(1)]);
+        second.keep_reservations();
+        drop(second);
+        assert_eq!(locker.reserved(), vec![out_point(1)]);
+    }
+}
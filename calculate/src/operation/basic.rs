@@ -8,7 +8,6 @@ use std::{
     process::{Command, Stdio},
 };
 
-use async_trait::async_trait;
 use ckb_jsonrpc_types::{JsonBytes, Transaction};
 use ckb_sdk::{
     constants::TYPE_ID_CODE_HASH,
@@ -21,36 +20,44 @@ use ckb_sdk::{
 use ckb_types::{
     core::{Capacity, DepType},
     h256,
-    packed::CellOutput,
+    packed::{CellOutput, WitnessArgs},
     prelude::{Builder, Entity, Pack, Unpack},
     H160, H256,
 };
 use eyre::{eyre, Result};
 use secp256k1::SecretKey;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::{
-    operation::{Log, Operation},
+    fee::{FeeEstimator, Secp256k1MultisigEstimator},
+    operation::{Event, Log, Operation},
     rpc::{GetCellsIter, Network, RPC},
+    selection::CoinSelection,
+    serde_ext,
+    simulation::TransactionSimulator,
     skeleton::{
-        CellDepEx, CellInputEx, CellOutputEx, ChangeReceiver, HeaderDepEx, ScriptEx,
-        TransactionSkeleton, WitnessEx,
+        CellDepEx, CellInputEx, CellOutputEx, ChangePolicy, ChangeReceiver, Contribution,
+        HeaderDepEx, Payer, ScriptEx, SinceEx, TransactionSkeleton, WitnessEx,
     },
 };
 
 /// Operation that add cell dep to transaction skeleton by tx hash with index
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddCellDep {
     pub name: String,
     pub tx_hash: H256,
     pub index: u32,
+    #[serde(with = "serde_ext::dep_type")]
     pub dep_type: DepType,
+    #[serde(default)]
     pub with_data: bool,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddCellDep {
-    async fn run(
-        self: Box<Self>,
+impl AddCellDep {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -72,10 +79,14 @@ impl<T: RPC> Operation<T> for AddCellDep {
 }
 
 /// Operation that add cell dep to transaction skeleton by type script, which is type id for specific
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddCellDepByType {
     pub name: String,
     pub type_script: ScriptEx,
+    #[serde(with = "serde_ext::dep_type")]
     pub dep_type: DepType,
+    #[serde(default)]
     pub with_data: bool,
 }
 
@@ -88,12 +99,9 @@ impl AddCellDepByType {
         }
         Ok(query.into())
     }
-}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddCellDepByType {
-    async fn run(
-        self: Box<Self>,
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -115,12 +123,13 @@ impl<T: RPC> Operation<T> for AddCellDepByType {
 }
 
 /// Operation that add secp256k1_sighash_all cell dep to transaction skeleton
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddSecp256k1SighashCellDep {}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddSecp256k1SighashCellDep {
-    async fn run(
-        self: Box<Self>,
+impl AddSecp256k1SighashCellDep {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -141,12 +150,12 @@ impl<T: RPC> Operation<T> for AddSecp256k1SighashCellDep {
                     let out_index: u32 = sighash_celldep.out_point().index().unpack();
                     tx.inner.outputs[out_index as usize].clone().into()
                 };
-                CellDepEx {
-                    name: "secp256k1_sighash_all".to_string(),
-                    celldep: sighash_celldep.clone(),
-                    output: CellOutputEx::new(output, vec![]),
-                    with_data: false,
-                }
+                CellDepEx::new(
+                    "secp256k1_sighash_all".to_string(),
+                    sighash_celldep.clone(),
+                    output,
+                    None,
+                )
             }
             Network::Testnet => {
                 CellDepEx::new_from_outpoint(
@@ -178,14 +187,15 @@ impl<T: RPC> Operation<T> for AddSecp256k1SighashCellDep {
 }
 
 /// Operation that add a standalone header dep to transaction without linking to any input cell
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddHeaderDep {
     pub block_hash: H256,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddHeaderDep {
-    async fn run(
-        self: Box<Self>,
+impl AddHeaderDep {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -197,14 +207,15 @@ impl<T: RPC> Operation<T> for AddHeaderDep {
 }
 
 /// Operation that add a header dep to transaction by block number
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddHeaderDepByBlockNumber {
     pub block_number: u64,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddHeaderDepByBlockNumber {
-    async fn run(
-        self: Box<Self>,
+impl AddHeaderDepByBlockNumber {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -223,14 +234,15 @@ impl<T: RPC> Operation<T> for AddHeaderDepByBlockNumber {
 }
 
 /// Operation that add a header dep to transaction by input index, which will link to that input cell
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddHeaderDepByInputIndex {
     pub input_index: usize,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddHeaderDepByInputIndex {
-    async fn run(
-        self: Box<Self>,
+impl AddHeaderDepByInputIndex {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -246,8 +258,11 @@ impl<T: RPC> Operation<T> for AddHeaderDepByInputIndex {
 ///
 /// # Parameters
 /// - `count`: u32, the count of input cells to add that searching coming out of ckb-indexer
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddInputCell {
     pub lock_script: ScriptEx,
+    #[serde(default)]
     pub type_script: Option<ScriptEx>,
     pub count: u32,
     pub search_mode: SearchMode,
@@ -266,12 +281,9 @@ impl AddInputCell {
         query.script_search_mode = Some(self.search_mode.clone());
         Ok(query.into())
     }
-}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddInputCell {
-    async fn run(
-        self: Box<Self>,
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -294,36 +306,41 @@ impl<T: RPC> Operation<T> for AddInputCell {
 }
 
 /// Operation that add input cell to transaction skeleton by out point directly
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddInputCellByOutPoint {
     pub tx_hash: H256,
     pub index: u32,
-    pub since: Option<u64>,
+    #[serde(default, with = "serde_ext::option_since")]
+    pub since: Option<SinceEx>,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddInputCellByOutPoint {
-    async fn run(
-        self: Box<Self>,
+impl AddInputCellByOutPoint {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
     ) -> Result<()> {
+        let since = self.since.map(|v| v.value()).transpose()?;
         let cell_input =
-            CellInputEx::new_from_outpoint(rpc, self.tx_hash, self.index, self.since, true).await?;
+            CellInputEx::new_from_outpoint(rpc, self.tx_hash, self.index, since, true).await?;
         skeleton.input(cell_input)?.witness(Default::default());
         Ok(())
     }
 }
 
 /// Operation that add input cell to transaction skeleton by user address
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddInputCellByAddress {
+    #[serde(with = "serde_ext::address")]
     pub address: Address,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddInputCellByAddress {
-    async fn run(
-        self: Box<Self>,
+impl AddInputCellByAddress {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -337,6 +354,8 @@ impl<T: RPC> Operation<T> for AddInputCellByAddress {
 }
 
 /// Operation that add input cell to transaction skeleton by type script
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddInputCellByType {
     pub type_script: ScriptEx,
     pub count: u32,
@@ -350,12 +369,9 @@ impl AddInputCellByType {
         query.with_data = Some(true);
         Ok(query.into())
     }
-}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddInputCellByType {
-    async fn run(
-        self: Box<Self>,
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -382,20 +398,22 @@ impl<T: RPC> Operation<T> for AddInputCellByType {
 /// # Parameters
 /// - `absolute_capacity` bool, wether mark the `capacity` as absolute value or additional
 /// - `type_id`: bool, if true, calculate type id and override into type script if provided
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize, Operation)]
+#[operation(params)]
+#[serde(default)]
 pub struct AddOutputCell {
     pub lock_script: ScriptEx,
     pub type_script: Option<ScriptEx>,
     pub capacity: u64,
+    #[serde(with = "serde_ext::hex_bytes")]
     pub data: Vec<u8>,
     pub absolute_capacity: bool,
     pub type_id: bool,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddOutputCell {
-    async fn run(
-        self: Box<Self>,
+impl AddOutputCell {
+    async fn execute<T: RPC>(
+        self,
         _: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -438,16 +456,20 @@ impl<T: RPC> Operation<T> for AddOutputCell {
 }
 
 /// Operation that add output cell to transaction skeleton by address
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddOutputCellByAddress {
+    #[serde(with = "serde_ext::address")]
     pub address: Address,
+    #[serde(default, with = "serde_ext::hex_bytes")]
     pub data: Vec<u8>,
+    #[serde(default)]
     pub add_type_id: bool,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddOutputCellByAddress {
-    async fn run(
-        self: Box<Self>,
+impl AddOutputCellByAddress {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
@@ -470,19 +492,25 @@ impl<T: RPC> Operation<T> for AddOutputCellByAddress {
 /// # Parameters
 /// - `input_index`: usize, the index of input cell in inputs, if it is usize::MAX, copy the last one
 /// - `adjust_capacity`: bool, if true, adjust the capacity if `data` provided
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize, Operation)]
+#[operation(params)]
+#[serde(default)]
 pub struct AddOutputCellByInputIndex {
     pub input_index: usize,
+    #[serde(with = "serde_ext::option_hex_bytes")]
     pub data: Option<Vec<u8>>,
     pub lock_script: Option<ScriptEx>,
+    #[serde(
+        with = "serde_ext::double_option",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub type_script: Option<Option<ScriptEx>>,
     pub adjust_capacity: bool,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddOutputCellByInputIndex {
-    async fn run(
-        self: Box<Self>,
+impl AddOutputCellByInputIndex {
+    async fn execute<T: RPC>(
+        self,
         _: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -517,17 +545,22 @@ impl<T: RPC> Operation<T> for AddOutputCellByInputIndex {
 /// Operation that add wintess in form of WitnessArgs to transaction skeleton
 ///
 /// `witness_index`: Option<usize>, the index of witness to update, if None, add a new witness
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddWitnessArgs {
+    #[serde(default)]
     pub witness_index: Option<usize>,
+    #[serde(default, with = "serde_ext::hex_bytes")]
     pub lock: Vec<u8>,
+    #[serde(default, with = "serde_ext::hex_bytes")]
     pub input_type: Vec<u8>,
+    #[serde(default, with = "serde_ext::hex_bytes")]
     pub output_type: Vec<u8>,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddWitnessArgs {
-    async fn run(
-        self: Box<Self>,
+impl AddWitnessArgs {
+    async fn execute<T: RPC>(
+        self,
         _: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -549,23 +582,27 @@ impl<T: RPC> Operation<T> for AddWitnessArgs {
 }
 
 /// Operation that sign and add secp256k1_sighash_all signatures to transaction skeleton
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddSecp256k1SighashSignatures {
     pub user_lock_scripts: Vec<ScriptEx>,
+    #[serde(with = "serde_ext::secret_keys")]
     pub user_private_keys: Vec<SecretKey>,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddSecp256k1SighashSignatures {
-    async fn run(
-        self: Box<Self>,
+impl AddSecp256k1SighashSignatures {
+    async fn execute<T: RPC>(
+        self,
         _: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
     ) -> Result<()> {
         let tx = skeleton.clone().into_transaction_view();
         let mut tx_groups_builder = TransactionWithScriptGroupsBuilder::default().set_tx_view(tx);
+        let mut signed_indices = vec![];
         for lock_script in self.user_lock_scripts {
             let (input_indices, _) = skeleton.lock_script_groups(&lock_script);
+            signed_indices.extend(input_indices.first().cloned());
             tx_groups_builder = tx_groups_builder
                 .add_lock_script_group(&lock_script.to_script(skeleton)?, &input_indices);
         }
@@ -577,8 +614,27 @@ impl<T: RPC> Operation<T> for AddSecp256k1SighashSignatures {
                 &SignContexts::new_sighash(self.user_private_keys),
             )
             .expect("sign");
-        let tx = tx_groups.get_tx_view();
-        skeleton.update_witnesses_from_transaction_view(tx)?;
+        // only take signatures back, so that the metadata of witnesses survives for exporting
+        let signed_witnesses = tx_groups.get_tx_view().witnesses();
+        // the signer pads empty witnesses up to the first input of each signed group
+        while skeleton.witnesses.len() < signed_witnesses.len() {
+            skeleton.witness(WitnessEx::default());
+        }
+        for index in signed_indices {
+            let signed = signed_witnesses
+                .get(index)
+                .ok_or(eyre!("signed witness not found"))?;
+            let witness_args = WitnessArgs::from_slice(&signed.raw_data())
+                .map_err(|_| eyre!("invalid signed witness args"))?;
+            let witness = &mut skeleton.witnesses[index];
+            witness.lock = witness_args
+                .lock()
+                .to_opt()
+                .unwrap_or_default()
+                .raw_data()
+                .to_vec();
+            witness.empty = false;
+        }
         Ok(())
     }
 }
@@ -602,16 +658,19 @@ pub struct ReprTxHelper {
 /// Operation that sign and add secp256k1_sighash_all signatures to transaction skeleton with ckb-cli
 ///
 /// note: this operation requires `ckb-cli` installed and available in PATH, refer to https://github.com/nervosnetwork/ckb-cli
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddSecp256k1SighashSignaturesWithCkbCli {
+    #[serde(with = "serde_ext::address")]
     pub signer_address: Address,
     pub cache_path: PathBuf,
+    #[serde(default)]
     pub keep_cache_file: bool,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddSecp256k1SighashSignaturesWithCkbCli {
-    async fn run(
-        self: Box<Self>,
+impl AddSecp256k1SighashSignaturesWithCkbCli {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -684,28 +743,246 @@ impl<T: RPC> Operation<T> for AddSecp256k1SighashSignaturesWithCkbCli {
     }
 }
 
+/// Operation that normalizes Witnesses, CellDeps and HeaderDeps of transaction skeleton, refer to `TransactionSkeleton::normalize`
+///
+/// note: this operation should be placed after all of cells are added and before signing
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
+pub struct NormalizeTransaction {}
+
+impl NormalizeTransaction {
+    async fn execute<T: RPC>(
+        self,
+        _: &T,
+        skeleton: &mut TransactionSkeleton,
+        _: &mut Log,
+    ) -> Result<()> {
+        skeleton.normalize();
+        Ok(())
+    }
+}
+
+/// Operation that validates transaction skeleton structurally and its since fields against the tip header, refer to
+/// `TransactionSkeleton::validate_with` and `TransactionSkeleton::validate_since`
+///
+/// note: this operation should be placed at the end of instruction, all of issues are reported in one error
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
+pub struct ValidateTransaction {
+    pub additional_fee_rate: u64,
+}
+
+impl ValidateTransaction {
+    async fn execute<T: RPC>(
+        self,
+        rpc: &T,
+        skeleton: &mut TransactionSkeleton,
+        _: &mut Log,
+    ) -> Result<()> {
+        let fee = skeleton.fee(rpc, self.additional_fee_rate).await?;
+        skeleton.validate_with(rpc, fee).await?;
+        skeleton.validate_since(rpc).await?;
+        Ok(())
+    }
+}
+
 /// Operation that balance transaction skeleton
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct BalanceTransaction {
     pub balancer: ScriptEx,
     pub change_receiver: ChangeReceiver,
+    #[serde(default)]
     pub additional_fee_rate: u64,
-}
+    /// Multisig configs in format of `ckb-cli` to estimate the unsigned multisig lock groups
+    #[serde(default)]
+    pub multisig_configs: HashMap<H160, ReprMultisigConfig>,
+    /// If set, charge fee on cycles as well by running scripts locally under this cycles limit
+    #[serde(default)]
+    pub max_cycles: Option<u64>,
+    #[serde(default)]
+    pub coin_selection: CoinSelection,
+    #[serde(default)]
+    pub change_policy: ChangePolicy,
+}
+
+impl BalanceTransaction {
+    /// Balance with the default coin selection and change policy, without multisig or cycles estimation
+    pub fn new(
+        balancer: ScriptEx,
+        change_receiver: ChangeReceiver,
+        additional_fee_rate: u64,
+    ) -> Self {
+        BalanceTransaction {
+            balancer,
+            change_receiver,
+            additional_fee_rate,
+            multisig_configs: Default::default(),
+            max_cycles: None,
+            coin_selection: Default::default(),
+            change_policy: Default::default(),
+        }
+    }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for BalanceTransaction {
-    async fn run(
-        self: Box<Self>,
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
     ) -> Result<()> {
-        let fee = skeleton.fee(rpc, self.additional_fee_rate).await?;
+        let estimator = fee_estimator(&self.multisig_configs, self.max_cycles)?;
+        let payer = Payer {
+            balancer: self.balancer,
+            change_receiver: self.change_receiver,
+            contribution: Contribution::Rest,
+            change_policy: self.change_policy,
+        };
         skeleton
-            .balance(rpc, fee, self.balancer, self.change_receiver)
+            .balance_under_fee_rate(
+                rpc,
+                self.additional_fee_rate,
+                &estimator,
+                &self.coin_selection,
+                &[payer],
+            )
             .await?;
-        (skeleton.witnesses.len()..skeleton.inputs.len()).for_each(|_| {
-            skeleton.witness(Default::default());
-        });
         Ok(())
     }
 }
+
+fn fee_estimator(
+    multisig_configs: &HashMap<H160, ReprMultisigConfig>,
+    max_cycles: Option<u64>,
+) -> Result<FeeEstimator> {
+    let estimator =
+        FeeEstimator::new().estimator(Secp256k1MultisigEstimator::from_ckb_cli(multisig_configs)?);
+    match max_cycles {
+        Some(max_cycles) => Ok(estimator.cycle_aware(TransactionSimulator::default(), max_cycles)),
+        None => Ok(estimator),
+    }
+}
+
+/// Operation that balance transaction skeleton by several payers, each one covers its own contribution
+///
+/// note: the contribution of each payer is emitted as `Event::PayerContribution` in order, and the lock scripts of
+/// payers can be passed to `AddSecp256k1SighashSignatures` directly
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
+pub struct BalanceTransactionByPayers {
+    pub payers: Vec<Payer>,
+    #[serde(default)]
+    pub additional_fee_rate: u64,
+    /// Multisig configs in format of `ckb-cli` to estimate the unsigned multisig lock groups
+    #[serde(default)]
+    pub multisig_configs: HashMap<H160, ReprMultisigConfig>,
+    /// If set, charge fee on cycles as well by running scripts locally under this cycles limit
+    #[serde(default)]
+    pub max_cycles: Option<u64>,
+    #[serde(default)]
+    pub coin_selection: CoinSelection,
+}
+
+impl BalanceTransactionByPayers {
+    async fn execute<T: RPC>(
+        self,
+        rpc: &T,
+        skeleton: &mut TransactionSkeleton,
+        log: &mut Log,
+    ) -> Result<()> {
+        let estimator = fee_estimator(&self.multisig_configs, self.max_cycles)?;
+        let contributions = skeleton
+            .balance_under_fee_rate(
+                rpc,
+                self.additional_fee_rate,
+                &estimator,
+                &self.coin_selection,
+                &self.payers,
+            )
+            .await?;
+        for contribution in contributions {
+            log.emit::<Self>(
+                skeleton,
+                Event::PayerContribution {
+                    lock_script: contribution.lock_script.clone(),
+                    contributed: contribution.contributed(),
+                    change: contribution.change,
+                },
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ckb_sdk::{constants::SIGHASH_TYPE_HASH, util::blake160};
+    use ckb_types::{
+        core::HeaderBuilder,
+        packed::{CellInput, OutPoint},
+    };
+    use futures::executor::block_on;
+    use secp256k1::{PublicKey, Secp256k1};
+
+    use super::*;
+    use crate::simulation::FakeRpcClient;
+
+    fn sighash_lock(key: &SecretKey) -> ScriptEx {
+        let pubkey = PublicKey::from_secret_key(&Secp256k1::new(), key);
+        ScriptEx::new_type(SIGHASH_TYPE_HASH, blake160(&pubkey.serialize()).0.to_vec())
+    }
+
+    fn input(tx: u8, lock_script: &ScriptEx) -> CellInputEx {
+        let out_point = OutPoint::new_builder().tx_hash([tx; 32].pack()).build();
+        let output = CellOutput::new_builder()
+            .lock(lock_script.clone().to_script_unchecked())
+            .build();
+        CellInputEx::new(
+            CellInput::new_builder().previous_output(out_point).build(),
+            output,
+            None,
+        )
+    }
+
+    #[test]
+    fn signing_keeps_witness_metadata() {
+        let keys = [
+            SecretKey::from_slice(&[1u8; 32]).unwrap(),
+            SecretKey::from_slice(&[2u8; 32]).unwrap(),
+        ];
+        let header = HeaderBuilder::default().build();
+        let mut skeleton = TransactionSkeleton::default();
+        skeleton
+            .input(input(1, &ScriptEx::default()))
+            .unwrap()
+            .input(input(2, &sighash_lock(&keys[0])))
+            .unwrap()
+            .input(input(3, &sighash_lock(&keys[1])))
+            .unwrap()
+            .headerdep(HeaderDepEx {
+                block_hash: header.hash().unpack(),
+                header,
+                cellinput_outpoint: None,
+            })
+            .witness(WitnessEx::new_plain(vec![9]))
+            .witness(WitnessEx::new_headerdep_reference(0));
+        let mut skeleton = TransactionSkeleton::from_json(&skeleton.to_json().unwrap()).unwrap();
+
+        let operation = Box::new(AddSecp256k1SighashSignatures {
+            user_lock_scripts: keys.iter().map(sighash_lock).collect(),
+            user_private_keys: keys.to_vec(),
+        });
+        let rpc = FakeRpcClient::default();
+        block_on(operation.run(&rpc, &mut skeleton, &mut Log::new())).unwrap();
+        let signed = TransactionSkeleton::from_json(&skeleton.to_json().unwrap()).unwrap();
+
+        let witnesses = &signed.witnesses;
+        assert_eq!(witnesses.len(), 3);
+        assert!(!witnesses[0].traditional);
+        assert_eq!(witnesses[0].lock, vec![9]);
+        assert_eq!(witnesses[1].headerdep_index(), Some(0));
+        assert_eq!(witnesses[1].lock.len(), 65);
+        assert!(witnesses[2].traditional && !witnesses[2].empty);
+        assert_eq!(witnesses[2].lock.len(), 65);
+        assert!(witnesses[2].input_type.is_empty());
+    }
+}
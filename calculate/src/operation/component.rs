@@ -1,4 +1,3 @@
-use async_trait::async_trait;
 use ckb_sdk::{
     rpc::ckb_indexer::{SearchKey, SearchMode},
     traits::CellQueryOptions,
@@ -11,10 +10,12 @@ use ckb_types::{
     H256,
 };
 use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     operation::{basic::AddOutputCell, Log, Operation},
     rpc::{GetCellsIter, Network, RPC},
+    serde_ext,
     skeleton::{CellInputEx, ScriptEx, TransactionSkeleton},
 };
 
@@ -51,6 +52,8 @@ pub mod hardcoded {
         h256!("0xff78bae0abf17d7a404c0be0f9ad9c9185b3f88dcc60403453d5ba8e1f22f53a");
 
     #[repr(u32)]
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
     pub enum Name {
         AlwaysSuccess = 0,
         InputTypeProxy,
@@ -108,14 +111,15 @@ pub mod hardcoded {
 ///
 /// # Parameters
 /// - `name`: component name in `ckb-proxy-locks`
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddComponentCelldep {
     pub name: hardcoded::Name,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddComponentCelldep {
-    async fn run(
-        self: Box<Self>,
+impl AddComponentCelldep {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
@@ -138,16 +142,19 @@ impl<T: RPC> Operation<T> for AddComponentCelldep {
 /// - `output_index`: reference output index, which is choosed to calculate type hash
 /// - `type_script`: optional type script
 /// - `data`: cell data
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddTypeBurnOutputCell {
     pub output_index: usize,
+    #[serde(default)]
     pub type_script: Option<ScriptEx>,
+    #[serde(default, with = "serde_ext::hex_bytes")]
     pub data: Vec<u8>,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddTypeBurnOutputCell {
-    async fn run(
-        self: Box<Self>,
+impl AddTypeBurnOutputCell {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
@@ -179,6 +186,8 @@ impl<T: RPC> Operation<T> for AddTypeBurnOutputCell {
 /// # Parameters
 /// - `type_hash`: the reference type script hash
 /// - `count`: max number of cells to add
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddTypeBurnInputCell {
     pub type_hash: H256,
     pub count: usize,
@@ -199,12 +208,9 @@ impl AddTypeBurnInputCell {
         query.with_data = Some(true);
         Ok(query.into())
     }
-}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddTypeBurnInputCell {
-    async fn run(
-        mut self: Box<Self>,
+    async fn execute<T: RPC>(
+        mut self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -224,14 +230,15 @@ impl<T: RPC> Operation<T> for AddTypeBurnInputCell {
 }
 
 /// Add `type-burn-lock` input cell by input index
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddTypeBurnInputCellByInputIndex {
     pub input_index: usize,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddTypeBurnInputCellByInputIndex {
-    async fn run(
-        self: Box<Self>,
+impl AddTypeBurnInputCellByInputIndex {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
@@ -257,17 +264,20 @@ impl<T: RPC> Operation<T> for AddTypeBurnInputCellByInputIndex {
 /// - `lock_script`: wether the script is used as lock script, otherwise type script
 /// - `type_script`: optional type script
 /// - `data`: cell data
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddLockProxyOutputCell {
     pub lock_hash: H256,
     pub lock_script: bool,
+    #[serde(default)]
     pub second_script: Option<ScriptEx>,
+    #[serde(default, with = "serde_ext::hex_bytes")]
     pub data: Vec<u8>,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddLockProxyOutputCell {
-    async fn run(
-        mut self: Box<Self>,
+impl AddLockProxyOutputCell {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
@@ -309,6 +319,8 @@ impl<T: RPC> Operation<T> for AddLockProxyOutputCell {
 /// - `lock_hash`: the proxied lock hash
 /// - `lock_script`: wether the script is used as lock script, otherwise type script
 /// - `count`: max number of cells to add
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddLockProxyInputCell {
     pub lock_hash: H256,
     pub lock_script: bool,
@@ -335,12 +347,9 @@ impl AddLockProxyInputCell {
         query.script_search_mode = Some(SearchMode::Exact);
         Ok(query.into())
     }
-}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddLockProxyInputCell {
-    async fn run(
-        mut self: Box<Self>,
+    async fn execute<T: RPC>(
+        mut self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
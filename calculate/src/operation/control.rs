@@ -0,0 +1,359 @@
+use async_trait::async_trait;
+use eyre::{eyre, Result};
+use serde_json::{json, Value};
+
+use crate::{
+    instruction::plan::describe,
+    operation::{Log, Operation},
+    rpc::RPC,
+    skeleton::TransactionSkeleton,
+};
+
+/// Predicate that made on the current transaction skeleton and log
+pub type Predicate = Box<dyn Fn(&TransactionSkeleton, &Log) -> bool + Send>;
+
+/// Factory to generate a fresh operation in each round, because operations are consumed after running
+pub type OperationFactory<T> = Box<dyn Fn(usize) -> Box<dyn Operation<T>> + Send>;
+
+/// Collector to gather items from the log of earlier operations
+pub type Collector<V> = Box<dyn Fn(&Log) -> Vec<V> + Send>;
+
+/// Run `then` operation if the predicate is true, otherwise run `otherwise` operation if provided
+///
+/// # Parameters
+/// - `predicate`: the condition decided by current skeleton and log
+/// - `then`: operation to run if the predicate is true
+/// - `otherwise`: operation to run if the predicate is false, do nothing if None
+pub struct If<T: RPC> {
+    pub predicate: Predicate,
+    pub then: Box<dyn Operation<T>>,
+    pub otherwise: Option<Box<dyn Operation<T>>>,
+}
+
+impl<T: RPC> If<T> {
+    pub fn new<F>(
+        predicate: F,
+        then: Box<dyn Operation<T>>,
+        otherwise: Option<Box<dyn Operation<T>>>,
+    ) -> Self
+    where
+        F: Fn(&TransactionSkeleton, &Log) -> bool + Send + 'static,
+    {
+        If {
+            predicate: Box::new(predicate),
+            then,
+            otherwise,
+        }
+    }
+}
+
+#[async_trait]
+impl<T: RPC> Operation<T> for If<T> {
+    fn params(&self) -> Value {
+        json!({
+            "then": describe(self.then.as_ref()),
+            "otherwise": self.otherwise.as_deref().map(describe),
+        })
+    }
+
+    async fn run(
+        self: Box<Self>,
+        rpc: &T,
+        skeleton: &mut TransactionSkeleton,
+        log: &mut Log,
+    ) -> Result<()> {
+        if (self.predicate)(skeleton, log) {
+            self.then.run(rpc, skeleton, log).await
+        } else if let Some(otherwise) = self.otherwise {
+            otherwise.run(rpc, skeleton, log).await
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Try candidate operations in sequence until the first one succeeds
+///
+/// note: the skeleton and log will be restored, and the cells reserved by the failed candidate will be released
+/// before trying the next candidate
+pub struct FirstSuccess<T: RPC> {
+    pub candidates: Vec<Box<dyn Operation<T>>>,
+}
+
+impl<T: RPC> FirstSuccess<T> {
+    pub fn new(candidates: Vec<Box<dyn Operation<T>>>) -> Self {
+        FirstSuccess { candidates }
+    }
+}
+
+#[async_trait]
+impl<T: RPC> Operation<T> for FirstSuccess<T> {
+    fn params(&self) -> Value {
+        let candidates = self
+            .candidates
+            .iter()
+            .map(|v| describe(v.as_ref()))
+            .collect::<Vec<_>>();
+        json!({ "candidates": candidates })
+    }
+
+    async fn run(
+        self: Box<Self>,
+        rpc: &T,
+        skeleton: &mut TransactionSkeleton,
+        log: &mut Log,
+    ) -> Result<()> {
+        let mut errors = vec![];
+        for candidate in self.candidates {
+            let (old_skeleton, old_log) = (skeleton.clone(), log.clone());
+            let reserved = skeleton.reservations.out_points();
+            match candidate.run(rpc, skeleton, log).await {
+                Ok(()) => return Ok(()),
+                Err(error) => {
+                    let newly_reserved = skeleton
+                        .reservations
+                        .out_points()
+                        .into_iter()
+                        .filter(|v| !reserved.contains(v));
+                    skeleton.reservations.release(newly_reserved);
+                    *skeleton = old_skeleton;
+                    *log = old_log;
+                    errors.push(error.to_string());
+                }
+            }
+        }
+        Err(eyre!("all candidates failed: [{}]", errors.join(", ")))
+    }
+}
+
+/// Repeat running the generated operation until the predicate is true
+///
+/// # Parameters
+/// - `factory`: generate the operation of each round, the round index is passed in
+/// - `until`: the predicate checked after each round
+/// - `max_rounds`: throw error if the predicate is still false after so many rounds
+pub struct RepeatUntil<T: RPC> {
+    pub factory: OperationFactory<T>,
+    pub until: Predicate,
+    pub max_rounds: usize,
+}
+
+impl<T: RPC> RepeatUntil<T> {
+    pub fn new<F, P>(factory: F, until: P, max_rounds: usize) -> Self
+    where
+        F: Fn(usize) -> Box<dyn Operation<T>> + Send + 'static,
+        P: Fn(&TransactionSkeleton, &Log) -> bool + Send + 'static,
+    {
+        RepeatUntil {
+            factory: Box::new(factory),
+            until: Box::new(until),
+            max_rounds,
+        }
+    }
+}
+
+#[async_trait]
+impl<T: RPC> Operation<T> for RepeatUntil<T> {
+    fn params(&self) -> Value {
+        json!({ "max_rounds": self.max_rounds })
+    }
+
+    async fn run(
+        self: Box<Self>,
+        rpc: &T,
+        skeleton: &mut TransactionSkeleton,
+        log: &mut Log,
+    ) -> Result<()> {
+        for round in 0..self.max_rounds {
+            (self.factory)(round).run(rpc, skeleton, log).await?;
+            if (self.until)(skeleton, log) {
+                return Ok(());
+            }
+        }
+        Err(eyre!(
+            "predicate unsatisfied after {} rounds",
+            self.max_rounds
+        ))
+    }
+}
+
+/// Run the generated operation for each item that collected from the log of earlier operations
+///
+/// # Parameters
+/// - `collect`: collect items from log, e.g. `Log::new_spore_ids`
+/// - `operation`: generate the operation for each item
+pub struct ForEach<T: RPC, V> {
+    pub collect: Collector<V>,
+    pub operation: Box<dyn Fn(V) -> Box<dyn Operation<T>> + Send>,
+}
+
+impl<T: RPC, V> ForEach<T, V> {
+    pub fn new<C, O>(collect: C, operation: O) -> Self
+    where
+        C: Fn(&Log) -> Vec<V> + Send + 'static,
+        O: Fn(V) -> Box<dyn Operation<T>> + Send + 'static,
+    {
+        ForEach {
+            collect: Box::new(collect),
+            operation: Box::new(operation),
+        }
+    }
+}
+
+#[async_trait]
+impl<T: RPC, V: Send> Operation<T> for ForEach<T, V> {
+    async fn run(
+        self: Box<Self>,
+        rpc: &T,
+        skeleton: &mut TransactionSkeleton,
+        log: &mut Log,
+    ) -> Result<()> {
+        for item in (self.collect)(log) {
+            (self.operation)(item).run(rpc, skeleton, log).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ckb_types::{packed::OutPoint, prelude::*};
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{locker::CellLocker, simulation::FakeRpcClient, skeleton::CellOutputEx};
+
+    /// Push an empty output and log its index, reserve a cell if `reserve` is set, then fail if `fail` is set
+    struct PushOutput {
+        index: u8,
+        reserve: Option<CellLocker>,
+        fail: bool,
+    }
+
+    impl PushOutput {
+        fn boxed(index: u8, fail: bool) -> Box<dyn Operation<FakeRpcClient>> {
+            Box::new(PushOutput {
+                index,
+                reserve: None,
+                fail,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Operation<FakeRpcClient> for PushOutput {
+        async fn run(
+            self: Box<Self>,
+            _: &FakeRpcClient,
+            skeleton: &mut TransactionSkeleton,
+            log: &mut Log,
+        ) -> Result<()> {
+            skeleton.output(CellOutputEx::new(Default::default(), vec![]));
+            log.emit_custom::<Self>(skeleton, "output", vec![self.index]);
+            if let Some(locker) = &self.reserve {
+                skeleton
+                    .reservations
+                    .try_lock(locker, out_point(self.index));
+            }
+            if self.fail {
+                return Err(eyre!("failed {}", self.index));
+            }
+            Ok(())
+        }
+    }
+
+    fn out_point(index: u8) -> OutPoint {
+        OutPoint::new_builder().index((index as u32).pack()).build()
+    }
+
+    fn run(operation: Box<dyn Operation<FakeRpcClient>>) -> (Result<()>, TransactionSkeleton, Log) {
+        let (mut skeleton, mut log) = (TransactionSkeleton::default(), Log::new());
+        let result = block_on(operation.run(&FakeRpcClient::default(), &mut skeleton, &mut log));
+        (result, skeleton, log)
+    }
+
+    fn logged_indices(log: &Log) -> Vec<u8> {
+        log.custom("output").into_iter().map(|v| v[0]).collect()
+    }
+
+    #[test]
+    fn if_picks_branch_by_predicate() {
+        let operation = If::new(
+            |skeleton, _| skeleton.outputs.is_empty(),
+            PushOutput::boxed(0, false),
+            Some(PushOutput::boxed(1, false)),
+        );
+        let (result, _, log) = run(Box::new(operation));
+        result.unwrap();
+        assert_eq!(logged_indices(&log), vec![0]);
+
+        let operation = If::new(|_, _| false, PushOutput::boxed(0, false), None);
+        let (result, skeleton, log) = run(Box::new(operation));
+        result.unwrap();
+        assert!(skeleton.outputs.is_empty());
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn first_success_restores_and_releases_failed_candidates() {
+        let locker = CellLocker::default();
+        let candidate = |index, fail| -> Box<dyn Operation<FakeRpcClient>> {
+            Box::new(PushOutput {
+                index,
+                reserve: Some(locker.clone()),
+                fail,
+            })
+        };
+        let operation = FirstSuccess::new(vec![candidate(0, true), candidate(1, false)]);
+        let (result, skeleton, log) = run(Box::new(operation));
+        result.unwrap();
+        assert_eq!(skeleton.outputs.len(), 1);
+        assert_eq!(logged_indices(&log), vec![1]);
+        assert!(!locker.is_locked(&out_point(0)));
+        assert_eq!(skeleton.reservations.out_points(), vec![out_point(1)]);
+
+        let operation = FirstSuccess::new(vec![candidate(2, true), candidate(3, true)]);
+        let (result, skeleton, log) = run(Box::new(operation));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "all candidates failed: [failed 2, failed 3]"
+        );
+        assert!(skeleton.outputs.is_empty());
+        assert!(log.is_empty());
+        assert!(!locker.is_locked(&out_point(2)));
+        assert!(!locker.is_locked(&out_point(3)));
+    }
+
+    #[test]
+    fn repeat_until_stops_on_predicate_or_max_rounds() {
+        let operation = RepeatUntil::new(
+            |round| PushOutput::boxed(round as u8, false),
+            |skeleton, _| skeleton.outputs.len() == 3,
+            5,
+        );
+        let (result, _, log) = run(Box::new(operation));
+        result.unwrap();
+        assert_eq!(logged_indices(&log), vec![0, 1, 2]);
+
+        let operation = RepeatUntil::new(
+            |round| PushOutput::boxed(round as u8, false),
+            |_, _| false,
+            2,
+        );
+        let (result, skeleton, _) = run(Box::new(operation));
+        assert!(result.is_err());
+        assert_eq!(skeleton.outputs.len(), 2);
+    }
+
+    #[test]
+    fn for_each_runs_over_collected_items() {
+        let operation = ForEach::new(
+            |_| vec![4u8, 5, 6],
+            |index| PushOutput::boxed(index, index == 5),
+        );
+        let (result, skeleton, log) = run(Box::new(operation));
+        assert_eq!(result.unwrap_err().to_string(), "failed 5");
+        assert_eq!(skeleton.outputs.len(), 2);
+        assert_eq!(logged_indices(&log), vec![4, 5]);
+    }
+}
@@ -1,21 +1,25 @@
-use async_trait::async_trait;
 use ckb_jsonrpc_types::JsonBytes;
 use ckb_sdk::{
     rpc::ckb_indexer::{SearchKey, SearchKeyFilter, SearchMode},
     traits::CellQueryOptions,
     util::{calculate_dao_maximum_withdraw4, minimal_unlock_point},
-    Since, SinceType,
 };
 use ckb_types::{
-    core::{Capacity, DepType},
-    h256, H256,
+    core::{Capacity, DepType, HeaderView},
+    h256,
+    prelude::Unpack,
+    H256,
 };
 use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    operation::{basic::AddCellDep, Log, Operation},
+    operation::{basic::AddCellDep, Event, Log, Operation},
     rpc::{GetCellsIter, Network, RPC},
-    skeleton::{CellInputEx, CellOutputEx, HeaderDepEx, ScriptEx, TransactionSkeleton, WitnessEx},
+    selection::DEFAULT_BATCH_SIZE,
+    skeleton::{
+        CellInputEx, CellOutputEx, HeaderDepEx, ScriptEx, SinceEx, TransactionSkeleton, WitnessEx,
+    },
 };
 
 pub mod hardcoded {
@@ -52,18 +56,18 @@ pub mod hardcoded {
     }
 }
 
-pub mod hookkey {
-    pub const DAO_WITHDRAW_PHASE_ONE: &str = "DAO_WITHDRAW_PHASE_ONE";
-    pub const DAO_WITHDRAW_PHASE_TWO: &str = "DAO_WITHDRAW_PHASE_TWO";
+fn u64_max() -> u64 {
+    u64::MAX
 }
 
 /// Add DAO celldep to the transaction
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddDaoCelldep {}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddDaoCelldep {
-    async fn run(
-        self: Box<Self>,
+impl AddDaoCelldep {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
@@ -85,18 +89,19 @@ impl<T: RPC> Operation<T> for AddDaoCelldep {
 /// # Parameters
 /// - `owner`: The owner of the DAO deposit cell
 /// - `deposit_capacity`: The total capacity to deposit
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params, celldep(AddDaoCelldep {}))]
 pub struct AddDaoDepositOutputCell {
     pub owner: ScriptEx,
     pub deposit_capacity: u64,
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddDaoDepositOutputCell {
-    async fn run(
-        self: Box<Self>,
+impl AddDaoDepositOutputCell {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
-        log: &mut Log,
+        _: &mut Log,
     ) -> Result<()> {
         let dao_type_script = hardcoded::dao_script(rpc.network());
         skeleton.output(CellOutputEx::new_from_scripts(
@@ -105,7 +110,7 @@ impl<T: RPC> Operation<T> for AddDaoDepositOutputCell {
             vec![0u8; 8],
             Some(Capacity::shannons(self.deposit_capacity)),
         )?);
-        Box::new(AddDaoCelldep {}).run(rpc, skeleton, log).await
+        Ok(())
     }
 }
 
@@ -117,11 +122,17 @@ impl<T: RPC> Operation<T> for AddDaoDepositOutputCell {
 /// - `owner`: The owner of the DAO deposit cell
 /// - `transfer_to`: The lock script of the withdraw cell, if not provided, use the same lock script in deposit cell
 /// - `throw_if_no_avaliable`: If true, throw an error if no available DAO deposit cells
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddDaoWithdrawPhaseOneCells {
+    #[serde(default = "u64_max")]
     pub maximal_withdraw_capacity: u64,
+    #[serde(default = "u64_max")]
     pub upperbound_timesamp: u64,
     pub owner: ScriptEx,
+    #[serde(default)]
     pub transfer_to: Option<ScriptEx>,
+    #[serde(default)]
     pub throw_if_no_avaliable: bool,
 }
 
@@ -140,26 +151,8 @@ impl AddDaoWithdrawPhaseOneCells {
         Ok(search_key)
     }
 
-    async fn check_deposit_timestamp<T: RPC>(
-        &self,
-        rpc: &T,
-        deposit_block_number: u64,
-    ) -> Result<bool> {
-        let Some(deposit_header) = rpc
-            .get_header_by_number(deposit_block_number.into())
-            .await?
-        else {
-            return Ok(false);
-        };
-        let deposit_timestamp: u64 = deposit_header.inner.timestamp.into();
-        Ok(deposit_timestamp <= self.upperbound_timesamp)
-    }
-}
-
-#[async_trait]
-impl<T: RPC> Operation<T> for AddDaoWithdrawPhaseOneCells {
-    async fn run(
-        self: Box<Self>,
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
@@ -171,40 +164,51 @@ impl<T: RPC> Operation<T> for AddDaoWithdrawPhaseOneCells {
         } else {
             None
         };
-        while let Some(cell) = search.next().await? {
-            let mature_deposit = self
-                .check_deposit_timestamp(rpc, cell.block_number.into())
-                .await?;
-            if !mature_deposit {
-                continue;
+        'search: while let Some(cells) = search.next_batch(DEFAULT_BATCH_SIZE).await? {
+            // the block that contains deposit cell is also the header dep, so fetch them in one batch request
+            let block_numbers = cells.iter().map(|v| v.block_number).collect();
+            let deposit_headers = rpc.get_headers_by_number(block_numbers).await?;
+            for (cell, deposit_header) in cells.into_iter().zip(deposit_headers) {
+                let Some(deposit_header) = deposit_header else {
+                    continue;
+                };
+                let deposit_header: HeaderView = deposit_header.into();
+                if deposit_header.timestamp() > self.upperbound_timesamp {
+                    continue;
+                }
+                let deposit_cell = CellInputEx::new_from_indexer_cell(cell, None);
+                let capacity = deposit_cell.output.capacity();
+                searched_capacity += capacity.as_u64();
+                if searched_capacity >= self.maximal_withdraw_capacity {
+                    break 'search;
+                }
+                let block_number = deposit_header.number();
+                let deposit_header_dep = HeaderDepEx {
+                    block_hash: deposit_header.hash().unpack(),
+                    header: deposit_header,
+                    cellinput_outpoint: Some(deposit_cell.input.previous_output()),
+                };
+                let withdraw_cell = CellOutputEx::new_from_scripts(
+                    transfer_lock_script
+                        .clone()
+                        .unwrap_or(deposit_cell.output.lock_script()),
+                    deposit_cell.output.type_script(),
+                    block_number.to_le_bytes().to_vec(),
+                    Some(capacity),
+                )?;
+                skeleton
+                    .input(deposit_cell)?
+                    .output(withdraw_cell)
+                    .headerdep(deposit_header_dep)
+                    .witness(Default::default());
             }
-            let deposit_cell = CellInputEx::new_from_indexer_cell(cell, None);
-            let capacity = deposit_cell.output.capacity();
-            searched_capacity += capacity.as_u64();
-            if searched_capacity >= self.maximal_withdraw_capacity {
-                break;
-            }
-            let deposit_header_dep =
-                HeaderDepEx::new_from_outpoint(rpc, deposit_cell.input.previous_output()).await?;
-            let block_number = deposit_header_dep.header.number();
-            let withdraw_cell = CellOutputEx::new_from_scripts(
-                transfer_lock_script
-                    .clone()
-                    .unwrap_or(deposit_cell.output.lock_script()),
-                deposit_cell.output.type_script(),
-                block_number.to_le_bytes().to_vec(),
-                Some(capacity),
-            )?;
-            skeleton
-                .input(deposit_cell)?
-                .output(withdraw_cell)
-                .headerdep(deposit_header_dep)
-                .witness(Default::default());
         }
-        log.push((
-            hookkey::DAO_WITHDRAW_PHASE_ONE,
-            searched_capacity.to_le_bytes().to_vec(),
-        ));
+        log.emit::<Self>(
+            skeleton,
+            Event::DaoWithdrawPhaseOne {
+                capacity: searched_capacity,
+            },
+        );
         if searched_capacity == 0 {
             if self.throw_if_no_avaliable {
                 return Err(eyre!("no available DAO deposit cells"));
@@ -222,10 +226,15 @@ impl<T: RPC> Operation<T> for AddDaoWithdrawPhaseOneCells {
 /// - `maximal_withdraw_capacity`: The maximal capacity to withdraw
 /// - `owner`: The owner of the DAO deposit cell
 /// - `transfer_to`: The lock script that receives all of capacities from searched withdraw cells, if None, use owner instead
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddDaoWithdrawPhaseTwoCells {
+    #[serde(default = "u64_max")]
     pub maximal_withdraw_capacity: u64,
     pub owner: ScriptEx,
+    #[serde(default)]
     pub transfer_to: Option<ScriptEx>,
+    #[serde(default)]
     pub throw_if_no_avaliable: bool,
 }
 
@@ -238,15 +247,10 @@ impl AddDaoWithdrawPhaseTwoCells {
         Ok(query.into())
     }
 
-    fn minimum_since(deposit_headerdep: &HeaderDepEx, withdraw_headerdep: &HeaderDepEx) -> u64 {
+    fn minimum_since(deposit_headerdep: &HeaderDepEx, withdraw_headerdep: &HeaderDepEx) -> SinceEx {
         let since_unlock =
             minimal_unlock_point(&deposit_headerdep.header, &withdraw_headerdep.header);
-        let since = Since::new(
-            SinceType::EpochNumberWithFraction,
-            since_unlock.full_value(),
-            false,
-        );
-        since.value()
+        SinceEx::epoch_with_fraction(since_unlock)
     }
 
     fn maximum_withdraw_capacity(
@@ -261,12 +265,9 @@ impl AddDaoWithdrawPhaseTwoCells {
             withdraw_cell.output.occupied_capacity().as_u64(),
         )
     }
-}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddDaoWithdrawPhaseTwoCells {
-    async fn run(
-        self: Box<Self>,
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
@@ -275,52 +276,71 @@ impl<T: RPC> Operation<T> for AddDaoWithdrawPhaseTwoCells {
         let mut search = GetCellsIter::new(rpc, self.search_key(rpc.network(), skeleton)?);
         let mut output_capacity = 0u64;
         let mut withdraw_headerdeps = vec![];
-        while let Some(cell) = search.next().await? {
-            let data = cell.output_data.as_ref().unwrap();
-            let deposit_block_number = u64::from_le_bytes(data.as_bytes().try_into().unwrap());
-            if deposit_block_number == 0 {
-                continue;
-            }
-            let deposit_headerdep =
-                HeaderDepEx::new_from_block_number(rpc, deposit_block_number).await?;
-            let withdraw_headerdep =
-                HeaderDepEx::new_from_outpoint(rpc, cell.out_point.clone().into()).await?;
-            let since = Self::minimum_since(&deposit_headerdep, &withdraw_headerdep);
-            let withdraw_cell = CellInputEx::new_from_indexer_cell(cell, Some(since));
-            searched_capacity += withdraw_cell.output.capacity().as_u64();
-            if searched_capacity >= self.maximal_withdraw_capacity {
-                break;
-            }
-            let headerdep_idx = skeleton
-                .headerdeps
+        'search: while let Some(cells) = search.next_batch(DEFAULT_BATCH_SIZE).await? {
+            let withdraw_cells = cells
+                .into_iter()
+                .filter_map(|cell| {
+                    let data = cell.output_data.as_ref().unwrap();
+                    let deposit_block_number =
+                        u64::from_le_bytes(data.as_bytes().try_into().unwrap());
+                    // deposit cells are marked with zero block number
+                    (deposit_block_number != 0).then_some((cell, deposit_block_number))
+                })
+                .collect::<Vec<_>>();
+            let deposit_block_numbers = withdraw_cells.iter().map(|(_, v)| *v).collect();
+            let deposit_headerdeps =
+                HeaderDepEx::new_batch_from_block_numbers(rpc, deposit_block_numbers).await?;
+            let withdraw_out_points = withdraw_cells
                 .iter()
-                .position(|v| v == &deposit_headerdep)
-                .unwrap_or(skeleton.headerdeps.len());
-            let witness_args = WitnessEx::new(vec![], headerdep_idx.to_le_bytes().to_vec(), vec![]);
-            output_capacity += Self::maximum_withdraw_capacity(
-                &deposit_headerdep,
-                &withdraw_headerdep,
-                &withdraw_cell,
-            );
-            skeleton
-                .input(withdraw_cell)?
-                .witness(witness_args)
-                .headerdep(deposit_headerdep);
-            if !withdraw_headerdeps.contains(&withdraw_headerdep) {
-                withdraw_headerdeps.push(withdraw_headerdep);
+                .map(|(cell, _)| cell.out_point.clone().into())
+                .collect();
+            let page_withdraw_headerdeps =
+                HeaderDepEx::new_batch_from_outpoints(rpc, withdraw_out_points).await?;
+            for (((cell, _), deposit_headerdep), withdraw_headerdep) in withdraw_cells
+                .into_iter()
+                .zip(deposit_headerdeps)
+                .zip(page_withdraw_headerdeps)
+            {
+                let since = Self::minimum_since(&deposit_headerdep, &withdraw_headerdep);
+                let withdraw_cell =
+                    CellInputEx::new_from_indexer_cell(cell, None).with_since(since)?;
+                searched_capacity += withdraw_cell.output.capacity().as_u64();
+                if searched_capacity >= self.maximal_withdraw_capacity {
+                    break 'search;
+                }
+                let headerdep_idx = skeleton
+                    .headerdeps
+                    .iter()
+                    .position(|v| v == &deposit_headerdep)
+                    .unwrap_or(skeleton.headerdeps.len());
+                let witness_args = WitnessEx::new_headerdep_reference(headerdep_idx);
+                output_capacity += Self::maximum_withdraw_capacity(
+                    &deposit_headerdep,
+                    &withdraw_headerdep,
+                    &withdraw_cell,
+                );
+                skeleton
+                    .input(withdraw_cell)?
+                    .witness(witness_args)
+                    .headerdep(deposit_headerdep);
+                if !withdraw_headerdeps.contains(&withdraw_headerdep) {
+                    withdraw_headerdeps.push(withdraw_headerdep);
+                }
             }
         }
-        log.push((
-            hookkey::DAO_WITHDRAW_PHASE_TWO,
-            output_capacity.to_le_bytes().to_vec(),
-        ));
+        log.emit::<Self>(
+            skeleton,
+            Event::DaoWithdrawPhaseTwo {
+                capacity: output_capacity,
+            },
+        );
         if output_capacity == 0 {
             if self.throw_if_no_avaliable {
                 return Err(eyre!("no available DAO withdraw cells"));
             }
             return Ok(());
         }
-        skeleton.headerdeps.extend(withdraw_headerdeps.into_iter());
+        skeleton.headerdeps.extend(withdraw_headerdeps);
         let transfer_lock_script = if let Some(transfer_to) = self.transfer_to {
             transfer_to.to_script(skeleton)?
         } else {
@@ -0,0 +1,258 @@
+use std::fmt::Display;
+
+use ckb_types::{packed::Script, prelude::Entity, H256};
+
+use crate::skeleton::TransactionSkeleton;
+
+/// Typed event that emitted from operations while assembling transaction skeleton
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The new generated spore unique id when creating new spore cell in Outputs field
+    NewSporeId(H256),
+    /// The new generated cluster unique id when creating new cluster cell in Outputs field
+    NewClusterId(H256),
+    /// The owner lock script of cluster cell that put in transaction's Inputs and Outputs field, which means it
+    /// should have matched signature in Witnesses
+    ClusterCellOwnerLock(Script),
+    /// The total capacity of deposit cells that marked as withdrawing in DAO phase one
+    DaoWithdrawPhaseOne { capacity: u64 },
+    /// The total capacity that withdrawn from DAO phase two, including the compensation
+    DaoWithdrawPhaseTwo { capacity: u64 },
+    /// The capacity that a payer contributed in balancing, and the change it received, in shannons
+    PayerContribution {
+        lock_script: Script,
+        contributed: u64,
+        change: u64,
+    },
+    /// Custom event for operations outside of this crate
+    Custom { key: String, data: Vec<u8> },
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::NewSporeId(id) => write!(f, "NEW_SPORE_ID -> {id:#x}"),
+            Event::NewClusterId(id) => write!(f, "NEW_CLUSTER_ID -> {id:#x}"),
+            Event::ClusterCellOwnerLock(script) => {
+                write!(
+                    f,
+                    "CLUSTER_CELL_OWNER_LOCK -> {}",
+                    hex::encode(script.as_slice())
+                )
+            }
+            Event::DaoWithdrawPhaseOne { capacity } => {
+                write!(f, "DAO_WITHDRAW_PHASE_ONE -> {capacity}")
+            }
+            Event::DaoWithdrawPhaseTwo { capacity } => {
+                write!(f, "DAO_WITHDRAW_PHASE_TWO -> {capacity}")
+            }
+            Event::PayerContribution {
+                lock_script,
+                contributed,
+                change,
+            } => write!(
+                f,
+                "PAYER_CONTRIBUTION -> {}: contributed {contributed}, change {change}",
+                hex::encode(lock_script.as_slice())
+            ),
+            Event::Custom { key, data } => write!(f, "{key} -> {}", hex::encode(data)),
+        }
+    }
+}
+
+/// Snapshot of the field sizes of transaction skeleton at the moment an event is emitted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SkeletonState {
+    pub inputs: usize,
+    pub outputs: usize,
+    pub celldeps: usize,
+    pub headerdeps: usize,
+    pub witnesses: usize,
+}
+
+impl From<&TransactionSkeleton> for SkeletonState {
+    fn from(skeleton: &TransactionSkeleton) -> Self {
+        SkeletonState {
+            inputs: skeleton.inputs.len(),
+            outputs: skeleton.outputs.len(),
+            celldeps: skeleton.celldeps.len(),
+            headerdeps: skeleton.headerdeps.len(),
+            witnesses: skeleton.witnesses.len(),
+        }
+    }
+}
+
+/// A single log record, which contains the event and where it comes from
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub operation: &'static str,
+    pub state: SkeletonState,
+    pub event: Event,
+}
+
+impl Display for LogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.operation, self.event)
+    }
+}
+
+/// Short type name of operation, e.g. `AddSporeOutputCell`
+pub fn operation_name<O: ?Sized>() -> &'static str {
+    let full_name = std::any::type_name::<O>();
+    let name = full_name.split('<').next().unwrap_or(full_name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// Typed event log that collected from operations in sequence
+#[derive(Debug, Clone, Default)]
+pub struct Log {
+    entries: Vec<LogEntry>,
+}
+
+impl Log {
+    pub fn new() -> Self {
+        Log::default()
+    }
+
+    /// Record an event emitted from operation `O` under current skeleton state
+    pub fn emit<O: ?Sized>(&mut self, skeleton: &TransactionSkeleton, event: Event) -> &mut Self {
+        self.entries.push(LogEntry {
+            operation: operation_name::<O>(),
+            state: skeleton.into(),
+            event,
+        });
+        self
+    }
+
+    /// Record a custom event emitted from operation `O`
+    pub fn emit_custom<O: ?Sized>(
+        &mut self,
+        skeleton: &TransactionSkeleton,
+        key: &str,
+        data: Vec<u8>,
+    ) -> &mut Self {
+        let event = Event::Custom {
+            key: key.to_string(),
+            data,
+        };
+        self.emit::<O>(skeleton, event)
+    }
+
+    pub fn entries(&self) -> &[LogEntry] {
+        &self.entries
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = &Event> {
+        self.entries.iter().map(|v| &v.event)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Filter log entries by the operation name, e.g. `AddSporeOutputCell`
+    pub fn by_operation<'a>(&'a self, operation: &'a str) -> impl Iterator<Item = &'a LogEntry> {
+        self.entries
+            .iter()
+            .filter(move |v| v.operation == operation)
+    }
+
+    /// All new generated spore ids in order
+    pub fn new_spore_ids(&self) -> Vec<H256> {
+        self.events()
+            .filter_map(|event| match event {
+                Event::NewSporeId(id) => Some(id.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// All new generated cluster ids in order
+    pub fn new_cluster_ids(&self) -> Vec<H256> {
+        self.events()
+            .filter_map(|event| match event {
+                Event::NewClusterId(id) => Some(id.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// All cluster owner lock scripts that require signatures, without duplication
+    pub fn cluster_owner_locks(&self) -> Vec<Script> {
+        let mut locks: Vec<Script> = vec![];
+        self.events().for_each(|event| {
+            if let Event::ClusterCellOwnerLock(script) = event {
+                if !locks.contains(script) {
+                    locks.push(script.clone());
+                }
+            }
+        });
+        locks
+    }
+
+    /// Total capacity of deposit cells marked in DAO withdraw phase one
+    pub fn dao_withdraw_phase_one_capacity(&self) -> u64 {
+        self.events()
+            .map(|event| match event {
+                Event::DaoWithdrawPhaseOne { capacity } => *capacity,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Total capacity withdrawn in DAO withdraw phase two
+    pub fn dao_withdraw_phase_two_capacity(&self) -> u64 {
+        self.events()
+            .map(|event| match event {
+                Event::DaoWithdrawPhaseTwo { capacity } => *capacity,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Contributions of payers in balancing, in form of `(lock_script, contributed, change)`
+    pub fn payer_contributions(&self) -> Vec<(Script, u64, u64)> {
+        self.events()
+            .filter_map(|event| match event {
+                Event::PayerContribution {
+                    lock_script,
+                    contributed,
+                    change,
+                } => Some((lock_script.clone(), *contributed, *change)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// All data of custom events under the key
+    pub fn custom(&self, key: &str) -> Vec<&[u8]> {
+        self.events()
+            .filter_map(|event| match event {
+                Event::Custom { key: k, data } if k == key => Some(data.as_slice()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl IntoIterator for Log {
+    type Item = LogEntry;
+    type IntoIter = std::vec::IntoIter<LogEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Log {
+    type Item = &'a LogEntry;
+    type IntoIter = std::slice::Iter<'a, LogEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
@@ -1,16 +1,39 @@
 pub mod basic;
 pub mod component;
+pub mod control;
 pub mod dao;
 pub mod spore;
-pub use common::{Log, Operation};
+pub use ckb_cinnabar_calculator_derive::Operation;
+pub use common::Operation;
+pub use log::{operation_name, Event, Log, LogEntry, SkeletonState};
 
-mod common {
-    use crate::{rpc::RPC, skeleton::TransactionSkeleton};
+mod log;
 
-    pub type Log = Vec<(&'static str, Vec<u8>)>;
+mod common {
+    use crate::{
+        operation::{operation_name, Log},
+        rpc::RPC,
+        skeleton::TransactionSkeleton,
+    };
 
+    /// Operation that assembles transaction skeleton step by step
+    ///
+    /// note: `Send` lets `Box<dyn Operation<T>>` be held across awaits, e.g. in combinators, which adds no burden
+    /// to implementors since `run` already moves `Box<Self>` into a `Send` future
     #[async_trait::async_trait]
-    pub trait Operation<T: RPC> {
+    pub trait Operation<T: RPC>: Send {
+        /// Short name of operation, which is used to locate operation in middlewares
+        fn name(&self) -> &'static str {
+            operation_name::<Self>()
+        }
+
+        /// Serializable summary of parameters, which is used to render plan before running
+        ///
+        /// note: secrets must be redacted, `Null` means no summary provided
+        fn params(&self) -> serde_json::Value {
+            serde_json::Value::Null
+        }
+
         async fn run(
             self: Box<Self>,
             rpc: &T,
@@ -19,3 +42,106 @@ mod common {
         ) -> eyre::Result<()>;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ckb_types::H256;
+    use eyre::Result;
+    use futures::executor::block_on;
+    use serde::Serialize;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        instruction::Instruction,
+        rpc::RPC,
+        simulation::FakeRpcClient,
+        skeleton::{CellOutputEx, TransactionSkeleton},
+    };
+
+    fn push_output(skeleton: &mut TransactionSkeleton, data: u8) {
+        skeleton.output(CellOutputEx::new(Default::default(), vec![data]));
+    }
+
+    /// Derived with the default `execute` method and no parameter summary
+    #[derive(Operation)]
+    struct MarkCelldep {}
+
+    impl MarkCelldep {
+        async fn execute<T: RPC>(
+            self,
+            _: &T,
+            skeleton: &mut TransactionSkeleton,
+            _: &mut Log,
+        ) -> Result<()> {
+            push_output(skeleton, 0xff);
+            Ok(())
+        }
+    }
+
+    #[derive(Serialize, Operation)]
+    #[operation(method = mint, log = Event::NewSporeId)]
+    #[operation(celldep(MarkCelldep {}), params)]
+    struct Mint {
+        id: u8,
+    }
+
+    impl Mint {
+        async fn mint<T: RPC>(
+            self,
+            _: &T,
+            skeleton: &mut TransactionSkeleton,
+            _: &mut Log,
+        ) -> Result<H256> {
+            push_output(skeleton, self.id);
+            Ok(H256([self.id; 32]))
+        }
+    }
+
+    #[derive(Operation)]
+    #[operation(params = summary)]
+    struct Secret {
+        key: u8,
+    }
+
+    impl Secret {
+        fn summary(&self) -> Value {
+            json!({ "key": "<hidden>" })
+        }
+
+        async fn execute<T: RPC>(
+            self,
+            _: &T,
+            skeleton: &mut TransactionSkeleton,
+            _: &mut Log,
+        ) -> Result<()> {
+            push_output(skeleton, self.key);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn derived_operations_run_in_instruction() {
+        let instruction = Instruction::<FakeRpcClient>::new(vec![
+            Box::new(Mint { id: 1 }),
+            Box::new(Secret { key: 2 }),
+        ]);
+        let plan = instruction.plan();
+        assert_eq!(plan.steps[0].name, "Mint");
+        assert_eq!(plan.steps[0].params, json!({ "id": 1 }));
+        assert_eq!(plan.steps[1].params, json!({ "key": "<hidden>" }));
+        let operation: &dyn Operation<FakeRpcClient> = &MarkCelldep {};
+        assert_eq!(operation.params(), Value::Null);
+
+        let (mut skeleton, mut log) = (TransactionSkeleton::default(), Log::new());
+        block_on(instruction.run(&FakeRpcClient::default(), &mut skeleton, &mut log)).unwrap();
+        let data = skeleton
+            .outputs
+            .iter()
+            .map(|v| v.data[0])
+            .collect::<Vec<_>>();
+        assert_eq!(data, vec![1, 0xff, 2]);
+        assert_eq!(log.new_spore_ids(), vec![H256([1; 32])]);
+        assert_eq!(log.len(), 1);
+    }
+}
@@ -1,4 +1,3 @@
-use async_trait::async_trait;
 use ckb_sdk::{
     rpc::ckb_indexer::{SearchKey, SearchMode},
     traits::CellQueryOptions,
@@ -10,10 +9,12 @@ use ckb_types::{
     H256,
 };
 use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    operation::{basic::AddOutputCell, Log, Operation},
+    operation::{basic::AddOutputCell, Event, Log, Operation},
     rpc::{GetCellsIter, Network, RPC},
+    serde_ext,
     skeleton::{CellDepEx, CellInputEx, CellOutputEx, ScriptEx, TransactionSkeleton, WitnessEx},
 };
 
@@ -88,23 +89,14 @@ pub mod hardcoded {
     }
 }
 
-pub mod hookkey {
-    /// The owner lock script of cluster cell that put in transaction's Inputs and Outputs field, which means it
-    /// should have matched signature in Witnesses
-    pub const CLUSTER_CELL_OWNER_LOCK: &str = "CLUSTER_CELL_OWNER_LOCK";
-    /// The new generated cluster unique id when creating new cluster cell in Outputs field
-    pub const NEW_CLUSTER_ID: &str = "NEW_CLUSTER_ID";
-    /// The new generated spore unique id when creating new spore cell in Outputs field
-    pub const NEW_SPORE_ID: &str = "NEW_SPORE_ID";
-}
-
 /// Add the lastest Spore deployment cell into transaction skeleton according to the network type.
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddSporeCelldep {}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddSporeCelldep {
-    async fn run(
-        self: Box<Self>,
+impl AddSporeCelldep {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
@@ -122,12 +114,13 @@ impl<T: RPC> Operation<T> for AddSporeCelldep {
 }
 
 /// Add the lastest Cluster deployment cell into transaction skeleton according to the network type.
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddClusterCelldep {}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddClusterCelldep {
-    async fn run(
-        self: Box<Self>,
+impl AddClusterCelldep {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
@@ -144,7 +137,8 @@ impl<T: RPC> Operation<T> for AddClusterCelldep {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ClusterAuthorityMode {
     LockProxy,
     ClusterCell,
@@ -156,6 +150,8 @@ pub enum ClusterAuthorityMode {
 /// # Parameters
 /// - `cluster_id`: The unique identifier of the cluster cell
 /// - `authority_mode`: Indicate how to provide cluster authority while operating Spore
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddClusterCelldepByClusterId {
     pub cluster_id: H256,
     pub authority_mode: ClusterAuthorityMode,
@@ -169,12 +165,9 @@ impl AddClusterCelldepByClusterId {
         query.script_search_mode = Some(SearchMode::Exact);
         Ok(query.into())
     }
-}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddClusterCelldepByClusterId {
-    async fn run(
-        self: Box<Self>,
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
@@ -196,14 +189,12 @@ impl<T: RPC> Operation<T> for AddClusterCelldepByClusterId {
         let (inputs, outputs) = skeleton.lock_script_groups(&cluster_owner_lock_script);
         // ignore the case of only one legit cell in Inputs or Outputs
         if inputs.is_empty() || outputs.is_empty() {
-            log.push((
-                hookkey::CLUSTER_CELL_OWNER_LOCK,
-                cluster_owner_lock_script
-                    .clone()
-                    .to_script_unchecked()
-                    .as_slice()
-                    .to_vec(),
-            ));
+            log.emit::<Self>(
+                skeleton,
+                Event::ClusterCellOwnerLock(
+                    cluster_owner_lock_script.clone().to_script_unchecked(),
+                ),
+            );
             match self.authority_mode {
                 ClusterAuthorityMode::LockProxy => {
                     skeleton
@@ -236,6 +227,8 @@ impl<T: RPC> Operation<T> for AddClusterCelldepByClusterId {
 /// - `lock_script`: The spore owner lock script
 /// - `cluster_id`: The unique identifier of the cluster cell
 /// - `count`: The number of spore cells to search and add
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params, celldep(AddSporeCelldep {}))]
 pub struct AddSporeInputCellByClusterId {
     pub lock_script: ScriptEx,
     pub cluster_id: H256,
@@ -251,15 +244,12 @@ impl AddSporeInputCellByClusterId {
         query.script_search_mode = Some(SearchMode::Prefix);
         Ok(query.into())
     }
-}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddSporeInputCellByClusterId {
-    async fn run(
-        self: Box<Self>,
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
-        log: &mut Log,
+        _: &mut Log,
     ) -> Result<()> {
         let search_key = self.search_key(rpc, skeleton)?;
         let mut searched = 0usize;
@@ -279,7 +269,7 @@ impl<T: RPC> Operation<T> for AddSporeInputCellByClusterId {
                 break;
             }
         }
-        Box::new(AddSporeCelldep {}).run(rpc, skeleton, log).await
+        Ok(())
     }
 }
 
@@ -288,8 +278,11 @@ impl<T: RPC> Operation<T> for AddSporeInputCellByClusterId {
 /// # Parameters
 /// - `spore_id`: The unique identifier of the spore cell
 /// - `check_owner`: The owner lock script to check if the spore cell is owned by the passed owner
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params, celldep(AddSporeCelldep {}))]
 pub struct AddSporeInputCellBySporeId {
     pub spore_id: H256,
+    #[serde(default)]
     pub check_owner: Option<ScriptEx>,
 }
 
@@ -302,15 +295,12 @@ impl AddSporeInputCellBySporeId {
         query.script_search_mode = Some(SearchMode::Exact);
         Ok(query.into())
     }
-}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddSporeInputCellBySporeId {
-    async fn run(
-        self: Box<Self>,
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
-        log: &mut Log,
+        _: &mut Log,
     ) -> Result<()> {
         let search_key = self.search_key(rpc, skeleton)?;
         let Some(indexer_cell) = GetCellsIter::new(rpc, search_key).next().await? else {
@@ -326,7 +316,7 @@ impl<T: RPC> Operation<T> for AddSporeInputCellBySporeId {
             }
         }
         skeleton.input(spore_cell)?.witness(Default::default());
-        Box::new(AddSporeCelldep {}).run(rpc, skeleton, log).await
+        Ok(())
     }
 }
 
@@ -338,10 +328,14 @@ impl<T: RPC> Operation<T> for AddSporeInputCellBySporeId {
 /// - `content`: The concrete content in bytes
 /// - `cluster_id`: The unique identifier of the cluster cell to create from
 /// - `authority_mode`: The cluster authority mode
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params, log = Event::NewSporeId, celldep(AddSporeCelldep {}))]
 pub struct AddSporeOutputCell {
     pub lock_script: ScriptEx,
     pub content_type: String,
+    #[serde(with = "serde_ext::hex_bytes")]
     pub content: Vec<u8>,
+    #[serde(default)]
     pub cluster_id: Option<H256>,
     pub authority_mode: ClusterAuthorityMode,
 }
@@ -355,14 +349,13 @@ pub fn make_spore_data(content_type: &str, content: &[u8], cluster_id: Option<&H
     molecule_spore_data.as_bytes().to_vec()
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddSporeOutputCell {
-    async fn run(
-        self: Box<Self>,
+impl AddSporeOutputCell {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
-    ) -> Result<()> {
+    ) -> Result<H256> {
         if let Some(cluster_id) = self.cluster_id.clone() {
             Box::new(AddClusterCelldepByClusterId {
                 cluster_id,
@@ -384,9 +377,7 @@ impl<T: RPC> Operation<T> for AddSporeOutputCell {
         })
         .run(rpc, skeleton, log)
         .await?;
-        let spore_id = skeleton.calc_type_id(skeleton.outputs.len() - 1)?;
-        log.push((hookkey::NEW_SPORE_ID, spore_id.as_bytes().to_vec()));
-        Box::new(AddSporeCelldep {}).run(rpc, skeleton, log).await
+        skeleton.calc_type_id(skeleton.outputs.len() - 1)
     }
 }
 
@@ -394,6 +385,8 @@ impl<T: RPC> Operation<T> for AddSporeOutputCell {
 ///
 /// # Parameters
 /// - `input_index`: The index of input cell in transaction skeleton
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params, celldep(AddClusterCelldep {}))]
 pub struct AddClusterInputCellByClusterId {
     pub cluster_id: H256,
 }
@@ -407,15 +400,12 @@ impl AddClusterInputCellByClusterId {
         query.script_search_mode = Some(SearchMode::Exact);
         Ok(query.into())
     }
-}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddClusterInputCellByClusterId {
-    async fn run(
-        self: Box<Self>,
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
-        log: &mut Log,
+        _: &mut Log,
     ) -> Result<()> {
         let search_key = self.search_key(rpc, skeleton)?;
         let Some(indexer_cell) = GetCellsIter::new(rpc, search_key).next().await? else {
@@ -423,7 +413,7 @@ impl<T: RPC> Operation<T> for AddClusterInputCellByClusterId {
         };
         let cluster_cell = CellInputEx::new_from_indexer_cell(indexer_cell, None);
         skeleton.input(cluster_cell)?.witness(Default::default());
-        Box::new(AddClusterCelldep {}).run(rpc, skeleton, log).await
+        Ok(())
     }
 }
 
@@ -434,9 +424,12 @@ impl<T: RPC> Operation<T> for AddClusterInputCellByClusterId {
 /// - `name`: The name of the cluster
 /// - `description`: The description of the cluster
 /// - `cluster_id_collector`: The callback function to collect the generated cluster id
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params, log = Event::NewClusterId, celldep(AddClusterCelldep {}))]
 pub struct AddClusterOutputCell {
     pub lock_script: ScriptEx,
     pub name: String,
+    #[serde(default, with = "serde_ext::hex_bytes")]
     pub description: Vec<u8>,
 }
 
@@ -448,14 +441,13 @@ pub fn make_cluster_data(name: &str, description: &[u8]) -> Vec<u8> {
     molecule_cluster_data.as_bytes().to_vec()
 }
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddClusterOutputCell {
-    async fn run(
-        self: Box<Self>,
+impl AddClusterOutputCell {
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         log: &mut Log,
-    ) -> Result<()> {
+    ) -> Result<H256> {
         let cluster_data = make_cluster_data(&self.name, &self.description);
         let cluster_type_script = hardcoded::cluster_script(rpc.network(), vec![]); // later on, args will be filled with type_id
         Box::new(AddOutputCell {
@@ -468,15 +460,15 @@ impl<T: RPC> Operation<T> for AddClusterOutputCell {
         })
         .run(rpc, skeleton, log)
         .await?;
-        let cluster_id = skeleton.calc_type_id(skeleton.outputs.len() - 1)?;
-        log.push((hookkey::NEW_CLUSTER_ID, cluster_id.as_bytes().to_vec()));
-        Box::new(AddClusterCelldep {}).run(rpc, skeleton, log).await
+        skeleton.calc_type_id(skeleton.outputs.len() - 1)
     }
 }
 
 /// Search spore related cells from transaction skeleton and parse the operations' intention to spore actions
 ///
 /// note: this is essential for a historical issue of co-build project
+#[derive(Serialize, Deserialize, Operation)]
+#[operation(params)]
 pub struct AddSporeActions {}
 
 impl AddSporeActions {
@@ -490,12 +482,9 @@ impl AddSporeActions {
         }
         None
     }
-}
 
-#[async_trait]
-impl<T: RPC> Operation<T> for AddSporeActions {
-    async fn run(
-        self: Box<Self>,
+    async fn execute<T: RPC>(
+        self,
         rpc: &T,
         skeleton: &mut TransactionSkeleton,
         _: &mut Log,
@@ -0,0 +1,620 @@
+use std::sync::{Arc, RwLock};
+
+use ckb_hash::blake2b_256;
+use ckb_jsonrpc_types::{
+    BlockNumber, BlockView, CellData, CellInfo, CellWithStatus, HeaderView, JsonBytes, OutPoint,
+    OutputsValidator, Status, Transaction, TransactionWithStatusResponse, TxPoolInfo,
+};
+use ckb_sdk::rpc::ckb_indexer::{Cell, Pagination, ScriptType, SearchKey, SearchMode};
+use ckb_types::{
+    core::TransactionView,
+    packed,
+    prelude::{Entity, IntoTransactionView, Unpack},
+    H256,
+};
+use eyre::Result;
+
+use crate::{
+    locker::CellLocker,
+    rpc::{Network, Rpc, RpcError, RPC},
+    skeleton::{CellOutputEx, TransactionSkeleton},
+};
+
+/// Transaction that sent or going to be sent, but not committed yet
+#[derive(Clone)]
+struct PendingTransaction {
+    hash: H256,
+    inputs: Vec<OutPoint>,
+    outputs: Vec<(OutPoint, CellOutputEx)>,
+}
+
+#[derive(Default)]
+struct PendingPool {
+    transactions: Vec<PendingTransaction>,
+}
+
+impl PendingPool {
+    fn is_spent(&self, out_point: &OutPoint) -> bool {
+        self.transactions
+            .iter()
+            .any(|tx| tx.inputs.contains(out_point))
+    }
+
+    fn is_pending_output(&self, out_point: &OutPoint) -> bool {
+        self.transactions
+            .iter()
+            .any(|tx| tx.outputs.iter().any(|(v, _)| v == out_point))
+    }
+
+    /// Outputs of pending transactions that not spent by other pending ones, in order of sending
+    fn live_outputs(&self) -> Vec<(OutPoint, CellOutputEx)> {
+        self.transactions
+            .iter()
+            .flat_map(|tx| tx.outputs.iter())
+            .filter(|(out_point, _)| !self.is_spent(out_point))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Cursor of overlay `get_cells`, pending outputs are searched ahead of the ones in indexer
+enum OverlayCursor {
+    Pending(usize),
+    Indexer(Option<JsonBytes>),
+}
+
+impl OverlayCursor {
+    fn decode(cursor: Option<JsonBytes>) -> Result<Self, RpcError> {
+        let Some(cursor) = cursor else {
+            return Ok(OverlayCursor::Pending(0));
+        };
+        let bytes = cursor.into_bytes();
+        match bytes.first() {
+            Some(0) => {
+                let offset = bytes[1..]
+                    .try_into()
+                    .map_err(|_| RpcError::Other("invalid overlay cursor".to_string()))?;
+                Ok(OverlayCursor::Pending(u64::from_le_bytes(offset) as usize))
+            }
+            Some(1) if bytes.len() == 1 => Ok(OverlayCursor::Indexer(None)),
+            Some(1) => Ok(OverlayCursor::Indexer(Some(JsonBytes::from_bytes(
+                bytes.slice(1..),
+            )))),
+            _ => Err(RpcError::Other("invalid overlay cursor".to_string())),
+        }
+    }
+
+    fn encode(&self) -> JsonBytes {
+        let mut bytes = vec![];
+        match self {
+            OverlayCursor::Pending(offset) => {
+                bytes.push(0);
+                bytes.extend((*offset as u64).to_le_bytes());
+            }
+            OverlayCursor::Indexer(cursor) => {
+                bytes.push(1);
+                if let Some(cursor) = cursor {
+                    bytes.extend(cursor.as_bytes());
+                }
+            }
+        }
+        JsonBytes::from_vec(bytes)
+    }
+}
+
+/// RPC that layers outputs of pending transactions over the wrapped one, which enables building transaction on top
+/// of the unconfirmed ones, e.g. withdraw DAO right after depositing
+///
+/// Outputs of pending transactions are visible in `get_live_cell` and `get_cells`, and their inputs are hidden
+///
+/// note: transactions sent through this RPC are recorded automatically, and the committed or rejected ones are
+/// forgotten once observed by `get_transaction` or `get_transactions`, e.g. while waiting for confirmation
+#[derive(Clone)]
+pub struct OverlayRpc<T: RPC> {
+    inner: T,
+    pool: Arc<RwLock<PendingPool>>,
+}
+
+impl<T: RPC> OverlayRpc<T> {
+    pub fn new(inner: T) -> Self {
+        OverlayRpc {
+            inner,
+            pool: Default::default(),
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Record a pending transaction, the one with the same hash or conflicting inputs is replaced
+    pub fn push_transaction(&self, tx: &TransactionView) -> H256 {
+        let hash: H256 = tx.hash().unpack();
+        let inputs = tx
+            .input_pts_iter()
+            .map(Into::into)
+            .collect::<Vec<OutPoint>>();
+        let outputs = tx
+            .outputs_with_data_iter()
+            .enumerate()
+            .map(|(index, (output, data))| {
+                let out_point = packed::OutPoint::new(tx.hash(), index as u32);
+                (out_point.into(), CellOutputEx::new(output, data.to_vec()))
+            })
+            .collect();
+        let mut pool = self.pool.write().expect("poisoned overlay pool");
+        // the replaced ones, e.g. by fee bumping, are dropped as well
+        pool.transactions
+            .retain(|tx| tx.hash != hash && !tx.inputs.iter().any(|v| inputs.contains(v)));
+        pool.transactions.push(PendingTransaction {
+            hash: hash.clone(),
+            inputs,
+            outputs,
+        });
+        hash
+    }
+
+    /// Record a skeleton as pending transaction, which can be unsigned since witnesses don't affect the tx hash
+    pub fn push_skeleton(&self, skeleton: &TransactionSkeleton) -> H256 {
+        self.push_transaction(&skeleton.clone().into_transaction_view())
+    }
+
+    /// Drop a pending transaction, e.g. it's discarded without sending
+    pub fn forget(&self, hash: &H256) {
+        self.pool
+            .write()
+            .expect("poisoned overlay pool")
+            .transactions
+            .retain(|tx| &tx.hash != hash);
+    }
+
+    pub fn clear(&self) {
+        self.pool
+            .write()
+            .expect("poisoned overlay pool")
+            .transactions
+            .clear();
+    }
+
+    /// Hashes of pending transactions in order of recording
+    pub fn pending_transactions(&self) -> Vec<H256> {
+        self.pool
+            .read()
+            .expect("poisoned overlay pool")
+            .transactions
+            .iter()
+            .map(|tx| tx.hash.clone())
+            .collect()
+    }
+
+    fn pending_cells(
+        &self,
+        search_key: &SearchKey,
+        limit: usize,
+        offset: usize,
+    ) -> (Vec<Cell>, usize) {
+        let outputs = self
+            .pool
+            .read()
+            .expect("poisoned overlay pool")
+            .live_outputs();
+        let mut objects = vec![];
+        let mut offset = offset;
+        for (out_point, cell) in outputs.iter().skip(offset) {
+            if objects.len() >= limit {
+                break;
+            }
+            offset += 1;
+            if search_key_matches(search_key, cell) {
+                objects.push(indexer_cell(search_key, out_point, cell));
+            }
+        }
+        (objects, offset)
+    }
+}
+
+fn indexer_cell(search_key: &SearchKey, out_point: &OutPoint, cell: &CellOutputEx) -> Cell {
+    let output_data = search_key
+        .with_data
+        .unwrap_or(true)
+        .then(|| JsonBytes::from_vec(cell.data.clone()));
+    Cell {
+        output: cell.output.clone().into(),
+        output_data,
+        out_point: out_point.clone(),
+        block_number: 0.into(),
+        tx_index: 0.into(),
+    }
+}
+
+fn bytes_match(mode: Option<&SearchMode>, value: &[u8], pattern: &[u8]) -> bool {
+    match mode {
+        Some(SearchMode::Exact) => value == pattern,
+        Some(SearchMode::Partial) => {
+            pattern.is_empty() || value.windows(pattern.len()).any(|v| v == pattern)
+        }
+        Some(SearchMode::Prefix) | None => value.starts_with(pattern),
+    }
+}
+
+fn script_match(
+    mode: Option<&SearchMode>,
+    script: Option<&packed::Script>,
+    pattern: &packed::Script,
+) -> bool {
+    let Some(script) = script else {
+        return false;
+    };
+    script.code_hash() == pattern.code_hash()
+        && script.hash_type() == pattern.hash_type()
+        && bytes_match(mode, &script.args().raw_data(), &pattern.args().raw_data())
+}
+
+fn in_range(value: u64, range: &[ckb_jsonrpc_types::Uint64; 2]) -> bool {
+    value >= range[0].value() && value < range[1].value()
+}
+
+/// Check if the pending output matches the search key in the same way of indexer
+fn search_key_matches(search_key: &SearchKey, cell: &CellOutputEx) -> bool {
+    let (primary, secondary) = match search_key.script_type {
+        ScriptType::Lock => (Some(cell.lock_script()), cell.type_script()),
+        ScriptType::Type => (cell.type_script(), Some(cell.lock_script())),
+    };
+    let script: packed::Script = search_key.script.clone().into();
+    let mode = search_key
+        .script_search_mode
+        .as_ref()
+        .or(Some(&SearchMode::Prefix));
+    if !script_match(mode, primary.as_ref(), &script) {
+        return false;
+    }
+    let Some(filter) = &search_key.filter else {
+        return true;
+    };
+    if let Some(script) = &filter.script {
+        if !script_match(
+            Some(&SearchMode::Prefix),
+            secondary.as_ref(),
+            &script.clone().into(),
+        ) {
+            return false;
+        }
+    }
+    if let Some(range) = &filter.script_len_range {
+        let len = secondary.map(|v| v.as_slice().len()).unwrap_or_default();
+        if !in_range(len as u64, range) {
+            return false;
+        }
+    }
+    if let Some(data) = &filter.output_data {
+        let mode = filter.output_data_filter_mode.as_ref();
+        if !bytes_match(mode, &cell.data, data.as_bytes()) {
+            return false;
+        }
+    }
+    if let Some(range) = &filter.output_data_len_range {
+        if !in_range(cell.data.len() as u64, range) {
+            return false;
+        }
+    }
+    if let Some(range) = &filter.output_capacity_range {
+        if !in_range(cell.capacity().as_u64(), range) {
+            return false;
+        }
+    }
+    // pending outputs are not in any block yet
+    filter.block_range.is_none()
+}
+
+/// Committed or rejected transaction is no longer pending, since the inner rpc knows its effects
+fn is_settled(tx: &TransactionWithStatusResponse) -> bool {
+    matches!(tx.tx_status.status, Status::Committed | Status::Rejected)
+}
+
+/// Status of the cell that spent or created by pending transactions, None if it's up to inner rpc
+fn pending_cell(
+    pool: &PendingPool,
+    out_point: &OutPoint,
+    with_data: bool,
+) -> Option<CellWithStatus> {
+    if pool.is_spent(out_point) {
+        return Some(CellWithStatus {
+            cell: None,
+            status: "dead".to_owned(),
+        });
+    }
+    let (_, cell) = pool
+        .live_outputs()
+        .into_iter()
+        .find(|(v, _)| v == out_point)?;
+    let data = with_data.then(|| CellData {
+        hash: blake2b_256(&cell.data).into(),
+        content: JsonBytes::from_vec(cell.data.clone()),
+    });
+    Some(CellWithStatus {
+        cell: Some(CellInfo {
+            output: cell.output.into(),
+            data,
+        }),
+        status: "live".to_owned(),
+    })
+}
+
+impl<T: RPC + 'static> RPC for OverlayRpc<T> {
+    fn network(&self) -> Network {
+        self.inner.network()
+    }
+
+    fn url(&self) -> (String, String) {
+        self.inner.url()
+    }
+
+    fn cell_locker(&self) -> Option<&CellLocker> {
+        self.inner.cell_locker()
+    }
+
+    fn get_live_cell(&self, out_point: &OutPoint, with_data: bool) -> Rpc<CellWithStatus> {
+        let pool = self.pool.read().expect("poisoned overlay pool");
+        if let Some(cell) = pending_cell(&pool, out_point, with_data) {
+            return Box::pin(async move { Ok(cell) });
+        }
+        self.inner.get_live_cell(out_point, with_data)
+    }
+
+    fn get_cells(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Rpc<Pagination<Cell>> {
+        let overlay = self.clone();
+        Box::pin(async move {
+            let mut cursor = OverlayCursor::decode(cursor)?;
+            if let OverlayCursor::Pending(offset) = cursor {
+                let (objects, offset) = overlay.pending_cells(&search_key, limit as usize, offset);
+                if !objects.is_empty() {
+                    return Ok(Pagination {
+                        objects,
+                        last_cursor: OverlayCursor::Pending(offset).encode(),
+                    });
+                }
+                cursor = OverlayCursor::Indexer(None);
+            }
+            let OverlayCursor::Indexer(mut indexer_cursor) = cursor else {
+                unreachable!("pending cursor is exhausted");
+            };
+            // skip the cells consumed by pending transactions, or the committed ones that still recorded
+            loop {
+                let cells = overlay
+                    .inner
+                    .get_cells(search_key.clone(), limit, indexer_cursor)
+                    .await?;
+                if cells.objects.is_empty() {
+                    return Ok(Pagination {
+                        objects: vec![],
+                        last_cursor: OverlayCursor::Indexer(Some(cells.last_cursor)).encode(),
+                    });
+                }
+                indexer_cursor = Some(cells.last_cursor);
+                let objects = {
+                    let pool = overlay.pool.read().expect("poisoned overlay pool");
+                    cells
+                        .objects
+                        .into_iter()
+                        .filter(|cell| {
+                            !pool.is_spent(&cell.out_point)
+                                && !pool.is_pending_output(&cell.out_point)
+                        })
+                        .collect::<Vec<_>>()
+                };
+                if !objects.is_empty() {
+                    return Ok(Pagination {
+                        objects,
+                        last_cursor: OverlayCursor::Indexer(indexer_cursor).encode(),
+                    });
+                }
+            }
+        })
+    }
+
+    fn get_block_by_number(&self, number: BlockNumber) -> Rpc<Option<BlockView>> {
+        self.inner.get_block_by_number(number)
+    }
+
+    fn get_block(&self, hash: &H256) -> Rpc<Option<BlockView>> {
+        self.inner.get_block(hash)
+    }
+
+    fn get_header(&self, hash: &H256) -> Rpc<Option<HeaderView>> {
+        self.inner.get_header(hash)
+    }
+
+    fn get_header_by_number(&self, number: BlockNumber) -> Rpc<Option<HeaderView>> {
+        self.inner.get_header_by_number(number)
+    }
+
+    fn get_block_hash(&self, number: BlockNumber) -> Rpc<Option<H256>> {
+        self.inner.get_block_hash(number)
+    }
+
+    fn get_tip_block_number(&self) -> Rpc<BlockNumber> {
+        self.inner.get_tip_block_number()
+    }
+
+    fn get_tip_header(&self) -> Rpc<HeaderView> {
+        self.inner.get_tip_header()
+    }
+
+    fn tx_pool_info(&self) -> Rpc<TxPoolInfo> {
+        self.inner.tx_pool_info()
+    }
+
+    fn get_transaction(&self, hash: &H256) -> Rpc<Option<TransactionWithStatusResponse>> {
+        let overlay = self.clone();
+        let hash = hash.clone();
+        Box::pin(async move {
+            let tx = overlay.inner.get_transaction(&hash).await?;
+            if tx.as_ref().is_some_and(is_settled) {
+                overlay.forget(&hash);
+            }
+            Ok(tx)
+        })
+    }
+
+    fn send_transaction(
+        &self,
+        tx: Transaction,
+        outputs_validator: Option<OutputsValidator>,
+    ) -> Rpc<H256> {
+        let overlay = self.clone();
+        Box::pin(async move {
+            let view = packed::Transaction::from(tx.clone()).into_view();
+            let hash = overlay
+                .inner
+                .send_transaction(tx, outputs_validator)
+                .await?;
+            overlay.push_transaction(&view);
+            Ok(hash)
+        })
+    }
+
+    fn get_live_cells(
+        &self,
+        out_points: Vec<OutPoint>,
+        with_data: bool,
+    ) -> Rpc<Vec<CellWithStatus>> {
+        let pool = self.pool.read().expect("poisoned overlay pool");
+        let cells = out_points
+            .iter()
+            .map(|out_point| pending_cell(&pool, out_point, with_data))
+            .collect::<Vec<_>>();
+        let rest = out_points
+            .into_iter()
+            .zip(&cells)
+            .filter_map(|(out_point, cell)| cell.is_none().then_some(out_point))
+            .collect::<Vec<_>>();
+        let inner = self.inner.get_live_cells(rest, with_data);
+        Box::pin(async move {
+            let mut rest = inner.await?.into_iter();
+            cells
+                .into_iter()
+                .map(|cell| {
+                    cell.or_else(|| rest.next())
+                        .ok_or(RpcError::Other("missing live cell".to_string()))
+                })
+                .collect()
+        })
+    }
+
+    fn get_headers(&self, hashes: Vec<H256>) -> Rpc<Vec<Option<HeaderView>>> {
+        self.inner.get_headers(hashes)
+    }
+
+    fn get_headers_by_number(&self, numbers: Vec<BlockNumber>) -> Rpc<Vec<Option<HeaderView>>> {
+        self.inner.get_headers_by_number(numbers)
+    }
+
+    fn get_transactions(
+        &self,
+        hashes: Vec<H256>,
+    ) -> Rpc<Vec<Option<TransactionWithStatusResponse>>> {
+        let overlay = self.clone();
+        Box::pin(async move {
+            let txs = overlay.inner.get_transactions(hashes.clone()).await?;
+            hashes.iter().zip(&txs).for_each(|(hash, tx)| {
+                if tx.as_ref().is_some_and(is_settled) {
+                    overlay.forget(hash);
+                }
+            });
+            Ok(txs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ckb_types::{
+        core::{Capacity, TransactionBuilder},
+        packed::CellOutput,
+        prelude::{Builder, Pack},
+    };
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::simulation::FakeRpcClient;
+
+    fn round_trip(cursor: OverlayCursor) -> OverlayCursor {
+        OverlayCursor::decode(Some(cursor.encode())).unwrap()
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        assert!(matches!(
+            OverlayCursor::decode(None).unwrap(),
+            OverlayCursor::Pending(0)
+        ));
+        assert!(matches!(
+            round_trip(OverlayCursor::Pending(42)),
+            OverlayCursor::Pending(42)
+        ));
+        assert!(matches!(
+            round_trip(OverlayCursor::Indexer(None)),
+            OverlayCursor::Indexer(None)
+        ));
+        let indexer_cursor = JsonBytes::from_vec(vec![1, 2, 3]);
+        let OverlayCursor::Indexer(Some(decoded)) =
+            round_trip(OverlayCursor::Indexer(Some(indexer_cursor.clone())))
+        else {
+            panic!("indexer cursor expected");
+        };
+        assert_eq!(decoded, indexer_cursor);
+    }
+
+    #[test]
+    fn cursor_rejects_invalid_bytes() {
+        for bytes in [vec![], vec![0, 1, 2], vec![2]] {
+            assert!(OverlayCursor::decode(Some(JsonBytes::from_vec(bytes))).is_err());
+        }
+    }
+
+    fn pending_tx(spent: &OutPoint) -> TransactionView {
+        let output = CellOutput::new_builder()
+            .capacity(Capacity::shannons(100).pack())
+            .build();
+        TransactionBuilder::default()
+            .input(packed::CellInput::new(spent.clone().into(), 0))
+            .output(output)
+            .output_data(Default::default())
+            .build()
+    }
+
+    #[test]
+    fn committed_transaction_is_forgotten() {
+        let spent = OutPoint::from(packed::OutPoint::new(Default::default(), 0));
+        let mut fake = FakeRpcClient::default();
+        fake.insert_fake_cell(
+            spent.clone().into(),
+            CellOutputEx::new(CellOutput::default(), vec![]),
+            None,
+        );
+        let mut overlay = OverlayRpc::new(fake);
+        let hash = overlay.push_transaction(&pending_tx(&spent));
+        let created = OutPoint::from(packed::OutPoint::new(hash.pack(), 0));
+        let spent_cell = block_on(overlay.get_live_cell(&spent, false)).unwrap();
+        assert!(spent_cell.cell.is_none());
+        assert!(block_on(overlay.get_live_cell(&created, false))
+            .unwrap()
+            .cell
+            .is_some());
+
+        // still pending if the inner rpc doesn't know it
+        assert!(block_on(overlay.get_transaction(&hash)).unwrap().is_none());
+        assert_eq!(overlay.pending_transactions(), vec![hash.clone()]);
+
+        overlay
+            .inner
+            .insert_fake_tx_status(hash.clone(), H256::default(), 1);
+        block_on(overlay.get_transaction(&hash)).unwrap();
+        assert!(overlay.pending_transactions().is_empty());
+    }
+}
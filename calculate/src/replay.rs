@@ -0,0 +1,719 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use ckb_jsonrpc_types::{
+    BlockNumber, BlockView, CellWithStatus, HeaderView, JsonBytes, OutPoint, OutputsValidator,
+    Transaction, TransactionWithStatusResponse, TxPoolInfo,
+};
+use ckb_sdk::rpc::ckb_indexer::{Cell, Pagination, SearchKey};
+use ckb_types::H256;
+use eyre::Result;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    locker::CellLocker,
+    rpc::{Network, Rpc, RpcError, RPC},
+};
+
+/// Response of a recorded call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedResponse {
+    Result(Value),
+    /// `code` and `data` are kept only if the node responded with JSON-RPC error
+    Error {
+        code: Option<i64>,
+        message: String,
+        data: Option<Value>,
+    },
+}
+
+impl From<&RpcError> for RecordedResponse {
+    fn from(error: &RpcError) -> Self {
+        match error {
+            RpcError::JsonRpc {
+                code,
+                message,
+                data,
+            } => RecordedResponse::Error {
+                code: Some(*code),
+                message: message.clone(),
+                data: data.clone(),
+            },
+            _ => RecordedResponse::Error {
+                code: None,
+                message: error.to_string(),
+                data: None,
+            },
+        }
+    }
+}
+
+impl RecordedResponse {
+    fn into_result<R: DeserializeOwned>(self) -> Result<R, RpcError> {
+        match self {
+            RecordedResponse::Result(value) => {
+                serde_json::from_value(value).map_err(RpcError::Decode)
+            }
+            RecordedResponse::Error {
+                code: Some(code),
+                message,
+                data,
+            } => Err(RpcError::JsonRpc {
+                code,
+                message,
+                data,
+            }),
+            RecordedResponse::Error { message, .. } => Err(RpcError::Other(message)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedCall {
+    pub method: String,
+    pub params: Value,
+    #[serde(flatten)]
+    pub response: RecordedResponse,
+}
+
+/// Calls recorded by `RecordingRpc` in order, which are served back by `ReplayRpc`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fixture {
+    pub network: String,
+    pub url: (String, String),
+    pub calls: Vec<RecordedCall>,
+}
+
+impl Fixture {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content =
+            fs::read_to_string(path).map_err(|e| eyre::eyre!("{e}: {}", path.to_string_lossy()))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// RPC that records every call and response of the wrapped one, which are saved as fixture for `ReplayRpc`
+///
+/// Batched calls are recorded one by one, so they can be replayed no matter batched or not
+///
+/// e.g. record a recipe running against testnet:
+/// ```ignore
+/// let rpc = RecordingRpc::new(RpcClient::new_testnet());
+/// let skeleton = TransactionCalculator::default()
+///     .instruction(predefined::dao_withdraw_phase_two(&address, None, None))
+///     .new_skeleton(&rpc)
+///     .await?;
+/// rpc.save("tests/fixtures/dao_withdraw_phase_two.json")?;
+/// ```
+///
+/// note: cloned ones share the same records
+#[derive(Clone)]
+pub struct RecordingRpc<T: RPC> {
+    inner: T,
+    calls: Arc<Mutex<Vec<RecordedCall>>>,
+}
+
+impl<T: RPC> RecordingRpc<T> {
+    pub fn new(inner: T) -> Self {
+        RecordingRpc {
+            inner,
+            calls: Default::default(),
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn fixture(&self) -> Fixture {
+        Fixture {
+            network: self.inner.network().to_string(),
+            url: self.inner.url(),
+            calls: self.calls.lock().expect("poisoned recording").clone(),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.fixture().save(path)
+    }
+
+    fn record<R>(&self, method: &str, params: Value, call: Rpc<R>) -> Rpc<R>
+    where
+        R: Serialize + Send + 'static,
+    {
+        let method = method.to_string();
+        let calls = self.calls.clone();
+        Box::pin(async move {
+            let result = call.await;
+            let response = match &result {
+                Ok(value) => {
+                    RecordedResponse::Result(serde_json::to_value(value).unwrap_or_default())
+                }
+                Err(error) => error.into(),
+            };
+            calls
+                .lock()
+                .expect("poisoned recording")
+                .push(RecordedCall {
+                    method,
+                    params,
+                    response,
+                });
+            result
+        })
+    }
+
+    /// Record each of batched calls as a single one of `method`, a failed batch is recorded as failure of each call
+    fn record_batch<R>(&self, method: &str, params: Vec<Value>, call: Rpc<Vec<R>>) -> Rpc<Vec<R>>
+    where
+        R: Serialize + Send + 'static,
+    {
+        let method = method.to_string();
+        let calls = self.calls.clone();
+        Box::pin(async move {
+            let result = call.await;
+            let responses = match &result {
+                Ok(values) => values
+                    .iter()
+                    .map(|v| RecordedResponse::Result(serde_json::to_value(v).unwrap_or_default()))
+                    .collect(),
+                Err(error) => vec![RecordedResponse::from(error); params.len()],
+            };
+            let mut calls = calls.lock().expect("poisoned recording");
+            params
+                .into_iter()
+                .zip(responses)
+                .for_each(|(params, response)| {
+                    calls.push(RecordedCall {
+                        method: method.clone(),
+                        params,
+                        response,
+                    })
+                });
+            drop(calls);
+            result
+        })
+    }
+}
+
+impl<T: RPC + 'static> RPC for RecordingRpc<T> {
+    fn network(&self) -> Network {
+        self.inner.network()
+    }
+
+    fn url(&self) -> (String, String) {
+        self.inner.url()
+    }
+
+    fn cell_locker(&self) -> Option<&CellLocker> {
+        self.inner.cell_locker()
+    }
+
+    fn get_live_cell(&self, out_point: &OutPoint, with_data: bool) -> Rpc<CellWithStatus> {
+        let params = json!([out_point, with_data]);
+        self.record(
+            "get_live_cell",
+            params,
+            self.inner.get_live_cell(out_point, with_data),
+        )
+    }
+
+    fn get_cells(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Rpc<Pagination<Cell>> {
+        let params = json!([search_key, limit, cursor]);
+        self.record(
+            "get_cells",
+            params,
+            self.inner.get_cells(search_key, limit, cursor),
+        )
+    }
+
+    fn get_block_by_number(&self, number: BlockNumber) -> Rpc<Option<BlockView>> {
+        let params = json!([number]);
+        self.record(
+            "get_block_by_number",
+            params,
+            self.inner.get_block_by_number(number),
+        )
+    }
+
+    fn get_block(&self, hash: &H256) -> Rpc<Option<BlockView>> {
+        self.record("get_block", json!([hash]), self.inner.get_block(hash))
+    }
+
+    fn get_header(&self, hash: &H256) -> Rpc<Option<HeaderView>> {
+        self.record("get_header", json!([hash]), self.inner.get_header(hash))
+    }
+
+    fn get_header_by_number(&self, number: BlockNumber) -> Rpc<Option<HeaderView>> {
+        let params = json!([number]);
+        self.record(
+            "get_header_by_number",
+            params,
+            self.inner.get_header_by_number(number),
+        )
+    }
+
+    fn get_block_hash(&self, number: BlockNumber) -> Rpc<Option<H256>> {
+        let params = json!([number]);
+        self.record("get_block_hash", params, self.inner.get_block_hash(number))
+    }
+
+    fn get_tip_block_number(&self) -> Rpc<BlockNumber> {
+        self.record(
+            "get_tip_block_number",
+            json!([]),
+            self.inner.get_tip_block_number(),
+        )
+    }
+
+    fn get_tip_header(&self) -> Rpc<HeaderView> {
+        self.record("get_tip_header", json!([]), self.inner.get_tip_header())
+    }
+
+    fn tx_pool_info(&self) -> Rpc<TxPoolInfo> {
+        self.record("tx_pool_info", json!([]), self.inner.tx_pool_info())
+    }
+
+    fn get_transaction(&self, hash: &H256) -> Rpc<Option<TransactionWithStatusResponse>> {
+        let params = json!([hash]);
+        self.record("get_transaction", params, self.inner.get_transaction(hash))
+    }
+
+    fn send_transaction(
+        &self,
+        tx: Transaction,
+        outputs_validator: Option<OutputsValidator>,
+    ) -> Rpc<H256> {
+        let params = json!([tx, outputs_validator]);
+        self.record(
+            "send_transaction",
+            params,
+            self.inner.send_transaction(tx, outputs_validator),
+        )
+    }
+
+    fn get_live_cells(
+        &self,
+        out_points: Vec<OutPoint>,
+        with_data: bool,
+    ) -> Rpc<Vec<CellWithStatus>> {
+        let params = out_points
+            .iter()
+            .map(|out_point| json!([out_point, with_data]))
+            .collect();
+        let call = self.inner.get_live_cells(out_points, with_data);
+        self.record_batch("get_live_cell", params, call)
+    }
+
+    fn get_headers(&self, hashes: Vec<H256>) -> Rpc<Vec<Option<HeaderView>>> {
+        let params = hashes.iter().map(|hash| json!([hash])).collect();
+        self.record_batch("get_header", params, self.inner.get_headers(hashes))
+    }
+
+    fn get_headers_by_number(&self, numbers: Vec<BlockNumber>) -> Rpc<Vec<Option<HeaderView>>> {
+        let params = numbers.iter().map(|number| json!([number])).collect();
+        let call = self.inner.get_headers_by_number(numbers);
+        self.record_batch("get_header_by_number", params, call)
+    }
+
+    fn get_transactions(
+        &self,
+        hashes: Vec<H256>,
+    ) -> Rpc<Vec<Option<TransactionWithStatusResponse>>> {
+        let params = hashes.iter().map(|hash| json!([hash])).collect();
+        let call = self.inner.get_transactions(hashes);
+        self.record_batch("get_transaction", params, call)
+    }
+}
+
+/// Recorded responses keyed by method and params in json
+type Responses = HashMap<(String, String), VecDeque<RecordedResponse>>;
+
+/// RPC that serves the calls recorded in fixture offline, which reproduces real chain states deterministically
+///
+/// Calls are matched by method and params, the repeated ones are served in recorded order, and the last response
+/// is kept serving once exhausted, e.g. polling the status of a sent transaction
+///
+/// note: a call that never recorded fails, which usually means the recipe has been changed since recording
+#[derive(Clone)]
+pub struct ReplayRpc {
+    network: Network,
+    url: (String, String),
+    responses: Arc<Mutex<Responses>>,
+    cell_locker: Option<CellLocker>,
+}
+
+impl ReplayRpc {
+    pub fn new(fixture: Fixture) -> Result<Self> {
+        let mut responses = Responses::new();
+        for call in fixture.calls {
+            responses
+                .entry((call.method, call.params.to_string()))
+                .or_default()
+                .push_back(call.response);
+        }
+        Ok(ReplayRpc {
+            network: fixture.network.parse()?,
+            url: fixture.url,
+            responses: Arc::new(Mutex::new(responses)),
+            cell_locker: None,
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(Fixture::load(path)?)
+    }
+
+    pub fn with_cell_locker(mut self, locker: CellLocker) -> Self {
+        self.cell_locker = Some(locker);
+        self
+    }
+
+    fn replay<R>(&self, method: &str, params: Value) -> Rpc<R>
+    where
+        R: DeserializeOwned + Send + 'static,
+    {
+        let key = (method.to_string(), params.to_string());
+        let response = {
+            let mut responses = self.responses.lock().expect("poisoned replay");
+            responses.get_mut(&key).and_then(|queue| {
+                if queue.len() > 1 {
+                    queue.pop_front()
+                } else {
+                    queue.front().cloned()
+                }
+            })
+        };
+        let result = match response {
+            Some(response) => response.into_result(),
+            None => Err(RpcError::Other(format!(
+                "no recorded response of {method} with params {params}"
+            ))),
+        };
+        Box::pin(async move { result })
+    }
+}
+
+impl RPC for ReplayRpc {
+    fn network(&self) -> Network {
+        self.network.clone()
+    }
+
+    fn url(&self) -> (String, String) {
+        self.url.clone()
+    }
+
+    fn cell_locker(&self) -> Option<&CellLocker> {
+        self.cell_locker.as_ref()
+    }
+
+    fn get_live_cell(&self, out_point: &OutPoint, with_data: bool) -> Rpc<CellWithStatus> {
+        self.replay("get_live_cell", json!([out_point, with_data]))
+    }
+
+    fn get_cells(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Rpc<Pagination<Cell>> {
+        self.replay("get_cells", json!([search_key, limit, cursor]))
+    }
+
+    fn get_block_by_number(&self, number: BlockNumber) -> Rpc<Option<BlockView>> {
+        self.replay("get_block_by_number", json!([number]))
+    }
+
+    fn get_block(&self, hash: &H256) -> Rpc<Option<BlockView>> {
+        self.replay("get_block", json!([hash]))
+    }
+
+    fn get_header(&self, hash: &H256) -> Rpc<Option<HeaderView>> {
+        self.replay("get_header", json!([hash]))
+    }
+
+    fn get_header_by_number(&self, number: BlockNumber) -> Rpc<Option<HeaderView>> {
+        self.replay("get_header_by_number", json!([number]))
+    }
+
+    fn get_block_hash(&self, number: BlockNumber) -> Rpc<Option<H256>> {
+        self.replay("get_block_hash", json!([number]))
+    }
+
+    fn get_tip_block_number(&self) -> Rpc<BlockNumber> {
+        self.replay("get_tip_block_number", json!([]))
+    }
+
+    fn get_tip_header(&self) -> Rpc<HeaderView> {
+        self.replay("get_tip_header", json!([]))
+    }
+
+    fn tx_pool_info(&self) -> Rpc<TxPoolInfo> {
+        self.replay("tx_pool_info", json!([]))
+    }
+
+    fn get_transaction(&self, hash: &H256) -> Rpc<Option<TransactionWithStatusResponse>> {
+        self.replay("get_transaction", json!([hash]))
+    }
+
+    fn send_transaction(
+        &self,
+        tx: Transaction,
+        outputs_validator: Option<OutputsValidator>,
+    ) -> Rpc<H256> {
+        self.replay("send_transaction", json!([tx, outputs_validator]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ckb_jsonrpc_types::{Status, TxStatus};
+    use ckb_sdk::{constants::SIGHASH_TYPE_HASH, util::blake160, Address, NetworkType};
+    use ckb_types::{
+        core::{Capacity, EpochNumberWithFraction, HeaderBuilder},
+        packed::{self, CellOutput},
+        prelude::*,
+    };
+    use futures::executor::block_on;
+    use secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+    use super::*;
+    use crate::{
+        instruction::predefined,
+        operation::Log,
+        simulation::FakeRpcClient,
+        skeleton::{CellOutputEx, ScriptEx, TransactionSkeleton},
+    };
+
+    const CKB: u64 = 100_000_000;
+
+    fn secret_key(seed: u8) -> SecretKey {
+        SecretKey::from_slice(&[seed; 32]).unwrap()
+    }
+
+    fn address(key: &SecretKey) -> Address {
+        let pubkey = PublicKey::from_secret_key(&Secp256k1::new(), key);
+        let lock_script =
+            ScriptEx::new_type(SIGHASH_TYPE_HASH, blake160(&pubkey.serialize()).0.to_vec());
+        Address::new(
+            NetworkType::Testnet,
+            lock_script.to_script_unchecked().into(),
+            true,
+        )
+    }
+
+    fn cell(capacity: u64, owner: &Address) -> CellOutputEx {
+        let output = CellOutput::new_builder()
+            .capacity(Capacity::shannons(capacity).pack())
+            .lock(owner.payload().into())
+            .build();
+        CellOutputEx::new(output, vec![])
+    }
+
+    fn out_point(tx: u8) -> OutPoint {
+        OutPoint {
+            tx_hash: H256([tx; 32]),
+            index: 0.into(),
+        }
+    }
+
+    fn json<T: Serialize>(value: &T) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    fn call(method: &str, params: Value, response: RecordedResponse) -> RecordedCall {
+        RecordedCall {
+            method: method.to_string(),
+            params,
+            response,
+        }
+    }
+
+    fn fixture(calls: Vec<RecordedCall>) -> Fixture {
+        Fixture {
+            network: "fake".to_string(),
+            url: (String::new(), String::new()),
+            calls,
+        }
+    }
+
+    /// Transfer 500 CKB by the predefined recipe that balances and signs
+    async fn transfer<T: RPC>(rpc: &T, from: &Address, to: &Address, key: SecretKey) -> Vec<u8> {
+        let mut skeleton = TransactionSkeleton::default();
+        skeleton.output(cell(500 * CKB, to));
+        predefined::balance_and_sign(from, key, 0)
+            .run(rpc, &mut skeleton, &mut Log::new())
+            .await
+            .unwrap();
+        skeleton.into_transaction_view().data().as_slice().to_vec()
+    }
+
+    #[test]
+    fn replay_recorded_predefined_recipe() {
+        let key = secret_key(1);
+        let (sender, receiver) = (address(&key), address(&secret_key(2)));
+        let mut fake = FakeRpcClient::default();
+        fake.fake_provider.fake_feerate = 1000;
+        fake.insert_fake_cell(out_point(1).into(), cell(1000 * CKB, &sender), None);
+        let recording = RecordingRpc::new(fake);
+        let recorded = block_on(transfer(&recording, &sender, &receiver, key));
+
+        let path = std::env::temp_dir()
+            .join(format!("cinnabar-replay-{}", rand::random::<u64>()))
+            .join("transfer.json");
+        recording.save(&path).unwrap();
+        let fixture = Fixture::load(&path).unwrap();
+        assert!(fixture.calls.iter().any(|v| v.method == "get_cells"));
+        assert!(fixture.calls.iter().any(|v| v.method == "tx_pool_info"));
+        let replay = ReplayRpc::new(fixture).unwrap();
+        assert!(replay.network() == Network::Fake);
+        let replayed = block_on(transfer(&replay, &sender, &receiver, key));
+        assert_eq!(replayed, recorded);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn batched_calls_are_recorded_per_item() {
+        let owner = address(&secret_key(1));
+        let mut fake = FakeRpcClient::default();
+        let mut hashes = vec![];
+        for i in 1..=2 {
+            let header = HeaderBuilder::default()
+                .number((i as u64).pack())
+                .epoch(EpochNumberWithFraction::new(0, i as u64, 1000).pack())
+                .build();
+            hashes.push(header.hash().unpack());
+            fake.insert_fake_cell(out_point(i).into(), cell(100 * CKB, &owner), Some(header));
+        }
+        let out_points = vec![out_point(1), out_point(2)];
+        let tx_hashes = vec![out_point(1).tx_hash, out_point(2).tx_hash];
+        let recording = RecordingRpc::new(fake);
+        let cells = block_on(recording.get_live_cells(out_points.clone(), true)).unwrap();
+        let headers = block_on(recording.get_headers(hashes.clone())).unwrap();
+        let txs = block_on(recording.get_transactions(tx_hashes.clone())).unwrap();
+
+        let fixture = recording.fixture();
+        let methods = fixture
+            .calls
+            .iter()
+            .map(|v| v.method.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            methods,
+            vec![
+                "get_live_cell",
+                "get_live_cell",
+                "get_header",
+                "get_header",
+                "get_transaction",
+                "get_transaction"
+            ]
+        );
+        assert_eq!(fixture.calls[1].params, json!([out_points[1], true]));
+        assert_eq!(fixture.calls[2].params, json!([hashes[0]]));
+        assert_eq!(fixture.calls[5].params, json!([tx_hashes[1]]));
+        // replayed one by one, or batched by the default implementations
+        let replay = ReplayRpc::new(fixture).unwrap();
+        let live_cell = block_on(replay.get_live_cell(&out_points[1], true)).unwrap();
+        assert_eq!(json(&live_cell), json(&cells[1]));
+        let replayed = block_on(replay.get_live_cells(out_points, true)).unwrap();
+        assert_eq!(json(&replayed), json(&cells));
+        let replayed = block_on(replay.get_headers(hashes)).unwrap();
+        assert_eq!(json(&replayed), json(&headers));
+        let tx = block_on(replay.get_transaction(&tx_hashes[0])).unwrap();
+        assert_eq!(json(&tx), json(&txs[0]));
+        let replayed = block_on(replay.get_transactions(tx_hashes)).unwrap();
+        assert_eq!(json(&replayed), json(&txs));
+    }
+
+    #[test]
+    fn repeated_calls_keep_the_last_response() {
+        let calls = ["0x1", "0x2"]
+            .into_iter()
+            .map(|tip| {
+                call(
+                    "get_tip_block_number",
+                    json!([]),
+                    RecordedResponse::Result(json!(tip)),
+                )
+            })
+            .collect();
+        let replay = ReplayRpc::new(fixture(calls)).unwrap();
+        let tips = (0..4)
+            .map(|_| u64::from(block_on(replay.get_tip_block_number()).unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(tips, vec![1, 2, 2, 2]);
+    }
+
+    #[test]
+    fn replay_recorded_errors_and_fail_unrecorded_calls() {
+        let pending = TxStatus {
+            status: Status::Pending,
+            block_hash: None,
+            block_number: None,
+            reason: None,
+        };
+        let error = RpcError::JsonRpc {
+            code: -1107,
+            message: "PoolRejectedDuplicatedTransaction".to_string(),
+            data: None,
+        };
+        let tx = packed::Transaction::default();
+        let replay = ReplayRpc::new(fixture(vec![
+            call(
+                "send_transaction",
+                json!([Transaction::from(tx.clone()), Value::Null]),
+                (&error).into(),
+            ),
+            call(
+                "get_live_cell",
+                json!([out_point(1), true]),
+                (&RpcError::Other("no live cell found".to_string())).into(),
+            ),
+            call(
+                "get_transaction",
+                json!([H256::default()]),
+                RecordedResponse::Result(json!({"transaction": null, "tx_status": pending})),
+            ),
+        ]))
+        .unwrap();
+        let replayed = block_on(replay.send_transaction(tx.into(), None)).unwrap_err();
+        assert_eq!(replayed.code(), Some(-1107));
+        let replayed = block_on(replay.get_live_cell(&out_point(1), true)).unwrap_err();
+        assert_eq!(replayed.to_string(), "no live cell found");
+        let tx = block_on(replay.get_transaction(&H256::default())).unwrap();
+        assert_eq!(tx.unwrap().tx_status.status, Status::Pending);
+
+        // the params differ from the recorded ones
+        let error = block_on(replay.get_live_cell(&out_point(1), false)).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("no recorded response of get_live_cell with params"));
+        let error = block_on(replay.get_tip_header()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "no recorded response of get_tip_header with params []"
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use reqwest::Url;
+
+/// Default timeout of each request
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default retries of an idempotent request before giving up
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Default delay before the first retry, it's doubled on each retry up to `DEFAULT_MAX_BACKOFF`
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Default upper bound of the delay between retries
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// Default time that a failed endpoint is skipped, it's doubled on consecutive failures up to 8 times
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Methods that are not safe to repeat, they're only retried if the request never reached the node
+pub(crate) const NON_IDEMPOTENT_METHODS: &[&str] = &["send_transaction"];
+
+/// Policy of retrying failed requests with exponential backoff
+#[derive(Debug, Clone)]
+pub(crate) struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub retry_send_transaction: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            retry_send_transaction: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn is_idempotent(&self, method: &str) -> bool {
+        self.retry_send_transaction || !NON_IDEMPOTENT_METHODS.contains(&method)
+    }
+}
+
+pub(crate) struct Endpoint {
+    pub ckb_uri: Url,
+    pub indexer_uri: Url,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Health {
+    failures: u32,
+    unhealthy_until: Option<Instant>,
+}
+
+/// Ordered endpoints with health tracking, the first healthy one is preferred and the failed ones are skipped
+/// until their cooldown elapses
+///
+/// note: cloned endpoints share the same health
+#[derive(Clone)]
+pub(crate) struct Endpoints {
+    list: Arc<Vec<Endpoint>>,
+    health: Arc<Mutex<Vec<Health>>>,
+    cooldown: Duration,
+}
+
+impl Endpoints {
+    pub fn new(list: Vec<Endpoint>, cooldown: Duration) -> Self {
+        let health = vec![Health::default(); list.len()];
+        Endpoints {
+            list: Arc::new(list),
+            health: Arc::new(Mutex::new(health)),
+            cooldown,
+        }
+    }
+
+    fn health(&self) -> std::sync::MutexGuard<'_, Vec<Health>> {
+        self.health.lock().expect("poisoned endpoint health")
+    }
+
+    pub fn get(&self, index: usize) -> &Endpoint {
+        &self.list[index]
+    }
+
+    /// Select the first healthy endpoint, if all are unhealthy, select the one that recovers earliest
+    pub fn select(&self) -> usize {
+        let now = Instant::now();
+        let health = self.health();
+        health
+            .iter()
+            .position(|v| v.unhealthy_until.is_none_or(|until| until <= now))
+            .unwrap_or_else(|| {
+                (0..health.len())
+                    .min_by_key(|i| health[*i].unhealthy_until)
+                    .unwrap_or_default()
+            })
+    }
+
+    pub fn has_healthy(&self) -> bool {
+        let now = Instant::now();
+        self.health()
+            .iter()
+            .any(|v| v.unhealthy_until.is_none_or(|until| until <= now))
+    }
+
+    pub fn mark_healthy(&self, index: usize) {
+        self.health()[index] = Health::default();
+    }
+
+    pub fn mark_failed(&self, index: usize) {
+        let health = &mut self.health()[index];
+        let cooldown = self.cooldown * (1 << health.failures.min(3));
+        health.failures += 1;
+        health.unhealthy_until = Some(Instant::now() + cooldown);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoints(count: usize, cooldown: Duration) -> Endpoints {
+        let list = (0..count)
+            .map(|i| {
+                let uri = Url::parse(&format!("http://127.0.0.1:{}", 8114 + i)).unwrap();
+                Endpoint {
+                    ckb_uri: uri.clone(),
+                    indexer_uri: uri,
+                }
+            })
+            .collect();
+        Endpoints::new(list, cooldown)
+    }
+
+    #[test]
+    fn select_first_healthy_endpoint() {
+        let endpoints = endpoints(3, Duration::from_secs(60));
+        assert_eq!(endpoints.select(), 0);
+        endpoints.mark_failed(0);
+        assert_eq!(endpoints.select(), 1);
+        endpoints.mark_failed(1);
+        assert_eq!(endpoints.select(), 2);
+        // clones share the health
+        let cloned = endpoints.clone();
+        cloned.mark_healthy(0);
+        assert_eq!(endpoints.select(), 0);
+        assert!(endpoints.has_healthy());
+    }
+
+    #[test]
+    fn failed_endpoint_recovers_after_cooldown() {
+        let endpoints = endpoints(2, Duration::from_millis(20));
+        endpoints.mark_failed(0);
+        assert_eq!(endpoints.select(), 1);
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(endpoints.select(), 0);
+    }
+
+    #[test]
+    fn select_earliest_recovery_if_all_failed() {
+        let endpoints = endpoints(2, Duration::from_secs(60));
+        endpoints.mark_failed(1);
+        endpoints.mark_failed(0);
+        assert!(!endpoints.has_healthy());
+        assert_eq!(endpoints.select(), 1);
+        // the cooldown of consecutive failures is longer
+        endpoints.mark_failed(1);
+        assert_eq!(endpoints.select(), 0);
+    }
+
+    #[test]
+    fn cooldown_doubles_up_to_eight_times() {
+        let cooldown = Duration::from_secs(10);
+        let endpoints = endpoints(1, cooldown);
+        let mut cooldowns = vec![];
+        for _ in 0..5 {
+            let now = Instant::now();
+            endpoints.mark_failed(0);
+            let until = endpoints.health()[0].unhealthy_until.unwrap();
+            // round down to the cooldown unit, the elapsed time is negligible
+            cooldowns.push((until - now).as_secs() / cooldown.as_secs());
+        }
+        assert_eq!(cooldowns, vec![1, 2, 4, 8, 8]);
+        endpoints.mark_healthy(0);
+        assert_eq!(endpoints.health()[0].failures, 0);
+    }
+
+    #[test]
+    fn send_transaction_is_not_idempotent_by_default() {
+        let mut policy = RetryPolicy::default();
+        assert!(!policy.is_idempotent("send_transaction"));
+        assert!(policy.is_idempotent("get_transaction"));
+        policy.retry_send_transaction = true;
+        assert!(policy.is_idempotent("send_transaction"));
+    }
+}
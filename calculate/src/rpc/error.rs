@@ -0,0 +1,225 @@
+use std::fmt::Display;
+
+use serde_json::Value;
+
+/// Well-known error codes of CKB JSON-RPC, refer to https://github.com/nervosnetwork/ckb/blob/develop/rpc/README.md#error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CkbErrorCode {
+    TransactionFailedToResolve,
+    TransactionFailedToVerify,
+    PoolRejectedTransactionByOutputsValidator,
+    PoolRejectedTransactionByIllTransactionChecker,
+    PoolRejectedTransactionByMinFeeRate,
+    PoolRejectedTransactionByMaxAncestorsCountLimit,
+    PoolIsFull,
+    PoolRejectedDuplicatedTransaction,
+    PoolRejectedMalformedTransaction,
+    TransactionExpired,
+    PoolRejectedTransactionBySizeLimit,
+    PoolRejectedRBF,
+    PoolRejectedInvalidated,
+    Indexer,
+}
+
+impl CkbErrorCode {
+    pub fn from_code(code: i64) -> Option<Self> {
+        let code = match code {
+            -301 => CkbErrorCode::TransactionFailedToResolve,
+            -302 => CkbErrorCode::TransactionFailedToVerify,
+            -1102 => CkbErrorCode::PoolRejectedTransactionByOutputsValidator,
+            -1103 => CkbErrorCode::PoolRejectedTransactionByIllTransactionChecker,
+            -1104 => CkbErrorCode::PoolRejectedTransactionByMinFeeRate,
+            -1105 => CkbErrorCode::PoolRejectedTransactionByMaxAncestorsCountLimit,
+            -1106 => CkbErrorCode::PoolIsFull,
+            -1107 => CkbErrorCode::PoolRejectedDuplicatedTransaction,
+            -1108 => CkbErrorCode::PoolRejectedMalformedTransaction,
+            -1109 => CkbErrorCode::TransactionExpired,
+            -1110 => CkbErrorCode::PoolRejectedTransactionBySizeLimit,
+            -1111 => CkbErrorCode::PoolRejectedRBF,
+            -1112 => CkbErrorCode::PoolRejectedInvalidated,
+            -1200 => CkbErrorCode::Indexer,
+            _ => return None,
+        };
+        Some(code)
+    }
+}
+
+/// Error of calling RPC, which keeps the failure kind for callers to retry or recover
+///
+/// e.g. treat a duplicated transaction as sent:
+/// ```
+/// use ckb_cinnabar_calculator::{
+///     re_exports::ckb_jsonrpc_types::Transaction,
+///     rpc::{CkbErrorCode, RpcError, RPC},
+/// };
+///
+/// async fn send<T: RPC>(rpc: &T, tx: Transaction) -> Result<(), RpcError> {
+///     match rpc.send_transaction(tx, None).await {
+///         Err(e) if e.ckb_error_code() == Some(CkbErrorCode::PoolRejectedDuplicatedTransaction) => Ok(()),
+///         result => result.map(|_| ()),
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub enum RpcError {
+    /// Failed to reach the node, e.g. connection refused or DNS failure
+    Transport(reqwest::Error),
+    /// The node or proxy responded with non-success HTTP status
+    HttpStatus { status: u16, body: String },
+    /// Failed to encode request params
+    Encode(serde_json::Error),
+    /// The response doesn't match the expected schema, e.g. served by a different CKB version
+    Decode(serde_json::Error),
+    /// The node responded with JSON-RPC error
+    JsonRpc {
+        code: i64,
+        message: String,
+        data: Option<Value>,
+    },
+    /// The request didn't complete in time
+    Timeout,
+    /// Failure raised by RPC implementations other than the network client, e.g. fake or overlay one
+    Other(String),
+}
+
+impl RpcError {
+    pub fn from_reqwest(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            RpcError::Timeout
+        } else {
+            RpcError::Transport(error)
+        }
+    }
+
+    /// The JSON-RPC error code if the node responded with error
+    pub fn code(&self) -> Option<i64> {
+        match self {
+            RpcError::JsonRpc { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    pub fn ckb_error_code(&self) -> Option<CkbErrorCode> {
+        self.code().and_then(CkbErrorCode::from_code)
+    }
+
+    /// Whether the failure comes from the endpoint rather than the request, e.g. throttled or unreachable
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            RpcError::Transport(_) | RpcError::Timeout => true,
+            RpcError::HttpStatus { status, .. } => {
+                *status == 408 || *status == 429 || *status >= 500
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the request never reached the node, which is safe to resend even if not idempotent
+    pub fn is_connect(&self) -> bool {
+        matches!(self, RpcError::Transport(e) if e.is_connect())
+    }
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RpcError::Transport(e) => write!(f, "bad ckb request: {e}"),
+            RpcError::HttpStatus { status, body } => {
+                write!(f, "ckb rpc responded with http status {status}: {body}")
+            }
+            RpcError::Encode(e) => write!(f, "failed to encode rpc params: {e}"),
+            RpcError::Decode(e) => write!(f, "failed to parse json response: {e}"),
+            RpcError::JsonRpc {
+                code,
+                message,
+                data,
+            } => {
+                write!(f, "ckb rpc error {code}: {message}")?;
+                if let Some(data) = data {
+                    write!(f, ", data: {data}")?;
+                }
+                Ok(())
+            }
+            RpcError::Timeout => write!(f, "ckb rpc request timeout"),
+            RpcError::Other(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RpcError::Transport(e) => Some(e),
+            RpcError::Encode(e) | RpcError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json_rpc(code: i64) -> RpcError {
+        RpcError::JsonRpc {
+            code,
+            message: "error".to_string(),
+            data: None,
+        }
+    }
+
+    fn http_status(status: u16) -> RpcError {
+        RpcError::HttpStatus {
+            status,
+            body: String::new(),
+        }
+    }
+
+    #[test]
+    fn ckb_error_code_of_json_rpc_errors() {
+        assert_eq!(
+            json_rpc(-1107).ckb_error_code(),
+            Some(CkbErrorCode::PoolRejectedDuplicatedTransaction)
+        );
+        assert_eq!(
+            json_rpc(-302).ckb_error_code(),
+            Some(CkbErrorCode::TransactionFailedToVerify)
+        );
+        // unknown codes are kept but not classified
+        assert_eq!(json_rpc(-32601).code(), Some(-32601));
+        assert_eq!(json_rpc(-32601).ckb_error_code(), None);
+        assert_eq!(RpcError::Timeout.code(), None);
+        assert_eq!(http_status(500).ckb_error_code(), None);
+    }
+
+    #[test]
+    fn only_endpoint_failures_are_retryable() {
+        assert!(RpcError::Timeout.is_retryable());
+        for status in [408, 429, 500, 502, 503] {
+            assert!(http_status(status).is_retryable(), "status {status}");
+        }
+        for status in [400, 401, 404, 413] {
+            assert!(!http_status(status).is_retryable(), "status {status}");
+        }
+        // the node has handled the request, so the same request fails again
+        assert!(!json_rpc(-1104).is_retryable());
+        let decode = serde_json::from_str::<u64>("").unwrap_err();
+        assert!(!RpcError::Decode(decode).is_retryable());
+        assert!(!RpcError::Other("fake".to_string()).is_retryable());
+        assert!(!RpcError::Timeout.is_connect());
+    }
+
+    #[test]
+    fn display_json_rpc_error_with_data() {
+        let error = RpcError::JsonRpc {
+            code: -1104,
+            message: "PoolRejectedTransactionByMinFeeRate".to_string(),
+            data: Some(Value::String("fee rate too low".to_string())),
+        };
+        assert_eq!(
+            error.to_string(),
+            "ckb rpc error -1104: PoolRejectedTransactionByMinFeeRate, data: \"fee rate too low\""
+        );
+        assert_eq!(json_rpc(-1).to_string(), "ckb rpc error -1: error");
+    }
+}
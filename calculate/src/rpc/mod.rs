@@ -0,0 +1,1055 @@
+use std::{
+    fmt::Display,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use ckb_jsonrpc_types::{
+    BlockNumber, BlockView, CellWithStatus, HeaderView, JsonBytes, OutPoint, OutputsValidator,
+    Transaction, TransactionWithStatusResponse, TxPoolInfo, Uint32,
+};
+use ckb_sdk::rpc::ckb_indexer::{Cell, Order, Pagination, SearchKey};
+use ckb_types::{bytes::Bytes, packed, H256};
+use futures::future::try_join_all;
+use jsonrpc_core::{
+    futures::FutureExt,
+    response::{Output, Response},
+    Id,
+};
+use reqwest::{Client, Url};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::locker::CellLocker;
+
+mod endpoint;
+mod error;
+
+use endpoint::{Endpoint, Endpoints, RetryPolicy};
+pub use endpoint::{
+    DEFAULT_COOLDOWN, DEFAULT_INITIAL_BACKOFF, DEFAULT_MAX_BACKOFF, DEFAULT_MAX_RETRIES,
+    DEFAULT_TIMEOUT,
+};
+pub use error::*;
+
+pub type Rpc<T> = Pin<Box<dyn Future<Output = Result<T, RpcError>> + Send + 'static>>;
+
+pub const MAINNET_RPC_URL: &str = "https://mainnet.ckb.dev";
+pub const TESTNET_RPC_URL: &str = "https://testnet.ckbapp.dev";
+
+/// Default number of calls in one JSON-RPC batch request
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 100;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy)]
+enum Target {
+    CKB,
+    Indexer,
+}
+
+macro_rules! jsonrpc {
+    ($method:expr, $id:expr, $self:ident, $return:ty$(, $params:ident$(,)?)*) => {
+        $self.call::<$return>($method, $id, serde_json::to_value(($($params,)*)))
+    };
+}
+
+#[derive(PartialEq, Eq, Clone)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Custom(Url),
+    Fake,
+}
+
+impl Display for Network {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Network::Mainnet => write!(f, "mainnet"),
+            Network::Testnet => write!(f, "testnet"),
+            Network::Fake => write!(f, "fake"),
+            Network::Custom(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = eyre::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            "fake" => Ok(Network::Fake),
+            _ => Ok(Network::Custom(value.parse()?)),
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub trait RPC: Clone + Send + Sync {
+    fn network(&self) -> Network {
+        Network::Fake
+    }
+    fn url(&self) -> (String, String);
+    /// Shared reservation store of out points, reserved cells are skipped while searching live cells
+    fn cell_locker(&self) -> Option<&CellLocker> {
+        None
+    }
+    fn get_live_cell(&self, out_point: &OutPoint, with_data: bool) -> Rpc<CellWithStatus>;
+    fn get_cells(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Rpc<Pagination<Cell>>;
+    fn get_block_by_number(&self, number: BlockNumber) -> Rpc<Option<BlockView>>;
+    fn get_block(&self, hash: &H256) -> Rpc<Option<BlockView>>;
+    fn get_header(&self, hash: &H256) -> Rpc<Option<HeaderView>>;
+    fn get_header_by_number(&self, number: BlockNumber) -> Rpc<Option<HeaderView>>;
+    fn get_block_hash(&self, number: BlockNumber) -> Rpc<Option<H256>>;
+    fn get_tip_block_number(&self) -> Rpc<BlockNumber>;
+    fn get_tip_header(&self) -> Rpc<HeaderView>;
+    fn tx_pool_info(&self) -> Rpc<TxPoolInfo>;
+    fn get_transaction(&self, hash: &H256) -> Rpc<Option<TransactionWithStatusResponse>>;
+    fn send_transaction(
+        &self,
+        tx: Transaction,
+        outputs_validator: Option<OutputsValidator>,
+    ) -> Rpc<H256>;
+
+    /// Batched `get_live_cell`, the results are in the same order as `out_points`
+    ///
+    /// note: the default implementations of batched methods call one by one concurrently
+    fn get_live_cells(
+        &self,
+        out_points: Vec<OutPoint>,
+        with_data: bool,
+    ) -> Rpc<Vec<CellWithStatus>> {
+        let calls = out_points
+            .iter()
+            .map(|out_point| self.get_live_cell(out_point, with_data))
+            .collect::<Vec<_>>();
+        try_join_all(calls).boxed()
+    }
+    /// Batched `get_header`
+    fn get_headers(&self, hashes: Vec<H256>) -> Rpc<Vec<Option<HeaderView>>> {
+        let calls = hashes
+            .iter()
+            .map(|hash| self.get_header(hash))
+            .collect::<Vec<_>>();
+        try_join_all(calls).boxed()
+    }
+    /// Batched `get_header_by_number`
+    fn get_headers_by_number(&self, numbers: Vec<BlockNumber>) -> Rpc<Vec<Option<HeaderView>>> {
+        let calls = numbers
+            .into_iter()
+            .map(|number| self.get_header_by_number(number))
+            .collect::<Vec<_>>();
+        try_join_all(calls).boxed()
+    }
+    /// Batched `get_transaction`
+    fn get_transactions(
+        &self,
+        hashes: Vec<H256>,
+    ) -> Rpc<Vec<Option<TransactionWithStatusResponse>>> {
+        let calls = hashes
+            .iter()
+            .map(|hash| self.get_transaction(hash))
+            .collect::<Vec<_>>();
+        try_join_all(calls).boxed()
+    }
+}
+
+#[derive(Clone)]
+pub struct RpcClient {
+    network: Network,
+    raw: Client,
+    endpoints: Endpoints,
+    retry: RetryPolicy,
+    max_batch_size: usize,
+    id: Arc<AtomicU64>,
+    locker: Option<CellLocker>,
+}
+
+impl RpcClient {
+    pub fn builder() -> RpcClientBuilder {
+        RpcClientBuilder::default()
+    }
+
+    pub fn new_mainnet() -> Self {
+        RpcClient::builder()
+            .endpoint(MAINNET_RPC_URL, None)
+            .network(Network::Mainnet)
+            .build()
+            .expect("mainnet rpc client")
+    }
+
+    pub fn new_testnet() -> Self {
+        RpcClient::builder()
+            .endpoint(TESTNET_RPC_URL, None)
+            .network(Network::Testnet)
+            .build()
+            .expect("testnet rpc client")
+    }
+
+    fn call<R>(
+        &self,
+        method: &'static str,
+        target: Target,
+        params: serde_json::Result<Value>,
+    ) -> impl Future<Output = Result<R, RpcError>> + Send + 'static
+    where
+        R: DeserializeOwned + Send + 'static,
+    {
+        let rpc = self.clone();
+        async move {
+            let req_json = serde_json::json!({
+                "id": rpc.id.fetch_add(1, Ordering::Relaxed),
+                "jsonrpc": "2.0",
+                "method": method,
+                "params": params.map_err(RpcError::Encode)?,
+            });
+            let body = rpc.post(method, target, &req_json).await?;
+            let output = serde_json::from_slice::<Output>(&body).map_err(RpcError::Decode)?;
+            serde_json::from_value::<R>(output_result(output)?).map_err(RpcError::Decode)
+        }
+    }
+
+    /// Send calls of the same method in JSON-RPC batches, the results are in the same order as `params`
+    ///
+    /// note: calls are split into several batches by `max_batch_size`, and any failed call fails the whole
+    fn call_batch<R>(
+        &self,
+        method: &'static str,
+        target: Target,
+        params: Vec<serde_json::Result<Value>>,
+    ) -> impl Future<Output = Result<Vec<R>, RpcError>> + Send + 'static
+    where
+        R: DeserializeOwned + Send + 'static,
+    {
+        let rpc = self.clone();
+        async move {
+            let params = params
+                .into_iter()
+                .collect::<serde_json::Result<Vec<_>>>()
+                .map_err(RpcError::Encode)?;
+            let mut results = Vec::with_capacity(params.len());
+            for chunk in params.chunks(rpc.max_batch_size) {
+                let first_id = rpc.id.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                let req_json = chunk
+                    .iter()
+                    .zip(first_id..)
+                    .map(|(params, id)| {
+                        serde_json::json!({
+                            "id": id,
+                            "jsonrpc": "2.0",
+                            "method": method,
+                            "params": params,
+                        })
+                    })
+                    .collect::<Value>();
+                let body = rpc.post(method, target, &req_json).await?;
+                // the node responds a single failure if the whole batch is rejected
+                let outputs =
+                    match serde_json::from_slice::<Response>(&body).map_err(RpcError::Decode)? {
+                        Response::Single(output) => vec![output],
+                        Response::Batch(outputs) => outputs,
+                    };
+                // the responses of batch are not guaranteed in order
+                let mut chunk_results = vec![None; chunk.len()];
+                for output in outputs {
+                    let index = match output.id() {
+                        Id::Num(id) => id.checked_sub(first_id).map(|v| v as usize),
+                        _ => None,
+                    };
+                    let result = output_result(output)?;
+                    if let Some(slot) = index.and_then(|i| chunk_results.get_mut(i)) {
+                        *slot = Some(result);
+                    }
+                }
+                for result in chunk_results {
+                    let result = result.ok_or(RpcError::Other(format!(
+                        "missing response in batch of {method}"
+                    )))?;
+                    results.push(serde_json::from_value::<R>(result).map_err(RpcError::Decode)?);
+                }
+            }
+            Ok(results)
+        }
+    }
+
+    /// Post the request to the first healthy endpoint, retry and fail over on failures of endpoint
+    async fn post(
+        &self,
+        method: &str,
+        target: Target,
+        req_json: &Value,
+    ) -> Result<Bytes, RpcError> {
+        let idempotent = self.retry.is_idempotent(method);
+        let mut backoff = self.retry.initial_backoff;
+        let mut retries = 0;
+        loop {
+            let index = self.endpoints.select();
+            let endpoint = self.endpoints.get(index);
+            let url = match target {
+                Target::CKB => endpoint.ckb_uri.clone(),
+                Target::Indexer => endpoint.indexer_uri.clone(),
+            };
+            let error = match request(&self.raw, url, req_json).await {
+                Ok(body) => {
+                    self.endpoints.mark_healthy(index);
+                    return Ok(body);
+                }
+                Err(error) => error,
+            };
+            if !error.is_retryable() {
+                return Err(error);
+            }
+            self.endpoints.mark_failed(index);
+            if retries >= self.retry.max_retries || !(idempotent || error.is_connect()) {
+                return Err(error);
+            }
+            retries += 1;
+            // switch to the next healthy endpoint immediately, otherwise wait for recovery
+            if !self.endpoints.has_healthy() {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(self.retry.max_backoff);
+            }
+        }
+    }
+}
+
+async fn request(client: &Client, url: Url, req_json: &Value) -> Result<Bytes, RpcError> {
+    let resp = client
+        .post(url)
+        .json(req_json)
+        .send()
+        .await
+        .map_err(RpcError::from_reqwest)?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        return Err(RpcError::HttpStatus {
+            status: status.as_u16(),
+            body,
+        });
+    }
+    resp.bytes().await.map_err(RpcError::from_reqwest)
+}
+
+fn output_result(output: Output) -> Result<Value, RpcError> {
+    match output {
+        Output::Success(success) => Ok(success.result),
+        Output::Failure(failure) => Err(RpcError::JsonRpc {
+            code: failure.error.code.code(),
+            message: failure.error.message,
+            data: failure.error.data,
+        }),
+    }
+}
+
+/// Builder of `RpcClient`, with per-call timeout, retry policy and failover endpoints
+///
+/// e.g. fall back to a local node when the public one throttles:
+/// ```ignore
+/// let rpc = RpcClient::builder()
+///     .endpoint(TESTNET_RPC_URL, None)
+///     .endpoint("http://127.0.0.1:8114", Some("http://127.0.0.1:8116"))
+///     .network(Network::Testnet)
+///     .timeout(Some(Duration::from_secs(10)))
+///     .max_retries(5)
+///     .build()?;
+/// ```
+pub struct RpcClientBuilder {
+    endpoints: Vec<(String, Option<String>)>,
+    network: Option<Network>,
+    timeout: Option<Duration>,
+    retry: RetryPolicy,
+    max_batch_size: usize,
+    cooldown: Duration,
+    locker: Option<CellLocker>,
+}
+
+impl Default for RpcClientBuilder {
+    fn default() -> Self {
+        RpcClientBuilder {
+            endpoints: vec![],
+            network: None,
+            timeout: Some(DEFAULT_TIMEOUT),
+            retry: RetryPolicy::default(),
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            cooldown: DEFAULT_COOLDOWN,
+            locker: None,
+        }
+    }
+}
+
+impl RpcClientBuilder {
+    /// Append an endpoint, the earlier ones are preferred and the later ones are failovers
+    ///
+    /// # Parameters
+    /// - `ckb_uri`: e.g. "http://127.0.0.1:8114"
+    /// - `indexer_uri`: e.g. "http://127.0.0.1:8116", if None, use `ckb_uri`
+    pub fn endpoint(mut self, ckb_uri: &str, indexer_uri: Option<&str>) -> Self {
+        self.endpoints
+            .push((ckb_uri.to_string(), indexer_uri.map(ToString::to_string)));
+        self
+    }
+
+    /// If not set, it's `Network::Custom` of the first endpoint
+    pub fn network(mut self, network: Network) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Timeout of each request, if None, no timeout
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retry how many times on throttling, timeout or unreachable endpoint, 0 means no retry
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.retry.max_retries = max_retries;
+        self
+    }
+
+    /// The delay between retries starts from `initial` and doubles up to `max`
+    ///
+    /// note: no delay if there's another healthy endpoint to fail over
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.retry.initial_backoff = initial;
+        self.retry.max_backoff = max;
+        self
+    }
+
+    /// Mark `send_transaction` safe to retry, which is only retried if the request never reached the node by default
+    ///
+    /// note: a resent transaction may be rejected by `PoolRejectedDuplicatedTransaction`
+    pub fn retry_send_transaction(mut self, retry: bool) -> Self {
+        self.retry.retry_send_transaction = retry;
+        self
+    }
+
+    /// Split batched calls into several requests if exceeded, some public endpoints limit the batch size
+    pub fn max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size.max(1);
+        self
+    }
+
+    /// How long a failed endpoint is skipped, it doubles on consecutive failures
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Share the cell locker among skeletons that built from the client and its clones, refer to `CellLocker`
+    pub fn cell_locker(mut self, locker: CellLocker) -> Self {
+        self.locker = Some(locker);
+        self
+    }
+
+    pub fn build(self) -> eyre::Result<RpcClient> {
+        let endpoints = self
+            .endpoints
+            .into_iter()
+            .map(|(ckb_uri, indexer_uri)| {
+                let ckb_uri = Url::parse(&ckb_uri)
+                    .map_err(|e| eyre::eyre!("invalid ckb uri \"{ckb_uri}\": {e}"))?;
+                let indexer_uri = match indexer_uri {
+                    Some(uri) => Url::parse(&uri)
+                        .map_err(|e| eyre::eyre!("invalid indexer uri \"{uri}\": {e}"))?,
+                    None => ckb_uri.clone(),
+                };
+                Ok(Endpoint {
+                    ckb_uri,
+                    indexer_uri,
+                })
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+        let Some(first) = endpoints.first() else {
+            return Err(eyre::eyre!("no rpc endpoint"));
+        };
+        let network = self
+            .network
+            .unwrap_or_else(|| Network::Custom(first.ckb_uri.clone()));
+        let mut raw = Client::builder();
+        if let Some(timeout) = self.timeout {
+            raw = raw.timeout(timeout);
+        }
+        Ok(RpcClient {
+            network,
+            raw: raw.build()?,
+            endpoints: Endpoints::new(endpoints, self.cooldown),
+            retry: self.retry,
+            max_batch_size: self.max_batch_size,
+            id: Arc::new(AtomicU64::new(0)),
+            locker: self.locker,
+        })
+    }
+}
+
+impl RPC for RpcClient {
+    fn network(&self) -> Network {
+        self.network.clone()
+    }
+
+    fn url(&self) -> (String, String) {
+        let endpoint = self.endpoints.get(self.endpoints.select());
+        (
+            endpoint.ckb_uri.to_string(),
+            endpoint.indexer_uri.to_string(),
+        )
+    }
+
+    fn cell_locker(&self) -> Option<&CellLocker> {
+        self.locker.as_ref()
+    }
+
+    fn get_live_cell(&self, out_point: &OutPoint, with_data: bool) -> Rpc<CellWithStatus> {
+        jsonrpc!(
+            "get_live_cell",
+            Target::CKB,
+            self,
+            CellWithStatus,
+            out_point,
+            with_data
+        )
+        .boxed()
+    }
+
+    fn get_cells(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Rpc<Pagination<Cell>> {
+        let order = Order::Asc;
+        let limit = Uint32::from(limit);
+
+        jsonrpc!(
+            "get_cells",
+            Target::Indexer,
+            self,
+            Pagination<Cell>,
+            search_key,
+            order,
+            limit,
+            cursor,
+        )
+        .boxed()
+    }
+
+    fn get_block_by_number(&self, number: BlockNumber) -> Rpc<Option<BlockView>> {
+        jsonrpc!(
+            "get_block_by_number",
+            Target::CKB,
+            self,
+            Option<BlockView>,
+            number
+        )
+        .boxed()
+    }
+
+    fn get_block(&self, hash: &H256) -> Rpc<Option<BlockView>> {
+        jsonrpc!("get_block", Target::CKB, self, Option<BlockView>, hash).boxed()
+    }
+
+    fn get_header(&self, hash: &H256) -> Rpc<Option<HeaderView>> {
+        jsonrpc!("get_header", Target::CKB, self, Option<HeaderView>, hash).boxed()
+    }
+
+    fn get_header_by_number(&self, number: BlockNumber) -> Rpc<Option<HeaderView>> {
+        jsonrpc!(
+            "get_header_by_number",
+            Target::CKB,
+            self,
+            Option<HeaderView>,
+            number
+        )
+        .boxed()
+    }
+
+    fn get_block_hash(&self, number: BlockNumber) -> Rpc<Option<H256>> {
+        jsonrpc!("get_block_hash", Target::CKB, self, Option<H256>, number).boxed()
+    }
+
+    fn get_tip_block_number(&self) -> Rpc<BlockNumber> {
+        jsonrpc!("get_tip_block_number", Target::CKB, self, BlockNumber).boxed()
+    }
+
+    fn get_tip_header(&self) -> Rpc<HeaderView> {
+        jsonrpc!("get_tip_header", Target::CKB, self, HeaderView).boxed()
+    }
+
+    fn tx_pool_info(&self) -> Rpc<TxPoolInfo> {
+        jsonrpc!("tx_pool_info", Target::CKB, self, TxPoolInfo).boxed()
+    }
+
+    fn get_transaction(&self, hash: &H256) -> Rpc<Option<TransactionWithStatusResponse>> {
+        jsonrpc!(
+            "get_transaction",
+            Target::CKB,
+            self,
+            Option<TransactionWithStatusResponse>,
+            hash
+        )
+        .boxed()
+    }
+
+    fn send_transaction(
+        &self,
+        tx: Transaction,
+        outputs_validator: Option<OutputsValidator>,
+    ) -> Rpc<H256> {
+        jsonrpc!(
+            "send_transaction",
+            Target::CKB,
+            self,
+            H256,
+            tx,
+            outputs_validator
+        )
+        .boxed()
+    }
+
+    fn get_live_cells(
+        &self,
+        out_points: Vec<OutPoint>,
+        with_data: bool,
+    ) -> Rpc<Vec<CellWithStatus>> {
+        let params = out_points
+            .into_iter()
+            .map(|out_point| serde_json::to_value((out_point, with_data)))
+            .collect();
+        self.call_batch("get_live_cell", Target::CKB, params)
+            .boxed()
+    }
+
+    fn get_headers(&self, hashes: Vec<H256>) -> Rpc<Vec<Option<HeaderView>>> {
+        let params = hashes
+            .into_iter()
+            .map(|hash| serde_json::to_value((hash,)))
+            .collect();
+        self.call_batch("get_header", Target::CKB, params).boxed()
+    }
+
+    fn get_headers_by_number(&self, numbers: Vec<BlockNumber>) -> Rpc<Vec<Option<HeaderView>>> {
+        let params = numbers
+            .into_iter()
+            .map(|number| serde_json::to_value((number,)))
+            .collect();
+        self.call_batch("get_header_by_number", Target::CKB, params)
+            .boxed()
+    }
+
+    fn get_transactions(
+        &self,
+        hashes: Vec<H256>,
+    ) -> Rpc<Vec<Option<TransactionWithStatusResponse>>> {
+        let params = hashes
+            .into_iter()
+            .map(|hash| serde_json::to_value((hash,)))
+            .collect();
+        self.call_batch("get_transaction", Target::CKB, params)
+            .boxed()
+    }
+}
+
+pub type Filter = Box<dyn Fn(&Cell) -> bool + Send + Sync>;
+
+/// A wrapper of get_cells rpc call, it will automatically cross over live cells in interation
+pub struct GetCellsIter<'a, T: RPC> {
+    rpc: &'a T,
+    search_key: SearchKey,
+    cursor: Option<JsonBytes>,
+    filter: Option<Filter>,
+}
+
+impl<'a, T: RPC> GetCellsIter<'a, T> {
+    pub fn new(rpc: &'a T, search_key: SearchKey) -> Self {
+        GetCellsIter {
+            rpc,
+            search_key,
+            cursor: None,
+            filter: None,
+        }
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Fetch next batch of live cells, the ones rejected by filter or reserved in cell locker are skipped
+    ///
+    /// note: return None only if there's no more cells in indexer
+    pub async fn next_batch(&mut self, limit: u32) -> eyre::Result<Option<Vec<Cell>>> {
+        loop {
+            let cells = self
+                .rpc
+                .get_cells(self.search_key.clone(), limit, self.cursor.clone())
+                .await?;
+            if cells.objects.is_empty() {
+                return Ok(None);
+            }
+            self.cursor = Some(cells.last_cursor);
+            let objects = cells
+                .objects
+                .into_iter()
+                .filter(|cell| self.filter.as_ref().map(|f| f(cell)).unwrap_or(true))
+                .filter(|cell| !self.is_locked(cell))
+                .collect::<Vec<_>>();
+            if !objects.is_empty() {
+                return Ok(Some(objects));
+            }
+        }
+    }
+
+    fn is_locked(&self, cell: &Cell) -> bool {
+        self.rpc.cell_locker().is_some_and(|locker| {
+            let out_point: packed::OutPoint = cell.out_point.clone().into();
+            locker.is_locked(&out_point)
+        })
+    }
+
+    pub async fn next(&mut self) -> eyre::Result<Option<Cell>> {
+        Ok(self.next_batch(1).await?.map(|v| v[0].clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::Mutex,
+        time::Instant,
+    };
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// Local HTTP server that answers the n-th JSON-RPC request by `handler`, and keeps the requests
+    struct MockServer {
+        url: String,
+        requests: Arc<Mutex<Vec<Value>>>,
+    }
+
+    impl MockServer {
+        fn start<F>(handler: F) -> Self
+        where
+            F: Fn(usize, &Value) -> (u16, Value) + Send + 'static,
+        {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let requests = Arc::new(Mutex::new(vec![]));
+            let received = requests.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(mut stream) = stream else {
+                        continue;
+                    };
+                    let Some(request) = read_request(&mut stream) else {
+                        continue;
+                    };
+                    let index = {
+                        let mut received = received.lock().unwrap();
+                        received.push(request.clone());
+                        received.len() - 1
+                    };
+                    let (status, body) = handler(index, &request);
+                    let body = body.to_string();
+                    let response = format!(
+                        "HTTP/1.1 {status} MOCK\r\ncontent-type: application/json\r\n\
+                         content-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    let _ = stream.write_all(response.as_bytes());
+                }
+            });
+            MockServer { url, requests }
+        }
+
+        fn requests(&self) -> Vec<Value> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    fn read_request(stream: &mut TcpStream) -> Option<Value> {
+        let mut reader = BufReader::new(stream);
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).ok()?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().ok()?;
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).ok()?;
+        serde_json::from_slice(&body).ok()
+    }
+
+    fn success(request: &Value, result: Value) -> Value {
+        json!({"jsonrpc": "2.0", "id": request["id"], "result": result})
+    }
+
+    fn failure(request: &Value, code: i64, message: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "error": {"code": code, "message": message, "data": "detail"},
+        })
+    }
+
+    fn client(urls: &[&str]) -> RpcClientBuilder {
+        urls.iter().fold(RpcClient::builder(), |builder, url| {
+            builder.endpoint(url, None)
+        })
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Runtime::new().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn json_rpc_error_is_typed() {
+        let server = MockServer::start(|_, request| {
+            (
+                200,
+                failure(request, -1107, "PoolRejectedDuplicatedTransaction"),
+            )
+        });
+        let rpc = client(&[&server.url]).build().unwrap();
+        let error = block_on(rpc.send_transaction(Transaction::default(), None)).unwrap_err();
+        let RpcError::JsonRpc {
+            code,
+            message,
+            data,
+        } = &error
+        else {
+            panic!("unexpected error: {error}");
+        };
+        assert_eq!(*code, -1107);
+        assert_eq!(message, "PoolRejectedDuplicatedTransaction");
+        assert_eq!(data, &Some(json!("detail")));
+        assert_eq!(
+            error.ckb_error_code(),
+            Some(CkbErrorCode::PoolRejectedDuplicatedTransaction)
+        );
+        assert_eq!(server.requests()[0]["method"], "send_transaction");
+    }
+
+    #[test]
+    fn bad_responses_are_typed() {
+        let server = MockServer::start(|index, request| match index {
+            0 => (200, success(request, json!("not a block number"))),
+            _ => (400, json!("bad request")),
+        });
+        let rpc = client(&[&server.url]).build().unwrap();
+        let error = block_on(rpc.get_tip_block_number()).unwrap_err();
+        assert!(matches!(error, RpcError::Decode(_)), "{error}");
+        let error = block_on(rpc.get_tip_block_number()).unwrap_err();
+        let RpcError::HttpStatus { status, body } = &error else {
+            panic!("unexpected error: {error}");
+        };
+        assert_eq!(*status, 400);
+        assert_eq!(body, "\"bad request\"");
+        // client errors are not retried
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn slow_response_is_timeout() {
+        let server = MockServer::start(|_, request| {
+            std::thread::sleep(Duration::from_millis(500));
+            (200, success(request, json!("0x1")))
+        });
+        let rpc = client(&[&server.url])
+            .timeout(Some(Duration::from_millis(50)))
+            .max_retries(0)
+            .build()
+            .unwrap();
+        let error = block_on(rpc.get_tip_block_number()).unwrap_err();
+        assert!(matches!(error, RpcError::Timeout), "{error}");
+    }
+
+    #[test]
+    fn retry_throttled_request_with_backoff() {
+        let server = MockServer::start(|index, request| match index {
+            0 | 1 => (429, json!("too many requests")),
+            _ => (200, success(request, json!("0x10"))),
+        });
+        let rpc = client(&[&server.url])
+            .max_retries(3)
+            .backoff(Duration::from_millis(40), Duration::from_millis(60))
+            .build()
+            .unwrap();
+        let start = Instant::now();
+        let tip = block_on(rpc.get_tip_block_number()).unwrap();
+        assert_eq!(u64::from(tip), 16);
+        assert_eq!(server.requests().len(), 3);
+        // the only endpoint is unhealthy, so wait 40ms and then 60ms
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn give_up_after_max_retries() {
+        let server = MockServer::start(|_, _| (503, json!("unavailable")));
+        let rpc = client(&[&server.url])
+            .max_retries(2)
+            .backoff(Duration::from_millis(1), Duration::from_millis(1))
+            .build()
+            .unwrap();
+        let error = block_on(rpc.get_tip_block_number()).unwrap_err();
+        assert!(matches!(error, RpcError::HttpStatus { status: 503, .. }));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn send_transaction_is_not_retried_by_default() {
+        let server = MockServer::start(|_, _| (503, json!("unavailable")));
+        let builder = || {
+            client(&[&server.url])
+                .max_retries(2)
+                .backoff(Duration::from_millis(1), Duration::from_millis(1))
+        };
+        let rpc = builder().build().unwrap();
+        assert!(block_on(rpc.send_transaction(Transaction::default(), None)).is_err());
+        assert_eq!(server.requests().len(), 1);
+        let rpc = builder().retry_send_transaction(true).build().unwrap();
+        assert!(block_on(rpc.send_transaction(Transaction::default(), None)).is_err());
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[test]
+    fn fail_over_to_next_endpoint() {
+        let failed = MockServer::start(|_, _| (502, json!("bad gateway")));
+        let healthy = MockServer::start(|_, request| (200, success(request, json!("0x1"))));
+        let rpc = client(&[&failed.url, &healthy.url])
+            .max_retries(1)
+            .backoff(Duration::from_secs(10), Duration::from_secs(10))
+            .build()
+            .unwrap();
+        let start = Instant::now();
+        block_on(rpc.get_tip_block_number()).unwrap();
+        // the failed endpoint is skipped during its cooldown
+        block_on(rpc.get_tip_block_number()).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(failed.requests().len(), 1);
+        assert_eq!(healthy.requests().len(), 2);
+        assert_eq!(rpc.url().0, format!("{}/", healthy.url));
+    }
+
+    #[test]
+    fn resend_transaction_if_endpoint_unreachable() {
+        // nothing listens on the port once the listener is dropped
+        let unreachable = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let healthy =
+            MockServer::start(|_, request| (200, success(request, json!(H256::default()))));
+        let rpc = client(&[&unreachable, &healthy.url])
+            .max_retries(1)
+            .build()
+            .unwrap();
+        let hash = block_on(rpc.send_transaction(Transaction::default(), None)).unwrap();
+        assert_eq!(hash, H256::default());
+        assert_eq!(healthy.requests().len(), 1);
+    }
+
+    /// Batch of echo calls, whose results are the first params
+    fn echo_batch(rpc: &RpcClient, count: u64) -> Result<Vec<u64>, RpcError> {
+        let params = (0..count).map(|i| Ok(json!([i]))).collect();
+        block_on(rpc.call_batch::<u64>("echo", Target::CKB, params))
+    }
+
+    fn echo(request: &Value) -> Value {
+        success(request, request["params"][0].clone())
+    }
+
+    #[test]
+    fn batch_responses_are_matched_by_id() {
+        let server = MockServer::start(|_, request| {
+            let mut outputs = request
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(echo)
+                .collect::<Vec<_>>();
+            outputs.reverse();
+            (200, Value::Array(outputs))
+        });
+        let rpc = client(&[&server.url]).max_batch_size(2).build().unwrap();
+        assert_eq!(echo_batch(&rpc, 5).unwrap(), vec![0, 1, 2, 3, 4]);
+        let sizes = server
+            .requests()
+            .iter()
+            .map(|v| v.as_array().unwrap().len())
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn batch_fails_on_missing_response() {
+        let server = MockServer::start(|index, request| {
+            let mut outputs = request
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(echo)
+                .collect::<Vec<_>>();
+            match index {
+                // short response
+                0 => {
+                    outputs.pop();
+                }
+                // response of an unknown id
+                _ => outputs[0]["id"] = json!(u64::MAX),
+            }
+            (200, Value::Array(outputs))
+        });
+        let rpc = client(&[&server.url]).build().unwrap();
+        for _ in 0..2 {
+            let error = echo_batch(&rpc, 3).unwrap_err();
+            assert!(matches!(error, RpcError::Other(_)), "{error}");
+            assert_eq!(error.to_string(), "missing response in batch of echo");
+        }
+    }
+
+    #[test]
+    fn batch_fails_on_any_failure() {
+        let server = MockServer::start(|index, request| match index {
+            // the whole batch is rejected
+            0 => (200, failure(&Value::Null, -32600, "Invalid request")),
+            _ => {
+                let mut outputs = request
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(echo)
+                    .collect::<Vec<_>>();
+                outputs[1] = failure(&request[1], -32602, "Invalid params");
+                (200, Value::Array(outputs))
+            }
+        });
+        let rpc = client(&[&server.url]).build().unwrap();
+        assert_eq!(echo_batch(&rpc, 3).unwrap_err().code(), Some(-32600));
+        assert_eq!(echo_batch(&rpc, 3).unwrap_err().code(), Some(-32602));
+    }
+}
@@ -0,0 +1,533 @@
+use std::fmt::Display;
+
+use async_trait::async_trait;
+use ckb_sdk::{
+    rpc::ckb_indexer::SearchMode,
+    traits::{CellQueryOptions, ValueRangeOption},
+};
+use ckb_types::{core::Capacity, packed::Script};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rpc::{GetCellsIter, RPC},
+    skeleton::{CellInputEx, TransactionSkeleton},
+};
+
+/// Default count of cells that fetched in one `get_cells` request
+pub const DEFAULT_BATCH_SIZE: u32 = 100;
+
+/// Default upper limit of candidates that loaded by the strategies requiring a global view
+pub const DEFAULT_MAX_CANDIDATES: usize = 1000;
+
+/// Pure capacity cells of balancer that fetched in batches, the ones already in skeleton are skipped
+pub struct Candidates<'a, T: RPC> {
+    iter: GetCellsIter<'a, T>,
+    excluded: Vec<CellInputEx>,
+    batch_size: u32,
+    exhausted: bool,
+}
+
+impl<'a, T: RPC> Candidates<'a, T> {
+    pub fn new(
+        rpc: &'a T,
+        skeleton: &TransactionSkeleton,
+        balancer: Script,
+        batch_size: u32,
+    ) -> Self {
+        let mut search_key = CellQueryOptions::new_lock(balancer);
+        search_key.secondary_script_len_range = Some(ValueRangeOption::new(0, 1));
+        search_key.data_len_range = Some(ValueRangeOption::new(0, 1));
+        search_key.script_search_mode = Some(SearchMode::Exact);
+        Candidates {
+            iter: GetCellsIter::new(rpc, search_key.into()),
+            excluded: skeleton.inputs.clone(),
+            batch_size,
+            exhausted: false,
+        }
+    }
+
+    /// Fetch next batch of candidates in indexer order, return None if no more cells
+    pub async fn next_batch(&mut self) -> Result<Option<Vec<CellInputEx>>> {
+        if self.exhausted {
+            return Ok(None);
+        }
+        let Some(cells) = self.iter.next_batch(self.batch_size).await? else {
+            self.exhausted = true;
+            return Ok(None);
+        };
+        let candidates = cells
+            .into_iter()
+            .map(|cell| CellInputEx::new_from_indexer_cell(cell, None))
+            .filter(|input| !self.excluded.contains(input))
+            .collect();
+        Ok(Some(candidates))
+    }
+
+    /// Fetch candidates in batches until `limit` is reached or no more cells
+    pub async fn collect(&mut self, limit: usize) -> Result<Vec<CellInputEx>> {
+        let mut candidates = vec![];
+        while candidates.len() < limit {
+            let Some(batch) = self.next_batch().await? else {
+                break;
+            };
+            candidates.extend(batch);
+        }
+        candidates.truncate(limit);
+        Ok(candidates)
+    }
+}
+
+fn capacity_of(input: &CellInputEx) -> u64 {
+    input.output.capacity().as_u64()
+}
+
+fn total_capacity(inputs: &[CellInputEx]) -> u64 {
+    inputs.iter().map(capacity_of).sum()
+}
+
+/// Error of selection that names the shortfall, all capacities are in shannons
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InsufficientCapacity {
+    pub needed: u64,
+    pub available: u64,
+}
+
+impl InsufficientCapacity {
+    pub fn shortfall(&self) -> u64 {
+        self.needed.saturating_sub(self.available)
+    }
+}
+
+impl Display for InsufficientCapacity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "insufficient capacity to balance: needed {}, available {}, shortfall {} shannons",
+            self.needed,
+            self.available,
+            self.shortfall()
+        )
+    }
+}
+
+impl std::error::Error for InsufficientCapacity {}
+
+pub(crate) fn insufficient(needed: Capacity, available: u64) -> eyre::Report {
+    InsufficientCapacity {
+        needed: needed.as_u64(),
+        available,
+    }
+    .into()
+}
+
+/// Take candidates in order until the needed capacity is covered
+fn take_until_covered(candidates: Vec<CellInputEx>, needed: Capacity) -> Result<Vec<CellInputEx>> {
+    let mut selected = vec![];
+    let mut sum = 0u64;
+    for candidate in candidates {
+        if sum >= needed.as_u64() {
+            break;
+        }
+        sum += capacity_of(&candidate);
+        selected.push(candidate);
+    }
+    if sum < needed.as_u64() {
+        return Err(insufficient(needed, sum));
+    }
+    Ok(selected)
+}
+
+fn largest_first(mut candidates: Vec<CellInputEx>, needed: Capacity) -> Result<Vec<CellInputEx>> {
+    candidates.sort_by_key(|v| std::cmp::Reverse(capacity_of(v)));
+    take_until_covered(candidates, needed)
+}
+
+/// Strategy to choose input cells of balancer that cover the needed capacity
+#[async_trait]
+pub trait CoinSelector: Send + Sync {
+    /// Return the selected cells, whose total capacity must be no less than `needed`
+    async fn select<T: RPC>(
+        &self,
+        candidates: &mut Candidates<'_, T>,
+        needed: Capacity,
+    ) -> Result<Vec<CellInputEx>>;
+}
+
+/// Choose cells in indexer order, which stops fetching once the needed capacity is covered
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct FirstFit {}
+
+#[async_trait]
+impl CoinSelector for FirstFit {
+    async fn select<T: RPC>(
+        &self,
+        candidates: &mut Candidates<'_, T>,
+        needed: Capacity,
+    ) -> Result<Vec<CellInputEx>> {
+        let mut selected = vec![];
+        while total_capacity(&selected) < needed.as_u64() {
+            let Some(batch) = candidates.next_batch().await? else {
+                break;
+            };
+            selected.extend(batch);
+        }
+        take_until_covered(selected, needed)
+    }
+}
+
+/// Choose the largest cells first, which produces the fewest inputs
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LargestFirst {
+    pub max_candidates: usize,
+}
+
+impl Default for LargestFirst {
+    fn default() -> Self {
+        LargestFirst {
+            max_candidates: DEFAULT_MAX_CANDIDATES,
+        }
+    }
+}
+
+#[async_trait]
+impl CoinSelector for LargestFirst {
+    async fn select<T: RPC>(
+        &self,
+        candidates: &mut Candidates<'_, T>,
+        needed: Capacity,
+    ) -> Result<Vec<CellInputEx>> {
+        largest_first(candidates.collect(self.max_candidates).await?, needed)
+    }
+}
+
+/// Choose the smallest single cell that covers the needed capacity, otherwise fall back to largest-first
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SmallestSufficient {
+    pub max_candidates: usize,
+}
+
+impl Default for SmallestSufficient {
+    fn default() -> Self {
+        SmallestSufficient {
+            max_candidates: DEFAULT_MAX_CANDIDATES,
+        }
+    }
+}
+
+#[async_trait]
+impl CoinSelector for SmallestSufficient {
+    async fn select<T: RPC>(
+        &self,
+        candidates: &mut Candidates<'_, T>,
+        needed: Capacity,
+    ) -> Result<Vec<CellInputEx>> {
+        let candidates = candidates.collect(self.max_candidates).await?;
+        let sufficient = candidates
+            .iter()
+            .filter(|v| capacity_of(v) >= needed.as_u64())
+            .min_by_key(|v| capacity_of(v));
+        match sufficient {
+            Some(candidate) => Ok(vec![candidate.clone()]),
+            None => largest_first(candidates, needed),
+        }
+    }
+}
+
+/// Search cells whose total capacity lands in `[needed, needed + tolerance]`, so that almost nothing is left as
+/// change, otherwise fall back to largest-first
+///
+/// note: the depth-first search visits at most `max_tries` nodes
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BranchAndBound {
+    pub max_candidates: usize,
+    /// Acceptable excess capacity in shannons
+    pub tolerance: u64,
+    pub max_tries: usize,
+}
+
+impl Default for BranchAndBound {
+    fn default() -> Self {
+        BranchAndBound {
+            max_candidates: DEFAULT_MAX_CANDIDATES,
+            tolerance: 0,
+            max_tries: 100_000,
+        }
+    }
+}
+
+/// Depth-first search over capacities in descending order, which prunes the branches out of range
+struct Search<'a> {
+    capacities: &'a [u64],
+    /// `remaining[i]` is the total capacity of `capacities[i..]`
+    remaining: Vec<u64>,
+    range: (u64, u64),
+    selected: Vec<usize>,
+    tries: usize,
+    max_tries: usize,
+}
+
+impl Search<'_> {
+    fn dfs(&mut self, index: usize, sum: u64) -> bool {
+        self.tries += 1;
+        if sum >= self.range.0 {
+            return sum <= self.range.1;
+        }
+        if index == self.capacities.len()
+            || sum + self.remaining[index] < self.range.0
+            || self.tries > self.max_tries
+        {
+            return false;
+        }
+        let with_current = sum + self.capacities[index];
+        if with_current <= self.range.1 {
+            self.selected.push(index);
+            if self.dfs(index + 1, with_current) {
+                return true;
+            }
+            self.selected.pop();
+        }
+        self.dfs(index + 1, sum)
+    }
+}
+
+impl BranchAndBound {
+    fn search(&self, capacities: &[u64], needed: u64) -> Option<Vec<usize>> {
+        let mut remaining = vec![0u64; capacities.len() + 1];
+        for i in (0..capacities.len()).rev() {
+            remaining[i] = remaining[i + 1] + capacities[i];
+        }
+        let mut search = Search {
+            capacities,
+            remaining,
+            range: (needed, needed.saturating_add(self.tolerance)),
+            selected: vec![],
+            tries: 0,
+            max_tries: self.max_tries,
+        };
+        search.dfs(0, 0).then_some(search.selected)
+    }
+}
+
+#[async_trait]
+impl CoinSelector for BranchAndBound {
+    async fn select<T: RPC>(
+        &self,
+        candidates: &mut Candidates<'_, T>,
+        needed: Capacity,
+    ) -> Result<Vec<CellInputEx>> {
+        let mut candidates = candidates.collect(self.max_candidates).await?;
+        candidates.sort_by_key(|v| std::cmp::Reverse(capacity_of(v)));
+        let capacities = candidates.iter().map(capacity_of).collect::<Vec<_>>();
+        match self.search(&capacities, needed.as_u64()) {
+            Some(indices) => Ok(indices
+                .into_iter()
+                .map(|index| candidates[index].clone())
+                .collect()),
+            None => largest_first(candidates, needed),
+        }
+    }
+}
+
+/// Absorb at most `max_dust_cells` cells below `dust_threshold` in smallest-first, then cover the rest in largest-first
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Consolidate {
+    pub max_candidates: usize,
+    pub max_dust_cells: usize,
+    /// Capacity in shannons below which a cell is treated as dust
+    pub dust_threshold: u64,
+}
+
+#[async_trait]
+impl CoinSelector for Consolidate {
+    async fn select<T: RPC>(
+        &self,
+        candidates: &mut Candidates<'_, T>,
+        needed: Capacity,
+    ) -> Result<Vec<CellInputEx>> {
+        let (mut dust, others): (Vec<_>, Vec<_>) = candidates
+            .collect(self.max_candidates)
+            .await?
+            .into_iter()
+            .partition(|v| capacity_of(v) < self.dust_threshold);
+        dust.sort_by_key(capacity_of);
+        dust.truncate(self.max_dust_cells);
+        let dust_capacity = total_capacity(&dust);
+        if dust_capacity >= needed.as_u64() {
+            return Ok(dust);
+        }
+        let available = dust_capacity + total_capacity(&others);
+        if available < needed.as_u64() {
+            return Err(insufficient(needed, available));
+        }
+        let rest = Capacity::shannons(needed.as_u64() - dust_capacity);
+        dust.extend(largest_first(others, rest)?);
+        Ok(dust)
+    }
+}
+
+/// Serializable choice of builtin strategies, which is used in operations and recipes
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoinSelection {
+    FirstFit(FirstFit),
+    LargestFirst(LargestFirst),
+    SmallestSufficient(SmallestSufficient),
+    BranchAndBound(BranchAndBound),
+    Consolidate(Consolidate),
+}
+
+impl Default for CoinSelection {
+    fn default() -> Self {
+        CoinSelection::FirstFit(FirstFit {})
+    }
+}
+
+#[async_trait]
+impl CoinSelector for CoinSelection {
+    async fn select<T: RPC>(
+        &self,
+        candidates: &mut Candidates<'_, T>,
+        needed: Capacity,
+    ) -> Result<Vec<CellInputEx>> {
+        match self {
+            CoinSelection::FirstFit(selector) => selector.select(candidates, needed).await,
+            CoinSelection::LargestFirst(selector) => selector.select(candidates, needed).await,
+            CoinSelection::SmallestSufficient(selector) => {
+                selector.select(candidates, needed).await
+            }
+            CoinSelection::BranchAndBound(selector) => selector.select(candidates, needed).await,
+            CoinSelection::Consolidate(selector) => selector.select(candidates, needed).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ckb_types::{
+        core::ScriptHashType,
+        packed::{CellOutput, OutPoint},
+        prelude::{Builder, Entity, Pack, Unpack},
+        H256,
+    };
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{simulation::FakeRpcClient, skeleton::CellOutputEx};
+
+    fn balancer() -> Script {
+        Script::new_builder()
+            .code_hash(H256::default().pack())
+            .hash_type(ScriptHashType::Type.into())
+            .build()
+    }
+
+    /// Fake rpc with pure capacity cells of balancer in the given order, the out point index is the order
+    fn rpc(capacities: &[u64]) -> FakeRpcClient {
+        let mut rpc = FakeRpcClient::default();
+        for (index, capacity) in capacities.iter().enumerate() {
+            let out_point = OutPoint::new_builder().index((index as u32).pack()).build();
+            let output = CellOutput::new_builder()
+                .capacity(Capacity::shannons(*capacity).pack())
+                .lock(balancer())
+                .build();
+            rpc.insert_fake_cell(out_point, CellOutputEx::new(output, vec![]), None);
+        }
+        rpc
+    }
+
+    /// Select from the fake cells, return the indices of selected cells
+    fn select<S: CoinSelector>(selector: &S, capacities: &[u64], needed: u64) -> Result<Vec<u32>> {
+        let rpc = rpc(capacities);
+        let skeleton = TransactionSkeleton::default();
+        let mut candidates = Candidates::new(&rpc, &skeleton, balancer(), 2);
+        let selected = block_on(selector.select(&mut candidates, Capacity::shannons(needed)))?;
+        Ok(selected
+            .into_iter()
+            .map(|v| v.input.previous_output().index().unpack())
+            .collect())
+    }
+
+    fn shortfall(result: Result<Vec<u32>>) -> u64 {
+        result
+            .unwrap_err()
+            .downcast::<InsufficientCapacity>()
+            .unwrap()
+            .shortfall()
+    }
+
+    const CAPACITIES: &[u64] = &[30, 10, 50, 20, 5];
+
+    #[test]
+    fn first_fit_takes_in_indexer_order() {
+        assert_eq!(select(&FirstFit {}, CAPACITIES, 35).unwrap(), vec![0, 1]);
+        assert!(select(&FirstFit {}, CAPACITIES, 0).unwrap().is_empty());
+        assert_eq!(shortfall(select(&FirstFit {}, CAPACITIES, 120)), 5);
+    }
+
+    #[test]
+    fn largest_first_takes_fewest_cells() {
+        let selector = LargestFirst::default();
+        assert_eq!(select(&selector, CAPACITIES, 60).unwrap(), vec![2, 0]);
+        assert_eq!(shortfall(select(&selector, CAPACITIES, 200)), 85);
+    }
+
+    #[test]
+    fn smallest_sufficient_prefers_single_cell() {
+        let selector = SmallestSufficient::default();
+        assert_eq!(select(&selector, CAPACITIES, 25).unwrap(), vec![0]);
+        // no single cell covers, fall back to largest-first
+        assert_eq!(select(&selector, CAPACITIES, 70).unwrap(), vec![2, 0]);
+    }
+
+    #[test]
+    fn branch_and_bound_finds_exact_match() {
+        let selector = BranchAndBound::default();
+        assert_eq!(select(&selector, CAPACITIES, 35).unwrap(), vec![0, 4]);
+        assert_eq!(select(&selector, CAPACITIES, 65).unwrap(), vec![2, 1, 4]);
+        // no exact match, fall back to largest-first
+        assert_eq!(
+            select(&selector, CAPACITIES, 112).unwrap(),
+            vec![2, 0, 3, 1, 4]
+        );
+        assert_eq!(shortfall(select(&selector, CAPACITIES, 116)), 1);
+    }
+
+    #[test]
+    fn branch_and_bound_search_respects_tolerance_and_tries() {
+        let capacities = [50, 30, 20, 10, 5];
+        let mut selector = BranchAndBound::default();
+        assert_eq!(selector.search(&capacities, 64), None);
+        selector.tolerance = 1;
+        assert_eq!(selector.search(&capacities, 64), Some(vec![0, 3, 4]));
+        selector.max_tries = 1;
+        assert_eq!(selector.search(&capacities, 64), None);
+    }
+
+    #[test]
+    fn consolidate_absorbs_dust_first() {
+        let selector = Consolidate {
+            max_candidates: DEFAULT_MAX_CANDIDATES,
+            max_dust_cells: 2,
+            dust_threshold: 25,
+        };
+        assert_eq!(select(&selector, CAPACITIES, 10).unwrap(), vec![4, 1]);
+        assert_eq!(select(&selector, CAPACITIES, 40).unwrap(), vec![4, 1, 2]);
+        assert_eq!(shortfall(select(&selector, CAPACITIES, 100)), 5);
+    }
+
+    #[test]
+    fn candidates_skip_inputs_of_skeleton() {
+        let rpc = rpc(CAPACITIES);
+        let mut skeleton = TransactionSkeleton::default();
+        let mut candidates = Candidates::new(&rpc, &skeleton, balancer(), 2);
+        let first = block_on(candidates.next_batch()).unwrap().unwrap();
+        skeleton.inputs.extend(first);
+        let mut candidates = Candidates::new(&rpc, &skeleton, balancer(), 2);
+        let rest = block_on(candidates.collect(DEFAULT_MAX_CANDIDATES)).unwrap();
+        assert_eq!(
+            rest.iter().map(capacity_of).collect::<Vec<_>>(),
+            vec![50, 20, 5]
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Serde adaptors for the foreign types that used in operations, which don't implement serde natively
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Hex encoded bytes with or without `0x` prefix
+pub mod hex_bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        format!("0x{}", hex::encode(value)).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        hex::decode(value.trim_start_matches("0x")).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Optional hex encoded bytes
+pub mod option_hex_bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value
+            .as_ref()
+            .map(|v| format!("0x{}", hex::encode(v)))
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|v| hex::decode(v.trim_start_matches("0x")))
+            .transpose()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// CKB address in its string form, e.g. "ckt1qzda0cr08m85hc8jlnfp3zer7xulejywt49kt2rr0vthywaa50xwsq..."
+pub mod address {
+    use super::*;
+    use ckb_sdk::Address;
+
+    pub fn serialize<S: Serializer>(value: &Address, serializer: S) -> Result<S::Ok, S::Error> {
+        value.to_string().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Address, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Celldep type in form of "code" or "dep_group"
+pub mod dep_type {
+    use super::*;
+    use ckb_types::core::DepType;
+
+    pub fn serialize<S: Serializer>(value: &DepType, serializer: S) -> Result<S::Ok, S::Error> {
+        ckb_jsonrpc_types::DepType::from(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DepType, D::Error> {
+        ckb_jsonrpc_types::DepType::deserialize(deserializer).map(Into::into)
+    }
+}
+
+/// A list of hex encoded secp256k1 private keys
+///
+/// note: keys are never written out, serialization only emits `REDACTED` placeholders, so keep real
+/// keys out of recipe files and fill them in by `${name}` variables at loading time instead
+pub mod secret_keys {
+    use super::*;
+    use secp256k1::SecretKey;
+
+    pub const REDACTED: &str = "<redacted>";
+
+    pub fn serialize<S: Serializer>(value: &[SecretKey], serializer: S) -> Result<S::Ok, S::Error> {
+        vec![REDACTED; value.len()].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<SecretKey>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|v| {
+                if v == REDACTED {
+                    return Err(serde::de::Error::custom(
+                        "secret key is redacted, provide it by a recipe variable",
+                    ));
+                }
+                v.trim_start_matches("0x")
+                    .parse()
+                    .map_err(serde::de::Error::custom)
+            })
+            .collect()
+    }
+}
+
+/// Distinguish the missing field from the `null` field, which are `None` and `Some(None)` respectively
+///
+/// note: must be used along with `#[serde(default)]`, and `skip_serializing_if = "Option::is_none"` for serialization
+pub mod double_option {
+    use super::*;
+
+    pub fn serialize<T, S>(value: &Option<Option<T>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+        S: Serializer,
+    {
+        value
+            .as_ref()
+            .and_then(Option::as_ref)
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Option::<T>::deserialize(deserializer).map(Some)
+    }
+}
+
+/// Optional since value in either raw `u64` or typed form, e.g. `1000` or `{ "block_number": { "value": 1000 } }`
+pub mod option_since {
+    use super::*;
+    use crate::skeleton::SinceEx;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Raw(u64),
+        Typed(SinceEx),
+    }
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<SinceEx>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SinceEx>, D::Error> {
+        let since = Option::<Repr>::deserialize(deserializer)?.map(|v| match v {
+            Repr::Raw(value) => value.into(),
+            Repr::Typed(since) => since,
+        });
+        Ok(since)
+    }
+}
@@ -1,7 +1,7 @@
 use std::{collections::HashMap, sync::Arc};
 
 use ckb_chain_spec::consensus::{Consensus, ConsensusBuilder};
-use ckb_script::{TransactionScriptsVerifier, TxVerifyEnv};
+use ckb_script::{ScriptGroupType, TransactionScriptsVerifier, TxVerifyEnv};
 use ckb_traits::{CellDataProvider, ExtensionProvider, HeaderProvider};
 use ckb_types::{
     bytes::Bytes,
@@ -14,9 +14,12 @@ use ckb_types::{
     prelude::{Pack, Unpack},
     H256,
 };
-use eyre::Result;
+use eyre::{eyre, Result};
 
-use crate::{instruction::Instruction, operation::Log, rpc::RPC, skeleton::TransactionSkeleton};
+use crate::{
+    fee::FeeEstimator, instruction::Instruction, operation::Log, rpc::RPC,
+    skeleton::TransactionSkeleton,
+};
 
 mod operation;
 mod rpc;
@@ -159,7 +162,7 @@ impl TransactionSimulator {
             println!("transaction skeleton: {}", skeleton);
         }
         log.into_iter()
-            .for_each(|(name, msg)| println!("[calculate log] {name} -> {}", hex::encode(msg)));
+            .for_each(|entry| println!("[calculate log] {entry}"));
         let headers = skeleton
             .headerdeps
             .iter()
@@ -179,6 +182,60 @@ impl TransactionSimulator {
         });
         Ok(verifier.verify(max_cycles)?)
     }
+
+    /// Collect the cycles of an unsigned skeleton by running its script groups one by one
+    ///
+    /// # Parameters
+    /// - `skeleton`: the skeleton with placeholder witnesses, refer to `FeeEstimator::placeholder`
+    /// - `estimator`: provides the cycles of lock groups that fail before signing
+    /// - `max_cycles`: the cycles limit of all script groups
+    ///
+    /// note: failed type groups are not tolerated, since they won't pass after signing either
+    pub async fn estimate_cycles<T: RPC>(
+        &self,
+        rpc: &T,
+        skeleton: &TransactionSkeleton,
+        estimator: &FeeEstimator,
+        max_cycles: Cycle,
+    ) -> Result<Cycle> {
+        let headers = skeleton
+            .headerdeps
+            .iter()
+            .map(|v| (v.block_hash.clone(), v.header.clone()))
+            .collect();
+        let resolved_tx = {
+            let mut resolved_tx = skeleton.clone().into_resolved_transaction(rpc).await?;
+            complete_resolved_tx(self.outpoint_to_headers.clone(), &mut resolved_tx);
+            Arc::new(resolved_tx)
+        };
+        let context = Context::new(resolved_tx.clone(), headers);
+        let consensus = Arc::new(self.consensus.clone());
+        let env = Arc::new(self.env.clone());
+        let verifier = TransactionScriptsVerifier::new(resolved_tx, context, consensus, env);
+        let mut cycles: Cycle = 0;
+        for (group_type, script_hash, group) in verifier.groups_with_type() {
+            let remained_cycles = max_cycles.saturating_sub(cycles);
+            let group_cycles =
+                match verifier.verify_single(group_type, script_hash, remained_cycles) {
+                    Ok(group_cycles) => group_cycles,
+                    Err(error) => match group_type {
+                        ScriptGroupType::Lock => {
+                            estimator.lock_cycles(&group.script).ok_or(eyre!(
+                                "lock group {script_hash} failed without estimated cycles: {error}"
+                            ))?
+                        }
+                        ScriptGroupType::Type => {
+                            return Err(eyre!("type group {script_hash} failed: {error}"))
+                        }
+                    },
+                };
+            cycles = cycles.saturating_add(group_cycles);
+            if cycles > max_cycles {
+                return Err(eyre!("cycles {cycles} exceed the limit {max_cycles}"));
+            }
+        }
+        Ok(cycles)
+    }
 }
 
 #[allow(clippy::mutable_key_type)]
@@ -203,3 +260,142 @@ fn complete_resolved_tx(
         complete_cell_meta(resolved_cell_dep);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ckb_hash::blake2b_256;
+    use ckb_sdk::{constants::SIGHASH_TYPE_HASH, util::blake160};
+    use ckb_system_scripts::BUNDLED_CELL;
+    use ckb_types::{
+        packed::{CellInput, CellOutput, Script},
+        prelude::{Builder, Entity},
+    };
+    use futures::executor::block_on;
+    use secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+    use super::*;
+    use crate::{
+        fee::{WitnessEstimator, SECP256K1_SIGNATURE_CYCLES},
+        operation::{basic::AddSecp256k1SighashSignatures, Operation},
+        skeleton::{CellOutputEx, ScriptEx},
+    };
+
+    /// Type id args of the system cells in genesis block, which are the same on every chain
+    fn genesis_type_id_args(output_index: u64) -> H256 {
+        let mut preimage = CellInput::new_cellbase_input(0).as_slice().to_vec();
+        preimage.extend(output_index.to_le_bytes());
+        blake2b_256(preimage).into()
+    }
+
+    fn system_cell(name: &str) -> Vec<u8> {
+        BUNDLED_CELL
+            .get(&format!("specs/cells/{name}"))
+            .unwrap()
+            .to_vec()
+    }
+
+    /// Estimate unsigned locks as four signatures, but leave the placeholder size to others
+    struct HeavyLockEstimator;
+
+    impl WitnessEstimator for HeavyLockEstimator {
+        fn lock_size(&self, _: &Script) -> Option<usize> {
+            None
+        }
+
+        fn lock_cycles(&self, _: &Script) -> Option<Cycle> {
+            Some(SECP256K1_SIGNATURE_CYCLES * 4)
+        }
+    }
+
+    /// Skeleton that spends an unsigned secp256k1_sighash_all input
+    fn unsigned_skeleton(rpc: &FakeRpcClient, key: &SecretKey) -> (TransactionSkeleton, ScriptEx) {
+        let pubkey = PublicKey::from_secret_key(&Secp256k1::new(), key);
+        let lock_script =
+            ScriptEx::new_type(SIGHASH_TYPE_HASH, blake160(&pubkey.serialize()).0.to_vec());
+        let operations: Vec<Box<dyn Operation<FakeRpcClient>>> = vec![
+            Box::new(AddFakeContractCelldep {
+                name: "secp256k1_blake160_sighash_all".to_string(),
+                contract_data: system_cell("secp256k1_blake160_sighash_all"),
+                type_id_args: Some(genesis_type_id_args(1)),
+            }),
+            Box::new(AddFakeContractCelldep {
+                name: "secp256k1_data".to_string(),
+                contract_data: system_cell("secp256k1_data"),
+                type_id_args: None,
+            }),
+            Box::new(AddFakeInputCell {
+                lock_script: lock_script.clone(),
+                type_script: None,
+                data: vec![],
+                capacity: 0,
+                absolute_capacity: false,
+            }),
+        ];
+        let (mut skeleton, mut log) = (TransactionSkeleton::default(), Log::new());
+        block_on(Instruction::new(operations).run(rpc, &mut skeleton, &mut log)).unwrap();
+        let output = CellOutput::new_builder()
+            .lock(lock_script.clone().to_script_unchecked())
+            .build();
+        skeleton.output(CellOutputEx::new(output, vec![]));
+        (skeleton, lock_script)
+    }
+
+    #[test]
+    fn estimate_cycles_tolerates_unsigned_lock_groups() {
+        let rpc = FakeRpcClient::default();
+        let key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let (mut skeleton, lock_script) = unsigned_skeleton(&rpc, &key);
+        let simulator = TransactionSimulator::default();
+        let estimator = FeeEstimator::default();
+        let placeholder = estimator.placeholder(&skeleton);
+        let estimate = |skeleton, estimator| {
+            block_on(simulator.estimate_cycles(&rpc, skeleton, estimator, DEFUALT_MAX_CYCLES))
+        };
+
+        // the unsigned lock group fails, and is charged by the estimator instead
+        assert_eq!(
+            estimate(&placeholder, &estimator).unwrap(),
+            SECP256K1_SIGNATURE_CYCLES
+        );
+        let heavy = FeeEstimator::new().estimator(HeavyLockEstimator);
+        assert_eq!(
+            estimate(&placeholder, &heavy).unwrap(),
+            SECP256K1_SIGNATURE_CYCLES * 4
+        );
+        let limited =
+            simulator.estimate_cycles(&rpc, &placeholder, &heavy, SECP256K1_SIGNATURE_CYCLES);
+        assert!(block_on(limited).is_err());
+
+        // the signed lock group really runs, which stays under the estimation
+        let operation = Box::new(AddSecp256k1SighashSignatures {
+            user_lock_scripts: vec![lock_script],
+            user_private_keys: vec![key],
+        });
+        block_on(operation.run(&rpc, &mut skeleton, &mut Log::new())).unwrap();
+        let cycles = estimate(&skeleton, &estimator).unwrap();
+        assert!(cycles > 0 && cycles <= SECP256K1_SIGNATURE_CYCLES);
+    }
+
+    #[test]
+    fn cycle_aware_fee_charges_heavy_locks() {
+        let rpc = FakeRpcClient::default();
+        let key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let (skeleton, _) = unsigned_skeleton(&rpc, &key);
+        let fee = |estimator: FeeEstimator| {
+            block_on(estimator.estimate_fee(&rpc, &skeleton, 1000))
+                .unwrap()
+                .as_u64()
+        };
+        let size_fee = fee(FeeEstimator::new().estimator(HeavyLockEstimator));
+        let cycle_fee = fee(FeeEstimator::new()
+            .estimator(HeavyLockEstimator)
+            .cycle_aware(TransactionSimulator::default(), DEFUALT_MAX_CYCLES));
+        // 8M cycles weigh more than the serialized size
+        assert!(cycle_fee > size_fee);
+        // a single signature weighs less than the serialized size
+        let light_fee =
+            fee(FeeEstimator::new()
+                .cycle_aware(TransactionSimulator::default(), DEFUALT_MAX_CYCLES));
+        assert_eq!(light_fee, size_fee);
+    }
+}
@@ -2,14 +2,15 @@ use std::collections::HashMap;
 
 use ckb_jsonrpc_types::{
     BlockNumber, BlockView, CellData, CellInfo, CellWithStatus, HeaderView, JsonBytes, OutPoint,
-    OutputsValidator, Status, Transaction, TransactionWithStatusResponse, TxPoolInfo, TxStatus,
+    OutputsValidator, ResponseFormat, Status, Transaction, TransactionView,
+    TransactionWithStatusResponse, TxPoolInfo, TxStatus,
 };
 use ckb_sdk::rpc::ckb_indexer::{Cell, Pagination, ScriptType, SearchKey, SearchMode};
 use ckb_types::{core, packed, prelude::Unpack, H256};
-use eyre::eyre;
 
 use crate::{
-    rpc::{Rpc, RPC},
+    locker::CellLocker,
+    rpc::{Rpc, RpcError, RPC},
     skeleton::CellOutputEx,
 };
 
@@ -19,6 +20,7 @@ pub struct FakeProvider {
     pub fake_headers: HashMap<H256, HeaderView>,
     pub fake_outpoint_headers: HashMap<OutPoint, core::HeaderView>,
     pub fake_transaction_status: HashMap<H256, TxStatus>,
+    pub fake_pool_transactions: HashMap<H256, (core::TransactionView, u64)>,
     pub fake_feerate: u64,
     pub fake_tipnumber: u64,
     pub fate_tipheader: HeaderView,
@@ -151,6 +153,21 @@ impl FakeProvider {
     }
 
     fn get_transaction_by_hash(&self, hash: &H256) -> Option<TransactionWithStatusResponse> {
+        if let Some((tx, min_replace_fee)) = self.fake_pool_transactions.get(hash) {
+            return Some(TransactionWithStatusResponse {
+                transaction: Some(ResponseFormat::json(TransactionView::from(tx.clone()))),
+                cycles: None,
+                time_added_to_pool: None,
+                fee: None,
+                min_replace_fee: Some((*min_replace_fee).into()),
+                tx_status: TxStatus {
+                    status: Status::Pending,
+                    block_hash: None,
+                    block_number: None,
+                    reason: None,
+                },
+            });
+        }
         self.fake_transaction_status
             .get(hash)
             .map(|status| TransactionWithStatusResponse {
@@ -167,9 +184,15 @@ impl FakeProvider {
 #[derive(Clone, Default)]
 pub struct FakeRpcClient {
     pub fake_provider: FakeProvider,
+    pub cell_locker: Option<CellLocker>,
 }
 
 impl FakeRpcClient {
+    pub fn with_cell_locker(mut self, locker: CellLocker) -> Self {
+        self.cell_locker = Some(locker);
+        self
+    }
+
     pub fn set_fake_tip(&mut self, tip_number: u64, tip_header: HeaderView) -> &mut Self {
         self.fake_provider.fake_tipnumber = tip_number;
         self.fake_provider.fate_tipheader = tip_header;
@@ -223,6 +246,18 @@ impl FakeRpcClient {
         self
     }
 
+    /// Put a pending transaction into the fake tx pool, which is replaceable by paying at least `min_replace_fee`
+    pub fn insert_fake_pool_tx(
+        &mut self,
+        tx: core::TransactionView,
+        min_replace_fee: u64,
+    ) -> &mut Self {
+        self.fake_provider
+            .fake_pool_transactions
+            .insert(tx.hash().unpack(), (tx, min_replace_fee));
+        self
+    }
+
     pub fn insert_fake_header(&mut self, header: core::HeaderView) -> &mut Self {
         self.fake_provider
             .fake_headers
@@ -244,14 +279,18 @@ unsafe impl Sync for FakeRpcClient {}
 
 impl RPC for FakeRpcClient {
     fn url(&self) -> (String, String) {
-        unimplemented!("fake url method")
+        (String::new(), String::new())
+    }
+
+    fn cell_locker(&self) -> Option<&CellLocker> {
+        self.cell_locker.as_ref()
     }
 
     fn get_live_cell(&self, out_point: &OutPoint, _with_data: bool) -> Rpc<CellWithStatus> {
         let cell = self
             .fake_provider
             .get_cell_by_outpoint(out_point)
-            .ok_or(eyre!("no live cell found"));
+            .ok_or(RpcError::Other("no live cell found".to_string()));
         Box::pin(async move { cell })
     }
 
@@ -1,7 +1,7 @@
-use std::{fmt::Display, time::Duration};
+use std::{fmt::Display, fs, path::Path, time::Duration};
 
 use ckb_hash::{blake2b_256, Blake2bBuilder};
-use ckb_jsonrpc_types::{OutputsValidator, Status};
+use ckb_jsonrpc_types::{JsonBytes, OutputsValidator};
 use ckb_sdk::{
     constants::TYPE_ID_CODE_HASH,
     rpc::ckb_indexer::{Cell, SearchMode},
@@ -11,16 +11,24 @@ use ckb_sdk::{
 use ckb_types::{
     core::{
         cell::{CellMetaBuilder, ResolvedTransaction},
-        Capacity, DepType, HeaderView, ScriptHashType, TransactionView,
+        Capacity, DepType, EpochNumberWithFraction, HeaderView, ScriptHashType, TransactionView,
     },
     packed::{Bytes, CellDep, CellInput, CellOutput, OutPoint, OutPointVec, Script, WitnessArgs},
     prelude::{Builder, Entity, Pack, Unpack},
     H256,
 };
 use eyre::{eyre, Result};
-use futures::future::join_all;
-
-use crate::rpc::{GetCellsIter, Network, RPC};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    bump::FeeBump,
+    fee::FeeEstimator,
+    locker::{self, Reservations},
+    rpc::{GetCellsIter, Network, RPC},
+    selection::{insufficient, Candidates, CoinSelector, FirstFit, DEFAULT_BATCH_SIZE},
+    serde_ext,
+    watch::{TxProgress, WaitError, WaitOptions, Watcher},
+};
 
 /// A wrapper of packed Script
 ///
@@ -189,6 +197,81 @@ impl From<(String, Vec<u8>)> for ScriptEx {
     }
 }
 
+/// Serde form of ScriptEx, which accepts a json script, a celldep reference or a ckb address
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ScriptExRepr {
+    Script(ckb_jsonrpc_types::Script),
+    Reference {
+        celldep: String,
+        #[serde(default)]
+        args: JsonBytes,
+    },
+    Address(String),
+}
+
+impl Serialize for ScriptEx {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let repr = match self.clone() {
+            ScriptEx::Script(..) => ScriptExRepr::Script(self.clone().to_script_unchecked().into()),
+            ScriptEx::Reference(celldep, args) => ScriptExRepr::Reference {
+                celldep,
+                args: JsonBytes::from_vec(args),
+            },
+        };
+        repr.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ScriptEx {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        match ScriptExRepr::deserialize(deserializer)? {
+            ScriptExRepr::Script(script) => Ok(Script::from(script).into()),
+            ScriptExRepr::Reference { celldep, args } => {
+                Ok(ScriptEx::Reference(celldep, args.into_bytes().to_vec()))
+            }
+            ScriptExRepr::Address(address) => address
+                .parse::<Address>()
+                .map(Into::into)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// Fetch live cells in one batch request, fail if any of them is not live
+pub(crate) async fn get_live_cells<T: RPC>(
+    rpc: &T,
+    out_points: &[OutPoint],
+    with_data: bool,
+) -> Result<Vec<(CellOutput, Option<Vec<u8>>)>> {
+    let live_cells = rpc
+        .get_live_cells(
+            out_points.iter().cloned().map(Into::into).collect(),
+            with_data,
+        )
+        .await?;
+    if live_cells.len() != out_points.len() {
+        return Err(eyre!(
+            "expect {} live cells, but rpc returned {}",
+            out_points.len(),
+            live_cells.len()
+        ));
+    }
+    out_points
+        .iter()
+        .zip(live_cells)
+        .map(|(out_point, live_cell)| {
+            let live_cell = live_cell.cell.ok_or(eyre!(
+                "cell not found at ({}:{})",
+                hex::encode(out_point.tx_hash().raw_data()),
+                Unpack::<u32>::unpack(&out_point.index())
+            ))?;
+            let data = live_cell.data.map(|v| v.content.into_bytes().to_vec());
+            Ok((live_cell.output.into(), data))
+        })
+        .collect()
+}
+
 /// CellInput for transaction skeleton, which contains output cell and data
 #[derive(Debug, Clone)]
 pub struct CellInputEx {
@@ -250,6 +333,24 @@ impl CellInputEx {
         Ok(Self::new(input, output, data))
     }
 
+    /// Batched `new_from_outpoint`, which fetches all of previous outputs in one batch request
+    pub async fn new_batch_from_inputs<T: RPC>(
+        rpc: &T,
+        inputs: Vec<CellInput>,
+        with_data: bool,
+    ) -> Result<Vec<Self>> {
+        let out_points = inputs
+            .iter()
+            .map(|v| v.previous_output())
+            .collect::<Vec<_>>();
+        let live_cells = get_live_cells(rpc, &out_points, with_data).await?;
+        Ok(inputs
+            .into_iter()
+            .zip(live_cells)
+            .map(|(input, (output, data))| Self::new(input, output, data))
+            .collect())
+    }
+
     /// Initialize a CellInputEx from the ckb-indexer specific cell
     pub fn new_from_indexer_cell(indexer_cell: Cell, since: Option<u64>) -> Self {
         let input = CellInput::new_builder()
@@ -260,6 +361,17 @@ impl CellInputEx {
         Self::new(input, indexer_cell.output.into(), data)
     }
 
+    /// Replace the since field with typed value
+    pub fn with_since(mut self, since: SinceEx) -> Result<Self> {
+        self.input = self.input.as_builder().since(since.value()?.pack()).build();
+        Ok(self)
+    }
+
+    /// Decode the since field into typed value
+    pub fn since(&self) -> Result<SinceEx> {
+        SinceEx::from_value(self.input.since().unpack())
+    }
+
     /// Turn a CelldepEx into CellInputEx
     pub fn new_from_celldep(celldep: &CellDepEx, since: Option<u64>) -> Self {
         let input = CellInput::new_builder()
@@ -275,6 +387,203 @@ impl CellInputEx {
     }
 }
 
+/// Typed since field of CellInput, which is encoded into the raw value with flags, refer to RFC 0017
+///
+/// e.g. unlock after block 1000, or 7 days after the input cell is committed:
+/// ```ignore
+/// let since = SinceEx::block_number(1000);
+/// let since = SinceEx::timestamp(7 * 24 * 3600).relative();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SinceEx {
+    /// Raw since value that flags are already encoded, e.g. copied from an existing transaction
+    Raw(u64),
+    /// Block number, or the number of blocks after the input cell is committed if relative
+    BlockNumber {
+        value: u64,
+        #[serde(default)]
+        relative: bool,
+    },
+    /// Epoch number with fraction `index / length`
+    Epoch {
+        number: u64,
+        index: u64,
+        length: u64,
+        #[serde(default)]
+        relative: bool,
+    },
+    /// Median time of the past 37 blocks in seconds, or the seconds elapsed after the input cell is committed
+    Timestamp {
+        value: u64,
+        #[serde(default)]
+        relative: bool,
+    },
+}
+
+const SINCE_RELATIVE_FLAG: u64 = 1 << 63;
+const SINCE_METRIC_MASK: u64 = 0x6000_0000_0000_0000;
+const SINCE_RESERVED_MASK: u64 = 0x1f00_0000_0000_0000;
+const SINCE_VALUE_MASK: u64 = 0x00ff_ffff_ffff_ffff;
+const SINCE_EPOCH_FLAG: u64 = 0x2000_0000_0000_0000;
+const SINCE_TIMESTAMP_FLAG: u64 = 0x4000_0000_0000_0000;
+
+impl SinceEx {
+    pub fn block_number(value: u64) -> Self {
+        SinceEx::BlockNumber {
+            value,
+            relative: false,
+        }
+    }
+
+    pub fn epoch(number: u64, index: u64, length: u64) -> Self {
+        SinceEx::Epoch {
+            number,
+            index,
+            length,
+            relative: false,
+        }
+    }
+
+    pub fn epoch_with_fraction(epoch: EpochNumberWithFraction) -> Self {
+        Self::epoch(epoch.number(), epoch.index(), epoch.length())
+    }
+
+    pub fn timestamp(value: u64) -> Self {
+        SinceEx::Timestamp {
+            value,
+            relative: false,
+        }
+    }
+
+    /// Turn into relative form, which counts from the block that the input cell is committed
+    pub fn relative(self) -> Self {
+        match self {
+            SinceEx::Raw(value) => SinceEx::Raw(value | SINCE_RELATIVE_FLAG),
+            SinceEx::BlockNumber { value, .. } => SinceEx::BlockNumber {
+                value,
+                relative: true,
+            },
+            SinceEx::Epoch {
+                number,
+                index,
+                length,
+                ..
+            } => SinceEx::Epoch {
+                number,
+                index,
+                length,
+                relative: true,
+            },
+            SinceEx::Timestamp { value, .. } => SinceEx::Timestamp {
+                value,
+                relative: true,
+            },
+        }
+    }
+
+    /// Decode raw since value into typed form, error if flags are invalid
+    pub fn from_value(since: u64) -> Result<Self> {
+        if since & SINCE_RESERVED_MASK != 0 {
+            return Err(eyre!("invalid since flags: {since:#x}"));
+        }
+        let relative = since & SINCE_RELATIVE_FLAG != 0;
+        let value = since & SINCE_VALUE_MASK;
+        let since = match since & SINCE_METRIC_MASK {
+            0 => SinceEx::BlockNumber { value, relative },
+            SINCE_EPOCH_FLAG => {
+                let epoch = EpochNumberWithFraction::from_full_value_unchecked(value);
+                SinceEx::Epoch {
+                    number: epoch.number(),
+                    index: epoch.index(),
+                    length: epoch.length(),
+                    relative,
+                }
+            }
+            SINCE_TIMESTAMP_FLAG => SinceEx::Timestamp { value, relative },
+            _ => return Err(eyre!("invalid since metric: {since:#x}")),
+        };
+        Ok(since)
+    }
+
+    /// Encode into raw since value, error if the value overflows or the epoch fraction is invalid
+    pub fn value(&self) -> Result<u64> {
+        let (flags, value, relative) = match *self {
+            SinceEx::Raw(since) => {
+                Self::from_value(since)?;
+                return Ok(since);
+            }
+            SinceEx::BlockNumber { value, relative } => (0, value, relative),
+            SinceEx::Epoch {
+                number,
+                index,
+                length,
+                relative,
+            } => {
+                if length == 0
+                    || index >= length
+                    || number >= EpochNumberWithFraction::NUMBER_MAXIMUM_VALUE
+                    || length >= EpochNumberWithFraction::LENGTH_MAXIMUM_VALUE
+                {
+                    return Err(eyre!("invalid since epoch: {number} {index}/{length}"));
+                }
+                let epoch = EpochNumberWithFraction::new_unchecked(number, index, length);
+                (SINCE_EPOCH_FLAG, epoch.full_value(), relative)
+            }
+            SinceEx::Timestamp { value, relative } => (SINCE_TIMESTAMP_FLAG, value, relative),
+        };
+        if value > SINCE_VALUE_MASK {
+            return Err(eyre!("since value overflows: {value}"));
+        }
+        let relative = if relative { SINCE_RELATIVE_FLAG } else { 0 };
+        Ok(relative | flags | value)
+    }
+
+    pub fn is_relative(&self) -> bool {
+        match *self {
+            SinceEx::Raw(since) => since & SINCE_RELATIVE_FLAG != 0,
+            SinceEx::BlockNumber { relative, .. }
+            | SinceEx::Epoch { relative, .. }
+            | SinceEx::Timestamp { relative, .. } => relative,
+        }
+    }
+}
+
+/// Raw since value, so that the call sites of the former `Option<u64>` since fields keep working with `.into()`
+impl From<u64> for SinceEx {
+    fn from(value: u64) -> Self {
+        SinceEx::Raw(value)
+    }
+}
+
+impl Display for SinceEx {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let since = match self {
+            SinceEx::Raw(since) => match Self::from_value(*since) {
+                Ok(since) => since,
+                Err(_) => return write!(f, "raw {since:#x}"),
+            },
+            since => *since,
+        };
+        let prefix = if since.is_relative() {
+            "relative"
+        } else {
+            "absolute"
+        };
+        match since {
+            SinceEx::BlockNumber { value, .. } => write!(f, "{prefix} block number {value}"),
+            SinceEx::Epoch {
+                number,
+                index,
+                length,
+                ..
+            } => write!(f, "{prefix} epoch {number} {index}/{length}"),
+            SinceEx::Timestamp { value, .. } => write!(f, "{prefix} timestamp {value}"),
+            SinceEx::Raw(_) => unreachable!("decoded"),
+        }
+    }
+}
+
 /// CellOutput for transaction skeleton, which contains cell data
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CellOutputEx {
@@ -365,6 +674,8 @@ pub struct CellDepEx {
     pub celldep: CellDep,
     pub output: CellOutputEx,
     pub with_data: bool,
+    /// Names of the duplicated CellDeps that merged into this one, which are still available for `ScriptEx::Reference`
+    pub aliases: Vec<String>,
 }
 
 impl PartialEq for CellDepEx {
@@ -382,6 +693,7 @@ impl CellDepEx {
                 celldep: cell_dep,
                 output: CellOutputEx::new(output, data),
                 with_data: true,
+                aliases: Vec::new(),
             }
         } else {
             CellDepEx {
@@ -389,6 +701,25 @@ impl CellDepEx {
                 celldep: cell_dep,
                 output: CellOutputEx::new(output, Vec::new()),
                 with_data: false,
+                aliases: Vec::new(),
+            }
+        }
+    }
+
+    /// Check if the cell dep can be referred by `name`, including the names of merged duplicates
+    pub fn is_named(&self, name: &str) -> bool {
+        self.name == name || self.aliases.iter().any(|v| v == name)
+    }
+
+    /// Take over the names of a duplicated cell dep, and its data if not resolved yet
+    pub fn merge(&mut self, duplicate: CellDepEx) {
+        if !self.with_data && duplicate.with_data {
+            self.output = duplicate.output;
+            self.with_data = true;
+        }
+        for name in std::iter::once(duplicate.name).chain(duplicate.aliases) {
+            if !self.is_named(&name) {
+                self.aliases.push(name);
             }
         }
     }
@@ -423,6 +754,24 @@ impl CellDepEx {
         Ok(Self::new(name, cell_dep, output, data))
     }
 
+    /// Batched `new_from_outpoint`, which fetches all of cells in one batch request
+    pub async fn new_batch_from_celldeps<T: RPC>(
+        rpc: &T,
+        celldeps: Vec<(String, CellDep)>,
+        with_data: bool,
+    ) -> Result<Vec<Self>> {
+        let out_points = celldeps
+            .iter()
+            .map(|(_, v)| v.out_point())
+            .collect::<Vec<_>>();
+        let live_cells = get_live_cells(rpc, &out_points, with_data).await?;
+        Ok(celldeps
+            .into_iter()
+            .zip(live_cells)
+            .map(|((name, celldep), (output, data))| Self::new(name, celldep, output, data))
+            .collect())
+    }
+
     /// Initialize a CellDepEx from the ckb-indexer specific cell
     pub fn new_from_indexer_cell(name: String, indexer_cell: Cell, dep_type: DepType) -> Self {
         let out_point = indexer_cell.out_point.into();
@@ -461,6 +810,8 @@ pub struct WitnessEx {
     pub lock: Vec<u8>,
     pub input_type: Vec<u8>,
     pub output_type: Vec<u8>,
+    /// Whether `input_type` carries an u64 index of HeaderDeps in little-endian, e.g. DAO withdraw phase two
+    pub headerdep_reference: bool,
 }
 
 impl Default for WitnessEx {
@@ -471,6 +822,7 @@ impl Default for WitnessEx {
             lock: Vec::new(),
             input_type: Vec::new(),
             output_type: Vec::new(),
+            headerdep_reference: false,
         }
     }
 }
@@ -484,7 +836,29 @@ impl WitnessEx {
             lock,
             input_type,
             output_type,
+            headerdep_reference: false,
+        }
+    }
+
+    /// Initialize a WitnessArgsEx whose `input_type` refers to the index of HeaderDeps
+    pub fn new_headerdep_reference(headerdep_index: usize) -> Self {
+        WitnessEx {
+            headerdep_reference: true,
+            ..WitnessEx::new(
+                vec![],
+                (headerdep_index as u64).to_le_bytes().to_vec(),
+                vec![],
+            )
+        }
+    }
+
+    /// Get the referred index of HeaderDeps, return None if not a headerdep reference
+    pub fn headerdep_index(&self) -> Option<usize> {
+        if !self.headerdep_reference {
+            return None;
         }
+        let index: [u8; 8] = self.input_type.clone().try_into().ok()?;
+        Some(u64::from_le_bytes(index) as usize)
     }
 
     /// Initialize a WitnessArgsEx and mark it non-traditional
@@ -495,9 +869,15 @@ impl WitnessEx {
             lock: plain_bytes,
             input_type: Vec::new(),
             output_type: Vec::new(),
+            headerdep_reference: false,
         }
     }
 
+    /// Check if all of fields are empty
+    pub fn is_empty(&self) -> bool {
+        self.lock.is_empty() && self.input_type.is_empty() && self.output_type.is_empty()
+    }
+
     /// Turn into packed WitnessArgs
     pub fn into_witness_args(self) -> WitnessArgs {
         let bytes_opt = |bytes: Vec<u8>| {
@@ -589,6 +969,72 @@ impl HeaderDepEx {
             .ok_or(eyre!("block not found"))?;
         HeaderDepEx::new(rpc, block_hash, None).await
     }
+
+    /// Batched `new`, which fetches all of headers in one batch request
+    pub async fn new_batch<T: RPC>(
+        rpc: &T,
+        headerdeps: Vec<(H256, Option<OutPoint>)>,
+    ) -> Result<Vec<Self>> {
+        let block_hashes = headerdeps.iter().map(|(v, _)| v.clone()).collect();
+        let headers = rpc.get_headers(block_hashes).await?;
+        headerdeps
+            .into_iter()
+            .zip(headers)
+            .map(|((block_hash, outpoint), header)| {
+                let header = header.ok_or(eyre!("header not found: {block_hash:#x}"))?;
+                Ok(HeaderDepEx {
+                    block_hash,
+                    header: header.into(),
+                    cellinput_outpoint: outpoint,
+                })
+            })
+            .collect()
+    }
+
+    /// Batched `new_from_outpoint`, which resolves all of headers in two batch requests
+    pub async fn new_batch_from_outpoints<T: RPC>(
+        rpc: &T,
+        outpoints: Vec<OutPoint>,
+    ) -> Result<Vec<Self>> {
+        let tx_hashes = outpoints.iter().map(|v| v.tx_hash().unpack()).collect();
+        let txs = rpc.get_transactions(tx_hashes).await?;
+        let headerdeps = outpoints
+            .into_iter()
+            .zip(txs)
+            .map(|(outpoint, tx)| {
+                let tx = tx.ok_or(eyre!("transaction not found by input outpoint"))?;
+                let block_hash = tx
+                    .tx_status
+                    .block_hash
+                    .ok_or(eyre!("transaction not in block"))?;
+                Ok((block_hash, Some(outpoint)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        HeaderDepEx::new_batch(rpc, headerdeps).await
+    }
+
+    /// Batched `new_from_block_number`, which fetches all of headers in one batch request
+    pub async fn new_batch_from_block_numbers<T: RPC>(
+        rpc: &T,
+        block_numbers: Vec<u64>,
+    ) -> Result<Vec<Self>> {
+        let numbers = block_numbers.iter().map(|v| (*v).into()).collect();
+        let headers = rpc.get_headers_by_number(numbers).await?;
+        block_numbers
+            .into_iter()
+            .zip(headers)
+            .map(|(block_number, header)| {
+                let header: HeaderView = header
+                    .ok_or(eyre!("block not found: {block_number}"))?
+                    .into();
+                Ok(HeaderDepEx {
+                    block_hash: header.hash().unpack(),
+                    header,
+                    cellinput_outpoint: None,
+                })
+            })
+            .collect()
+    }
 }
 
 impl PartialEq for HeaderDepEx {
@@ -605,6 +1051,8 @@ pub struct TransactionSkeleton {
     pub celldeps: Vec<CellDepEx>,
     pub witnesses: Vec<WitnessEx>,
     pub headerdeps: Vec<HeaderDepEx>,
+    /// Cells reserved in the cell locker of rpc while building, which are released on drop unless sent
+    pub reservations: Reservations,
 }
 
 impl TransactionSkeleton {
@@ -629,18 +1077,8 @@ impl TransactionSkeleton {
         rpc: &T,
         tx: &TransactionView,
     ) -> Result<&mut Self> {
-        let inputs = tx
-            .inputs()
-            .into_iter()
-            .map(|input| {
-                let out_point = input.previous_output();
-                let tx_hash: H256 = out_point.tx_hash().unpack();
-                let index: u32 = out_point.index().unpack();
-                let since: u64 = input.since().unpack();
-                CellInputEx::new_from_outpoint(rpc, tx_hash, index, Some(since), true)
-            })
-            .collect::<Vec<_>>();
-        self.inputs = join_all(inputs).await.into_iter().collect::<Result<_>>()?;
+        let inputs = tx.inputs().into_iter().collect();
+        self.inputs = CellInputEx::new_batch_from_inputs(rpc, inputs, true).await?;
         Ok(self)
     }
 
@@ -654,19 +1092,9 @@ impl TransactionSkeleton {
             .cell_deps()
             .into_iter()
             .enumerate()
-            .map(|(i, cell_dep)| {
-                let name = format!("unknown-{i}");
-                let out_point = cell_dep.out_point();
-                let tx_hash: H256 = out_point.tx_hash().unpack();
-                let index: u32 = out_point.index().unpack();
-                let dep_type = cell_dep.dep_type().try_into().expect("dep type");
-                CellDepEx::new_from_outpoint(rpc, name, tx_hash, index, dep_type, false)
-            })
-            .collect::<Vec<_>>();
-        self.celldeps = join_all(celldeps)
-            .await
-            .into_iter()
-            .collect::<Result<_>>()?;
+            .map(|(i, cell_dep)| (format!("unknown-{i}"), cell_dep))
+            .collect();
+        self.celldeps = CellDepEx::new_batch_from_celldeps(rpc, celldeps, false).await?;
         Ok(self)
     }
 
@@ -676,13 +1104,11 @@ impl TransactionSkeleton {
         rpc: &T,
         tx: &TransactionView,
     ) -> Result<&mut Self> {
-        let mut headerdeps = vec![];
-        for header_dep in tx.header_deps_iter() {
-            let block_hash: H256 = header_dep.unpack();
-            let header_dep = HeaderDepEx::new(rpc, block_hash, None).await?;
-            headerdeps.push(header_dep);
-        }
-        self.headerdeps = headerdeps;
+        let headerdeps = tx
+            .header_deps_iter()
+            .map(|header_dep| (header_dep.unpack(), None))
+            .collect();
+        self.headerdeps = HeaderDepEx::new_batch(rpc, headerdeps).await?;
         Ok(self)
     }
 
@@ -704,6 +1130,10 @@ impl TransactionSkeleton {
             .witnesses()
             .into_iter()
             .map(|witness| {
+                // the rest witnesses of a lock group are usually left empty
+                if witness.raw_data().is_empty() {
+                    return Ok(WitnessEx::default());
+                }
                 let witness_args = WitnessArgs::from_slice(&witness.raw_data())
                     .map_err(|_| eyre!("invalid witness args"))?;
                 let lock = witness_args.lock().to_opt().unwrap_or_default();
@@ -758,6 +1188,14 @@ impl TransactionSkeleton {
             if self.contains_input(&cell_input) {
                 continue;
             }
+            if let Some(locker) = rpc.cell_locker() {
+                if !self
+                    .reservations
+                    .try_lock(locker, cell_input.input.previous_output())
+                {
+                    continue;
+                }
+            }
             self.inputs.push(cell_input);
             find_available_input = true;
             break;
@@ -811,7 +1249,7 @@ impl TransactionSkeleton {
     /// note: if index is `usize::MAX`, return the last output cell
     pub fn get_output_by_index(&self, output_index: usize) -> Result<&CellOutputEx> {
         if output_index == usize::MAX {
-            return self.outputs.last().ok_or(eyre!("no output"));
+            self.outputs.last().ok_or(eyre!("no output"))
         } else {
             self.outputs
                 .get(output_index)
@@ -862,10 +1300,11 @@ impl TransactionSkeleton {
         self.outputs.pop().ok_or(eyre!("no output to pop"))
     }
 
-    /// Push a single cell dep
+    /// Push a single cell dep, the name of a duplicated one is kept as alias of the existing one
     pub fn celldep(&mut self, cell_dep: CellDepEx) -> &mut Self {
-        if !self.celldeps.contains(&cell_dep) {
-            self.celldeps.push(cell_dep);
+        match self.celldeps.iter_mut().find(|v| *v == &cell_dep) {
+            Some(exist) => exist.merge(cell_dep),
+            None => self.celldeps.push(cell_dep),
         }
         self
     }
@@ -877,15 +1316,13 @@ impl TransactionSkeleton {
 
     /// Check if cell dep exists by name
     pub fn get_celldep_by_name(&self, name: &str) -> Option<&CellDepEx> {
-        self.celldeps.iter().find(|celldep| celldep.name == name)
+        self.celldeps.iter().find(|celldep| celldep.is_named(name))
     }
 
     /// Push a batch of cell deps
     pub fn celldeps(&mut self, cell_deps: Vec<CellDepEx>) -> &mut Self {
         cell_deps.into_iter().for_each(|v| {
-            if !self.celldeps.contains(&v) {
-                self.celldeps.push(v);
-            }
+            self.celldep(v);
         });
         self
     }
@@ -965,6 +1402,99 @@ impl TransactionSkeleton {
         (input_groups, output_groups)
     }
 
+    /// Normalize transaction skeleton before signing or sending, which is idempotent and includes:
+    /// 1. merge CellDeps that point to the same out point with the same dep type, even if names are different
+    /// 2. collapse duplicated HeaderDeps and remap the headerdep references in Witnesses
+    /// 3. align Witnesses with Inputs, and move the lock of each lock group to its first witness
+    ///
+    /// note: the names of merged CellDeps are kept as aliases, so `ScriptEx::Reference` still works
+    pub fn normalize(&mut self) -> &mut Self {
+        self.normalize_celldeps()
+            .normalize_headerdeps()
+            .normalize_witnesses()
+    }
+
+    /// Merge CellDeps that point to the same out point with the same dep type, the first one is kept and takes over
+    /// the names of others as aliases
+    pub fn normalize_celldeps(&mut self) -> &mut Self {
+        let mut celldeps: Vec<CellDepEx> = vec![];
+        for celldep in self.celldeps.drain(..) {
+            match celldeps.iter_mut().find(|v| *v == &celldep) {
+                Some(exist) => exist.merge(celldep),
+                None => celldeps.push(celldep),
+            }
+        }
+        self.celldeps = celldeps;
+        self
+    }
+
+    /// Collapse duplicated HeaderDeps and remap the headerdep references in Witnesses
+    pub fn normalize_headerdeps(&mut self) -> &mut Self {
+        let mut headerdeps: Vec<HeaderDepEx> = vec![];
+        let remap = self
+            .headerdeps
+            .drain(..)
+            .map(
+                |headerdep| match headerdeps.iter().position(|v| v == &headerdep) {
+                    Some(index) => index,
+                    None => {
+                        headerdeps.push(headerdep);
+                        headerdeps.len() - 1
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+        self.headerdeps = headerdeps;
+        self.witnesses.iter_mut().for_each(|witness| {
+            if let Some(index) = witness.headerdep_index().and_then(|v| remap.get(v)) {
+                witness.input_type = (*index as u64).to_le_bytes().to_vec();
+            }
+        });
+        self
+    }
+
+    /// Align Witnesses with Inputs, the non-traditional witnesses and the redundant non-empty ones are moved to the
+    /// end, and the missing ones are padded with empty witness
+    pub fn normalize_witnesses(&mut self) -> &mut Self {
+        let mut aligned = vec![];
+        let mut extras = vec![];
+        for witness in self.witnesses.drain(..) {
+            if !witness.traditional {
+                extras.push(witness);
+            } else if aligned.len() < self.inputs.len() {
+                aligned.push(witness);
+            } else if !witness.is_empty() {
+                extras.push(witness);
+            }
+        }
+        aligned.resize_with(self.inputs.len(), Default::default);
+        let mut lock_groups: Vec<(Script, Vec<usize>)> = vec![];
+        for (i, input) in self.inputs.iter().enumerate() {
+            let lock_script = input.output.lock_script();
+            match lock_groups
+                .iter_mut()
+                .find(|(lock, _)| lock == &lock_script)
+            {
+                Some((_, indices)) => indices.push(i),
+                None => lock_groups.push((lock_script, vec![i])),
+            }
+        }
+        for (_, indices) in lock_groups {
+            let first = indices[0];
+            if !aligned[first].lock.is_empty() {
+                continue;
+            }
+            if let Some(&index) = indices.iter().find(|&&i| !aligned[i].lock.is_empty()) {
+                aligned[first].lock = std::mem::take(&mut aligned[index].lock);
+                aligned[first].empty = false;
+                aligned[index].empty = aligned[index].is_empty();
+            }
+        }
+        aligned.extend(extras);
+        self.witnesses = aligned;
+        self
+    }
+
     /// Calculate type id based on the first input cell and output index
     pub fn calc_type_id(&self, out_index: usize) -> Result<H256> {
         let Some(first_input) = self.inputs.first() else {
@@ -988,7 +1518,7 @@ impl TransactionSkeleton {
                 .iter()
                 .enumerate()
                 .find_map(|(index, celldep)| {
-                    if &celldep.name == name {
+                    if celldep.is_named(name) {
                         Some((index, celldep))
                     } else {
                         None
@@ -1003,11 +1533,7 @@ impl TransactionSkeleton {
                 let expected_code_hash =
                     match (script.hash_type(), &celldep.output, celldep.with_data) {
                         (Ok(ScriptHashType::Type), output, _) => {
-                            if let Some(type_hash) = output.calc_type_hash() {
-                                type_hash
-                            } else {
-                                H256::default()
-                            }
+                            output.calc_type_hash().unwrap_or_default()
                         }
                         (Ok(_), output, true) => output.data_hash(),
                         _ => H256::default(),
@@ -1022,11 +1548,22 @@ impl TransactionSkeleton {
     }
 
     /// Calculate transaction fee based on current minimal fee rate and additional fee rate
+    ///
+    /// note: unsigned secp256k1_sighash_all lock groups are counted in with placeholder witnesses
     pub async fn fee<T: RPC>(&self, rpc: &T, additinal_fee_rate: u64) -> Result<Capacity> {
+        self.fee_with_estimator(rpc, additinal_fee_rate, &FeeEstimator::default())
+            .await
+    }
+
+    /// Calculate transaction fee with placeholder witnesses that estimated by `estimator`
+    pub async fn fee_with_estimator<T: RPC>(
+        &self,
+        rpc: &T,
+        additinal_fee_rate: u64,
+        estimator: &FeeEstimator,
+    ) -> Result<Capacity> {
         let fee_rate = u64::from(rpc.tx_pool_info().await?.min_fee_rate) + additinal_fee_rate;
-        let tx = self.clone().into_transaction_view();
-        let tx_fee = tx.data().as_slice().len() as u64 * fee_rate / 1000;
-        Ok(Capacity::shannons(tx_fee))
+        estimator.estimate_fee(rpc, self, fee_rate).await
     }
 
     /// Balance the transaction by adding input cells until the needed capacity is satisfied
@@ -1041,38 +1578,397 @@ impl TransactionSkeleton {
         balancer: ScriptEx,
         change_receiver: ChangeReceiver,
     ) -> Result<&mut Self> {
-        let change_cell_index = match change_receiver {
-            ChangeReceiver::Address(changer) => {
-                self.output_from_address(changer, Default::default())?;
-                self.outputs.len() - 1
+        self.balance_with_selector(
+            rpc,
+            fee,
+            balancer,
+            change_receiver,
+            &FirstFit {},
+            &ChangePolicy::default(),
+        )
+        .await
+    }
+
+    /// Balance the transaction by adding input cells that chosen by `selector`, refer to `balance`
+    ///
+    /// # Parameters
+    /// - `selector`: the strategy to choose input cells of balancer
+    /// - `policy`: how to handle the change that cannot afford a fresh change cell, ignored by `ChangeReceiver::Output`
+    pub async fn balance_with_selector<T: RPC, S: CoinSelector>(
+        &mut self,
+        rpc: &T,
+        fee: Capacity,
+        balancer: ScriptEx,
+        change_receiver: ChangeReceiver,
+        selector: &S,
+        policy: &ChangePolicy,
+    ) -> Result<&mut Self> {
+        let payer = Payer {
+            balancer,
+            change_receiver,
+            contribution: Contribution::Rest,
+            change_policy: policy.clone(),
+        };
+        self.balance_with_payers(rpc, fee, &[payer], selector)
+            .await?;
+        Ok(self)
+    }
+
+    /// Balance the transaction by several payers in order, each one covers its own contribution and receives its
+    /// own change
+    ///
+    /// # Parameters
+    /// - `fee`: the transaction fee, which is covered by the `Contribution::Fee` or `Contribution::Rest` payer
+    /// - `payers`: at most one payer of `Contribution::Rest`, which also receives the surplus of existing inputs
+    /// - `selector`: the strategy to choose input cells of each payer
+    ///
+    /// note: the contributions must add up to the needed capacity exactly if no `Contribution::Rest` payer,
+    /// otherwise the `Contribution::Rest` payer covers the shortage or receives the excess as change
+    pub async fn balance_with_payers<T: RPC, S: CoinSelector>(
+        &mut self,
+        rpc: &T,
+        fee: Capacity,
+        payers: &[Payer],
+        selector: &S,
+    ) -> Result<Vec<PayerContribution>> {
+        let count = |contribution: Contribution| {
+            payers
+                .iter()
+                .filter(|v| v.contribution == contribution)
+                .count()
+        };
+        if payers.is_empty() {
+            return Err(eyre!("no payer to balance transaction"));
+        }
+        if count(Contribution::Rest) > 1 || count(Contribution::Fee) > 1 {
+            return Err(eyre!("at most one payer covers the fee or the rest"));
+        }
+        let fixed = payers
+            .iter()
+            .map(|payer| match payer.contribution {
+                Contribution::Capacity(capacity) => capacity as i128,
+                Contribution::Fee => fee.as_u64() as i128,
+                Contribution::Rest => 0,
+            })
+            .sum::<i128>();
+        let needed = self.total_outputs_capacity().as_u64() as i128 + fee.as_u64() as i128
+            - self.total_inputs_capacity().as_u64() as i128;
+        let rest = needed - fixed;
+        if count(Contribution::Rest) == 0 && rest != 0 {
+            return Err(eyre!(
+                "contributions of payers add up to {fixed}, but {needed} is needed"
+            ));
+        }
+        // a payer may fail after others have funded, then the skeleton is restored and their cells are released
+        let snapshot = (
+            self.inputs.clone(),
+            self.outputs.clone(),
+            self.witnesses.clone(),
+        );
+        let result = self.fund_payers(rpc, fee, payers, rest, selector).await;
+        if result.is_err() {
+            let selected = self.inputs[snapshot.0.len()..]
+                .iter()
+                .map(|v| v.input.previous_output())
+                .collect::<Vec<_>>();
+            self.reservations.release(selected);
+            (self.inputs, self.outputs, self.witnesses) = snapshot;
+        }
+        result
+    }
+
+    /// Fund payers in order, the negative `rest` is returned to the `Contribution::Rest` payer as surplus
+    async fn fund_payers<T: RPC, S: CoinSelector>(
+        &mut self,
+        rpc: &T,
+        fee: Capacity,
+        payers: &[Payer],
+        rest: i128,
+        selector: &S,
+    ) -> Result<Vec<PayerContribution>> {
+        let mut contributions = vec![];
+        for payer in payers {
+            let (amount, surplus) = match payer.contribution {
+                Contribution::Capacity(capacity) => (capacity, 0),
+                Contribution::Fee => (fee.as_u64(), 0),
+                Contribution::Rest if rest < 0 => (0, rest.unsigned_abs() as u64),
+                Contribution::Rest => (rest as u64, 0),
+            };
+            let contribution = self
+                .fund(
+                    rpc,
+                    payer,
+                    Capacity::shannons(amount),
+                    Capacity::shannons(surplus),
+                    selector,
+                )
+                .await?;
+            contributions.push(contribution);
+        }
+        let donated = contributions.iter().map(|v| v.donated).sum::<u64>();
+        let fee_with_donated = fee.as_u64() + donated;
+        if self.exceeded_capacity().as_u64() != fee_with_donated {
+            return Err(eyre!(
+                "failed to balance transaction: inputs {}, outputs {}, fee {fee_with_donated}",
+                self.total_inputs_capacity().as_u64(),
+                self.total_outputs_capacity().as_u64(),
+            ));
+        }
+        Ok(contributions)
+    }
+
+    /// Add input cells of payer to cover `amount`, and then return the change plus `surplus` to the payer
+    async fn fund<T: RPC, S: CoinSelector>(
+        &mut self,
+        rpc: &T,
+        payer: &Payer,
+        amount: Capacity,
+        surplus: Capacity,
+        selector: &S,
+    ) -> Result<PayerContribution> {
+        let lock_script = payer.balancer.clone().to_script(self)?;
+        let first_input = self.inputs.len();
+        self.select_inputs(rpc, &lock_script, amount, selector)
+            .await?;
+        let inputs_capacity = |skeleton: &Self| {
+            skeleton.inputs[first_input..]
+                .iter()
+                .map(|v| v.output.capacity().as_u64())
+                .sum::<u64>()
+        };
+        // a custom selector may return less than needed, which must not wrap around
+        let change_of = |skeleton: &Self| {
+            let available = inputs_capacity(skeleton) + surplus.as_u64();
+            available
+                .checked_sub(amount.as_u64())
+                .ok_or_else(|| insufficient(amount, available))
+        };
+        let mut change = change_of(self)?;
+        let mut donated = 0;
+        let change_lock_script = match &payer.change_receiver {
+            ChangeReceiver::Address(changer) => ScriptEx::from(changer.clone()).to_script(self)?,
+            ChangeReceiver::Script(changer) => changer.clone().to_script(self)?,
+            ChangeReceiver::Output(index) => {
+                self.add_output_capacity(*index, Capacity::shannons(change))?;
+                return Ok(PayerContribution {
+                    lock_script,
+                    inputs: (first_input..self.inputs.len()).collect(),
+                    inputs_capacity: inputs_capacity(self),
+                    change,
+                    donated,
+                });
             }
-            ChangeReceiver::Script(changer) => {
-                self.output_from_script(changer, Default::default())?;
-                self.outputs.len() - 1
+        };
+        let change_cell = CellOutput::new_builder()
+            .lock(change_lock_script)
+            .build_exact_capacity(Capacity::zero())?;
+        let occupied_capacity = change_cell.capacity().unpack();
+        let mut change_capacities = vec![];
+        if change > 0 && change < occupied_capacity {
+            match &payer.change_policy {
+                ChangePolicy::DonateDust => {
+                    donated = change;
+                }
+                ChangePolicy::FoldIntoOutput(index) => {
+                    self.add_output_capacity(*index, Capacity::shannons(change))?;
+                }
+                ChangePolicy::AddInputs | ChangePolicy::Split(_) => {
+                    let shortfall = Capacity::shannons(occupied_capacity - change);
+                    self.select_inputs(rpc, &lock_script, shortfall, selector)
+                        .await?;
+                    change = change_of(self)?;
+                }
             }
-            ChangeReceiver::Output(index) => {
-                if self.outputs.len() <= index {
-                    return Err(eyre!("change output index out of range"));
+        }
+        if change >= occupied_capacity {
+            change_capacities = match &payer.change_policy {
+                ChangePolicy::Split(target) => {
+                    if *target < occupied_capacity {
+                        return Err(eyre!(
+                            "split target {target} is less than occupied capacity {occupied_capacity}"
+                        ));
+                    }
+                    // the last cell takes the remainder, which is no less than target
+                    let count = (change / target).max(1);
+                    let mut capacities = vec![*target; count as usize - 1];
+                    capacities.push(change - target * (count - 1));
+                    capacities
+                }
+                _ => vec![change],
+            };
+        }
+        for capacity in change_capacities {
+            let output = change_cell
+                .clone()
+                .as_builder()
+                .capacity(capacity.pack())
+                .build();
+            self.output(CellOutputEx::new(output, vec![]));
+        }
+        Ok(PayerContribution {
+            lock_script,
+            inputs: (first_input..self.inputs.len()).collect(),
+            inputs_capacity: inputs_capacity(self),
+            change: change - donated,
+            donated,
+        })
+    }
+
+    /// Add input cells of balancer whose total capacity covers `needed`
+    async fn select_inputs<T: RPC, S: CoinSelector>(
+        &mut self,
+        rpc: &T,
+        balancer: &Script,
+        needed: Capacity,
+        selector: &S,
+    ) -> Result<()> {
+        if needed == Capacity::zero() {
+            return Ok(());
+        }
+        loop {
+            let mut candidates = Candidates::new(rpc, self, balancer.clone(), DEFAULT_BATCH_SIZE);
+            let inputs = selector.select(&mut candidates, needed).await?;
+            // another skeleton may reserve the same cells during selection, then select again without them
+            if let Some(locker) = rpc.cell_locker() {
+                let mut reserved = vec![];
+                for input in &inputs {
+                    let out_point = input.input.previous_output();
+                    if !self.reservations.try_lock(locker, out_point.clone()) {
+                        break;
+                    }
+                    reserved.push(out_point);
+                }
+                if reserved.len() < inputs.len() {
+                    self.reservations.release(reserved);
+                    continue;
                 }
-                index
             }
-        };
-        while self.exceeded_capacity() < fee {
-            self.input_from_script(rpc, balancer.clone()).await?;
+            self.inputs.extend(inputs);
+            return Ok(());
         }
-        let exceeded_capacity_beyond_fee = self.exceeded_capacity().safe_sub(fee).unwrap();
-        let old_capacity: Capacity = self.outputs[change_cell_index].output.capacity().unpack();
-        let new_capacity = old_capacity.safe_add(exceeded_capacity_beyond_fee).unwrap();
-        self.outputs[change_cell_index].output = self.outputs[change_cell_index]
+    }
+
+    fn add_output_capacity(&mut self, index: usize, capacity: Capacity) -> Result<()> {
+        let output = self
+            .outputs
+            .get_mut(index)
+            .ok_or(eyre!("change output index out of range"))?;
+        let new_capacity = output.capacity().safe_add(capacity)?;
+        output.output = output
             .output
             .clone()
             .as_builder()
             .capacity(new_capacity.pack())
             .build();
-        if self.exceeded_capacity() != fee {
-            return Err(eyre!("failed to balance transaction"));
+        Ok(())
+    }
+
+    /// Balance the transaction by payers under fee rate, the fee is re-estimated until the added inputs and change
+    /// cells are covered
+    ///
+    /// # Parameters
+    /// - `additinal_fee_rate`: fee rate that added on top of the minimal fee rate of tx pool
+    /// - `estimator`: estimate placeholder witnesses of unsigned lock groups
+    /// - `selector`: the strategy to choose input cells of payers
+    /// - `payers`: who provide capacity and receive change, refer to `balance_with_payers`
+    pub async fn balance_under_fee_rate<T: RPC, S: CoinSelector>(
+        &mut self,
+        rpc: &T,
+        additinal_fee_rate: u64,
+        estimator: &FeeEstimator,
+        selector: &S,
+        payers: &[Payer],
+    ) -> Result<Vec<PayerContribution>> {
+        let mut fee = self
+            .fee_with_estimator(rpc, additinal_fee_rate, estimator)
+            .await?;
+        loop {
+            let mut balanced = self.clone();
+            let result = balanced
+                .balance_with_payers(rpc, fee, payers, selector)
+                .await;
+            // inputs selected in this attempt stay reserved only if it's accepted
+            let release = |balanced: &TransactionSkeleton| {
+                let selected = balanced.inputs[self.inputs.len()..]
+                    .iter()
+                    .map(|v| v.input.previous_output());
+                balanced.reservations.release(selected);
+            };
+            let contributions = match result {
+                Ok(contributions) => contributions,
+                Err(e) => {
+                    release(&balanced);
+                    return Err(e);
+                }
+            };
+            (balanced.witnesses.len()..balanced.inputs.len()).for_each(|_| {
+                balanced.witness(Default::default());
+            });
+            let balanced_fee = match balanced
+                .fee_with_estimator(rpc, additinal_fee_rate, estimator)
+                .await
+            {
+                Ok(balanced_fee) => balanced_fee,
+                Err(e) => {
+                    release(&balanced);
+                    return Err(e);
+                }
+            };
+            if balanced_fee <= fee {
+                *self = balanced;
+                return Ok(contributions);
+            }
+            release(&balanced);
+            fee = balanced_fee;
         }
-        Ok(self)
+    }
+
+    /// Raise the fee of a balanced transaction up to the estimation under `additional_fee_rate`, the original inputs
+    /// and outputs are all kept except the change cell of `balancer`
+    ///
+    /// note: signatures are not refreshed, sign again after rebalancing
+    pub async fn rebalance<T: RPC>(
+        &mut self,
+        rpc: &T,
+        additional_fee_rate: u64,
+        estimator: &FeeEstimator,
+        balancer: &ScriptEx,
+    ) -> Result<Capacity> {
+        let current_fee = self.exceeded_capacity();
+        let fee = self
+            .fee_with_estimator(rpc, additional_fee_rate, estimator)
+            .await?;
+        if fee <= current_fee {
+            return Ok(current_fee);
+        }
+        let delta = fee.safe_sub(current_fee)?;
+        let lock_script = balancer.clone().to_script(self)?;
+        let change = self.outputs.iter_mut().rev().find(|output| {
+            output.lock_script() == lock_script
+                && output.type_script().is_none()
+                && output.data.is_empty()
+                && output.capacity().safe_sub(delta).ok() >= Some(output.occupied_capacity())
+        });
+        if let Some(change) = change {
+            let capacity = change.capacity().safe_sub(delta)?;
+            change.output = change
+                .output
+                .clone()
+                .as_builder()
+                .capacity(capacity.pack())
+                .build();
+            return Ok(fee);
+        }
+        // no change cell can afford the raised fee, so inject more capacity from balancer
+        let payer = Payer {
+            balancer: balancer.clone(),
+            change_receiver: ChangeReceiver::Script(balancer.clone()),
+            contribution: Default::default(),
+            change_policy: Default::default(),
+        };
+        self.balance_under_fee_rate(rpc, additional_fee_rate, estimator, &FirstFit {}, &[payer])
+            .await?;
+        Ok(self.exceeded_capacity())
     }
 
     /// Turn into ResolvedTransaction for contracts native debugging
@@ -1086,46 +1982,66 @@ impl TransactionSkeleton {
                 .build();
             resolved_inputs.push(meta);
         }
+        // refresh the cell deps without data in one batch request
+        let mut celldeps = self.celldeps;
+        let refreshing = celldeps
+            .iter()
+            .filter(|v| !v.with_data)
+            .map(|v| (v.name.clone(), v.celldep.clone()))
+            .collect();
+        let refreshed = CellDepEx::new_batch_from_celldeps(rpc, refreshing, true).await?;
+        let stale = celldeps
+            .iter_mut()
+            .filter(|v| !v.with_data)
+            .collect::<Vec<_>>();
+        if refreshed.len() != stale.len() {
+            return Err(eyre!(
+                "expect {} refreshed celldeps, but got {}",
+                stale.len(),
+                refreshed.len()
+            ));
+        }
+        // only the output is refreshed, so that the name and aliases survive
+        for (celldep, refreshed) in stale.into_iter().zip(refreshed) {
+            celldep.output = refreshed.output;
+            celldep.with_data = true;
+        }
+        // dep group data is a list of out points, resolve all of them in one batch request
+        let dep_groups = celldeps
+            .iter()
+            .filter(|v| v.celldep.dep_type() == DepType::DepGroup.into())
+            .map(|v| {
+                OutPointVec::from_slice(&v.output.data)
+                    .map(|out_points| out_points.into_iter().collect::<Vec<_>>())
+                    .map_err(|_| eyre!("invalid dep group"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let sub_out_points = dep_groups.concat();
+        let mut sub_cells = get_live_cells(rpc, &sub_out_points, true)
+            .await?
+            .into_iter()
+            .zip(sub_out_points);
+        let mut dep_groups = dep_groups.into_iter();
         let mut resolved_cell_deps = vec![];
         let mut resolved_dep_groups = vec![];
-        for mut v in self.celldeps {
-            if !v.with_data {
-                v.refresh_cell_output(rpc).await?;
-            }
+        for v in celldeps {
             let output = v.output;
+            let meta = CellMetaBuilder::from_cell_output(output.output, output.data.into())
+                .out_point(v.celldep.out_point())
+                .build();
             if v.celldep.dep_type() == DepType::DepGroup.into() {
-                // dep group data is a list of out points
-                let sub_out_points = OutPointVec::from_slice(&output.data)
-                    .map_err(|_| eyre!("invalid dep group"))?;
-                for sub_out_point in sub_out_points {
-                    let tx_hash = sub_out_point.tx_hash().unpack();
-                    let index = sub_out_point.index().unpack();
-                    let sub_celldep = CellDepEx::new_from_outpoint(
-                        rpc,
-                        "".to_string(),
-                        tx_hash,
-                        index,
-                        DepType::Code,
-                        true,
-                    )
-                    .await?;
-                    let sub_output = sub_celldep.output;
+                let sub_count = dep_groups.next().map(|v| v.len()).unwrap_or_default();
+                for ((sub_output, sub_data), sub_out_point) in sub_cells.by_ref().take(sub_count) {
                     let meta = CellMetaBuilder::from_cell_output(
-                        sub_output.output,
-                        sub_output.data.into(),
+                        sub_output,
+                        sub_data.unwrap_or_default().into(),
                     )
                     .out_point(sub_out_point)
                     .build();
                     resolved_cell_deps.push(meta);
                 }
-                let meta = CellMetaBuilder::from_cell_output(output.output, output.data.into())
-                    .out_point(v.celldep.out_point())
-                    .build();
                 resolved_dep_groups.push(meta);
             } else {
-                let meta = CellMetaBuilder::from_cell_output(output.output, output.data.into())
-                    .out_point(v.celldep.out_point())
-                    .build();
                 resolved_cell_deps.push(meta);
             }
         }
@@ -1175,52 +2091,95 @@ impl TransactionSkeleton {
     ///
     /// `confirm_count`: wait how many blocks to firm confirmation, if 0, return immidiently after sending
     /// `wait_timeout`: wait how much time until throwing timeout error, if None, no timeout
+    ///
+    /// note: if rpc has cell locker, inputs are reserved as in-flight while waiting, and released once committed or
+    /// rejected, otherwise fall back to expirable reservation since the transaction may still stay in tx pool
     pub async fn send_and_wait<T: RPC>(
         self,
         rpc: &T,
         confirm_count: u8,
         wait_timeout: Option<Duration>,
     ) -> Result<H256> {
-        let hash = rpc
-            .send_transaction(self.into(), Some(OutputsValidator::Passthrough))
-            .await?;
-        if confirm_count == 0 {
-            return Ok(hash);
+        let options = WaitOptions::new(confirm_count).timeout(wait_timeout);
+        self.send_and_wait_with(rpc, options).await
+    }
+
+    /// Same as `send_and_wait`, but replace the transaction with higher fee if it stays pending for a while
+    ///
+    /// note: the hash of the finally committed transaction is returned, which differs from the original one if bumped
+    pub async fn send_and_wait_with_fee_bump<T: RPC>(
+        self,
+        rpc: &T,
+        confirm_count: u8,
+        wait_timeout: Option<Duration>,
+        fee_bump: &FeeBump<T>,
+    ) -> Result<H256> {
+        let options = WaitOptions::new(confirm_count)
+            .timeout(wait_timeout)
+            .fee_bump(fee_bump);
+        self.send_and_wait_with(rpc, options).await
+    }
+
+    /// Leave the cells reserved for this skeleton to the cell locker, so that they are not released on drop, which is
+    /// required if this skeleton is sent or exported without `send_and_wait`
+    pub fn keep_reservations(&self) -> &Self {
+        self.reservations.keep();
+        self
+    }
+
+    /// Send this transaction and wait under `options`, the inclusion is verified again at the final confirmation
+    ///
+    /// note: since fields of inputs are checked against the tip header before sending if enabled in `options`, refer
+    /// to `WaitOptions::validate_since`
+    ///
+    /// note: a reorged out transaction is waited again instead of failing, refer to `TxProgress`
+    pub async fn send_and_wait_with<T: RPC>(
+        self,
+        rpc: &T,
+        options: WaitOptions<'_, T>,
+    ) -> Result<H256> {
+        if options.validates_since() {
+            self.validate_since(rpc).await?;
         }
-        let mut block_number = 0u64;
-        let mut time_used = Duration::from_secs(0);
-        let interval = Duration::from_secs(3);
-        loop {
-            if let Some(timeout) = wait_timeout {
-                if time_used > timeout {
-                    return Err(eyre!("timeout waiting tx: {hash:#x}"));
+        let mut out_points = locker::input_out_points(&self);
+        let locker = rpc.cell_locker();
+        if let Some(locker) = locker {
+            locker.lock_in_flight(out_points.clone());
+        }
+        self.keep_reservations();
+        let tx = self.clone().into();
+        let hash = match rpc
+            .send_transaction(tx, Some(OutputsValidator::Passthrough))
+            .await
+        {
+            Ok(hash) => hash,
+            Err(e) => {
+                if let Some(locker) = locker {
+                    locker.release(out_points);
                 }
-                time_used += interval;
-            }
-            tokio::time::sleep(interval).await;
-            let tx = rpc
-                .get_transaction(&hash)
-                .await?
-                .ok_or(eyre!("no tx found: {hash:#x}"))?;
-            if tx.tx_status.status == Status::Rejected {
-                let reason = tx.tx_status.reason.unwrap_or_else(|| "unknown".to_string());
-                return Err(eyre!("tx {hash:#x} rejected, reason: {reason}"));
+                return Err(e.into());
             }
-            if tx.tx_status.status != Status::Committed {
-                continue;
+        };
+        options.report(TxProgress::Sent(hash.clone()));
+        if options.confirm_count() == 0 {
+            if let Some(locker) = locker {
+                locker.lock(out_points);
             }
-            if block_number == 0 {
-                if let Some(number) = tx.tx_status.block_number {
-                    block_number = number.into();
-                }
-            } else {
-                let tip_number = rpc.get_tip_header().await?.inner.number;
-                if u64::from(tip_number) >= block_number + confirm_count as u64 {
-                    break;
-                }
+            return Ok(hash);
+        }
+        let mut watcher = Watcher {
+            skeleton: self,
+            hashes: vec![hash],
+            out_points: &mut out_points,
+        };
+        let result = watcher.wait_for_confirmation(rpc, &options).await;
+        if let Some(locker) = locker {
+            match &result {
+                Ok(_) | Err(WaitError::Rejected(_)) => locker.release(out_points),
+                Err(_) => locker.lock(out_points),
             }
         }
-        Ok(hash)
+        result.map_err(|e| e.into_report())
     }
 }
 
@@ -1248,16 +2207,307 @@ impl From<TransactionSkeleton> for ckb_jsonrpc_types::Transaction {
     }
 }
 
+/// Version of the skeleton JSON format, which is bumped on any incompatible change
+///
+/// The format of version 1 is:
+/// ```json
+/// {
+///   "version": 1,
+///   "inputs": [{ "input": <CellInput>, "output": <CellOutput>, "data": "0x.." | null }],
+///   "outputs": [{ "output": <CellOutput>, "data": "0x.." }],
+///   "celldeps": [{ "name": "..", "aliases": [".."], "celldep": <CellDep>, "output": <CellOutput>, "data": "0x.." | null }],
+///   "headerdeps": [{ "header": <HeaderView>, "cellinput_outpoint": <OutPoint> | null }],
+///   "witnesses": [{ "empty": bool, "traditional": bool, "lock": "0x..", "input_type": "0x..", "output_type": "0x..", "headerdep_reference": bool }]
+/// }
+/// ```
+/// where `<CellInput>`, `<CellOutput>`, `<CellDep>`, `<HeaderView>` and `<OutPoint>` follow the CKB JSON-RPC types,
+/// `data` is null if the cell data is not resolved, and `aliases` is optional
+pub const SKELETON_FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct CellInputRepr {
+    input: ckb_jsonrpc_types::CellInput,
+    output: ckb_jsonrpc_types::CellOutput,
+    data: Option<JsonBytes>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CellOutputRepr {
+    output: ckb_jsonrpc_types::CellOutput,
+    data: JsonBytes,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CellDepRepr {
+    name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+    celldep: ckb_jsonrpc_types::CellDep,
+    output: ckb_jsonrpc_types::CellOutput,
+    data: Option<JsonBytes>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HeaderDepRepr {
+    header: ckb_jsonrpc_types::HeaderView,
+    cellinput_outpoint: Option<ckb_jsonrpc_types::OutPoint>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WitnessRepr {
+    empty: bool,
+    traditional: bool,
+    lock: JsonBytes,
+    input_type: JsonBytes,
+    output_type: JsonBytes,
+    #[serde(default)]
+    headerdep_reference: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SkeletonRepr {
+    version: u32,
+    inputs: Vec<CellInputRepr>,
+    outputs: Vec<CellOutputRepr>,
+    celldeps: Vec<CellDepRepr>,
+    headerdeps: Vec<HeaderDepRepr>,
+    witnesses: Vec<WitnessRepr>,
+}
+
+impl From<&TransactionSkeleton> for SkeletonRepr {
+    fn from(skeleton: &TransactionSkeleton) -> Self {
+        let resolved_data =
+            |with_data: bool, data: &[u8]| with_data.then(|| JsonBytes::from_vec(data.to_vec()));
+        SkeletonRepr {
+            version: SKELETON_FORMAT_VERSION,
+            inputs: skeleton
+                .inputs
+                .iter()
+                .map(|v| CellInputRepr {
+                    input: v.input.clone().into(),
+                    output: v.output.output.clone().into(),
+                    data: resolved_data(v.with_data, &v.output.data),
+                })
+                .collect(),
+            outputs: skeleton
+                .outputs
+                .iter()
+                .map(|v| CellOutputRepr {
+                    output: v.output.clone().into(),
+                    data: JsonBytes::from_vec(v.data.clone()),
+                })
+                .collect(),
+            celldeps: skeleton
+                .celldeps
+                .iter()
+                .map(|v| CellDepRepr {
+                    name: v.name.clone(),
+                    aliases: v.aliases.clone(),
+                    celldep: v.celldep.clone().into(),
+                    output: v.output.output.clone().into(),
+                    data: resolved_data(v.with_data, &v.output.data),
+                })
+                .collect(),
+            headerdeps: skeleton
+                .headerdeps
+                .iter()
+                .map(|v| HeaderDepRepr {
+                    header: v.header.clone().into(),
+                    cellinput_outpoint: v.cellinput_outpoint.clone().map(Into::into),
+                })
+                .collect(),
+            witnesses: skeleton
+                .witnesses
+                .iter()
+                .map(|v| WitnessRepr {
+                    empty: v.empty,
+                    traditional: v.traditional,
+                    lock: JsonBytes::from_vec(v.lock.clone()),
+                    input_type: JsonBytes::from_vec(v.input_type.clone()),
+                    output_type: JsonBytes::from_vec(v.output_type.clone()),
+                    headerdep_reference: v.headerdep_reference,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<SkeletonRepr> for TransactionSkeleton {
+    type Error = eyre::Report;
+
+    fn try_from(repr: SkeletonRepr) -> Result<Self> {
+        if repr.version != SKELETON_FORMAT_VERSION {
+            return Err(eyre!(
+                "unsupported skeleton format version {}, expect {SKELETON_FORMAT_VERSION}",
+                repr.version
+            ));
+        }
+        let inputs = repr
+            .inputs
+            .into_iter()
+            .map(|v| {
+                let data = v.data.map(|v| v.into_bytes().to_vec());
+                CellInputEx::new(v.input.into(), v.output.into(), data)
+            })
+            .collect();
+        let outputs = repr
+            .outputs
+            .into_iter()
+            .map(|v| CellOutputEx::new(v.output.into(), v.data.into_bytes().to_vec()))
+            .collect();
+        let celldeps = repr
+            .celldeps
+            .into_iter()
+            .map(|v| {
+                let data = v.data.map(|v| v.into_bytes().to_vec());
+                let mut celldep = CellDepEx::new(v.name, v.celldep.into(), v.output.into(), data);
+                celldep.aliases = v.aliases;
+                celldep
+            })
+            .collect();
+        let headerdeps = repr
+            .headerdeps
+            .into_iter()
+            .map(|v| {
+                let header: HeaderView = v.header.into();
+                HeaderDepEx {
+                    block_hash: header.hash().unpack(),
+                    header,
+                    cellinput_outpoint: v.cellinput_outpoint.map(Into::into),
+                }
+            })
+            .collect();
+        let witnesses = repr
+            .witnesses
+            .into_iter()
+            .map(|v| WitnessEx {
+                empty: v.empty,
+                traditional: v.traditional,
+                lock: v.lock.into_bytes().to_vec(),
+                input_type: v.input_type.into_bytes().to_vec(),
+                output_type: v.output_type.into_bytes().to_vec(),
+                headerdep_reference: v.headerdep_reference,
+            })
+            .collect();
+        Ok(TransactionSkeleton {
+            inputs,
+            outputs,
+            celldeps,
+            witnesses,
+            headerdeps,
+            reservations: Default::default(),
+        })
+    }
+}
+
+impl Serialize for TransactionSkeleton {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        SkeletonRepr::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TransactionSkeleton {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        SkeletonRepr::deserialize(deserializer)?
+            .try_into()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl TransactionSkeleton {
+    /// Export into the versioned JSON format, which is used to move skeleton between online and offline machines
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Import from the versioned JSON format, throw error if the version mismatches
+    pub fn from_json(content: &str) -> Result<Self> {
+        Ok(serde_json::from_str(content)?)
+    }
+
+    /// Export into file in the versioned JSON format
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// Import from file in the versioned JSON format
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+}
+
 /// Indicate how to receive the change capacity while balancing transaction
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ChangeReceiver {
     /// Balance by adding an extra change cell from ckb address
-    Address(Address),
+    Address(#[serde(with = "serde_ext::address")] Address),
     /// Balance by adding an extra change cell from lock script
     Script(ScriptEx),
     /// Balance by choosing an existing output cell
     Output(usize),
 }
 
+/// Indicate which part of the needed capacity a payer covers while balancing transaction
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Contribution {
+    /// Cover a fixed capacity in shannons
+    Capacity(u64),
+    /// Cover the transaction fee
+    Fee,
+    /// Cover whatever is left after other payers
+    #[default]
+    Rest,
+}
+
+/// A party that provides capacity and receives its own change while balancing transaction
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Payer {
+    pub balancer: ScriptEx,
+    pub change_receiver: ChangeReceiver,
+    #[serde(default)]
+    pub contribution: Contribution,
+    #[serde(default)]
+    pub change_policy: ChangePolicy,
+}
+
+/// Breakdown of what a payer provided in balancing, capacities are in shannons
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayerContribution {
+    pub lock_script: Script,
+    /// Indices of the input cells that added for payer
+    pub inputs: Vec<usize>,
+    pub inputs_capacity: u64,
+    /// Capacity returned to the change receiver of payer
+    pub change: u64,
+    /// Change that is too small to form a cell and left as fee
+    pub donated: u64,
+}
+
+impl PayerContribution {
+    /// Capacity that payer actually gave to transaction, including the donated change
+    pub fn contributed(&self) -> u64 {
+        self.inputs_capacity.saturating_sub(self.change)
+    }
+}
+
+/// Indicate how to handle the change capacity that is less than the occupied capacity of a fresh change cell
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangePolicy {
+    /// Add more input cells until the change cell is affordable
+    #[default]
+    AddInputs,
+    /// Leave the change as part of transaction fee
+    DonateDust,
+    /// Add the change into an existing output cell by index
+    FoldIntoOutput(usize),
+    /// Split the change into cells of target capacity in shannons, the last one takes the remainder
+    Split(u64),
+}
+
 impl From<Address> for ChangeReceiver {
     fn from(value: Address) -> Self {
         ChangeReceiver::Address(value)
@@ -1281,3 +2531,586 @@ impl From<usize> for ChangeReceiver {
         ChangeReceiver::Output(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ckb_types::core::HeaderBuilder;
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{
+        fee::WitnessEstimator,
+        locker::CellLocker,
+        selection::{FirstFit, InsufficientCapacity},
+        simulation::FakeRpcClient,
+    };
+
+    fn out_point(tx: u8, index: u32) -> OutPoint {
+        OutPoint::new_builder()
+            .tx_hash([tx; 32].pack())
+            .index(index.pack())
+            .build()
+    }
+
+    fn lock(args: u8) -> Script {
+        ScriptEx::new_code(H256::default(), vec![args]).to_script_unchecked()
+    }
+
+    fn celldep(name: &str, tx: u8, data: Option<Vec<u8>>) -> CellDepEx {
+        let celldep = CellDep::new_builder()
+            .out_point(out_point(tx, 0))
+            .dep_type(DepType::Code.into())
+            .build();
+        CellDepEx::new(name.to_string(), celldep, CellOutput::default(), data)
+    }
+
+    fn input(tx: u8, lock_args: u8) -> CellInputEx {
+        let input = CellInput::new_builder()
+            .previous_output(out_point(tx, 0))
+            .build();
+        let output = CellOutput::new_builder().lock(lock(lock_args)).build();
+        CellInputEx::new(input, output, None)
+    }
+
+    fn headerdep(timestamp: u64) -> HeaderDepEx {
+        let header = HeaderBuilder::default().timestamp(timestamp.pack()).build();
+        HeaderDepEx {
+            block_hash: header.hash().unpack(),
+            header,
+            cellinput_outpoint: None,
+        }
+    }
+
+    #[test]
+    fn normalize_celldeps_keeps_names_of_merged() {
+        let mut skeleton = TransactionSkeleton {
+            celldeps: vec![
+                celldep("first", 1, None),
+                celldep("other", 2, None),
+                celldep("second", 1, Some(b"code".to_vec())),
+            ],
+            ..Default::default()
+        };
+        skeleton.normalize_celldeps();
+        assert_eq!(skeleton.celldeps.len(), 2);
+        let merged = &skeleton.celldeps[0];
+        assert_eq!(merged.name, "first");
+        assert_eq!(merged.aliases, vec!["second".to_string()]);
+        assert!(merged.with_data);
+        assert_eq!(
+            skeleton.get_celldep_by_name("second").map(|v| &v.name),
+            Some(&"first".to_string())
+        );
+        let script = ScriptEx::Reference("second".to_string(), vec![1])
+            .to_script(&skeleton)
+            .unwrap();
+        assert_eq!(script.code_hash(), blake2b_256(b"code").pack());
+        // normalizing again changes nothing
+        let celldeps = skeleton.celldeps.clone();
+        skeleton.normalize_celldeps();
+        assert_eq!(skeleton.celldeps[0].aliases, celldeps[0].aliases);
+    }
+
+    #[test]
+    fn push_duplicated_celldep_keeps_its_name() {
+        let mut skeleton = TransactionSkeleton::default();
+        skeleton
+            .celldep(celldep("first", 1, None))
+            .celldeps(vec![celldep("second", 1, None), celldep("first", 1, None)]);
+        assert_eq!(skeleton.celldeps.len(), 1);
+        assert_eq!(skeleton.celldeps[0].aliases, vec!["second".to_string()]);
+        assert!(skeleton.get_celldep_by_name("second").is_some());
+    }
+
+    #[test]
+    fn normalize_headerdeps_remaps_references() {
+        let mut skeleton = TransactionSkeleton {
+            headerdeps: vec![headerdep(1), headerdep(2), headerdep(1), headerdep(3)],
+            witnesses: vec![
+                WitnessEx::new_headerdep_reference(2),
+                WitnessEx::new_headerdep_reference(3),
+                WitnessEx::new(vec![], 2u64.to_le_bytes().to_vec(), vec![]),
+            ],
+            ..Default::default()
+        };
+        skeleton.normalize_headerdeps();
+        assert_eq!(
+            skeleton.headerdeps,
+            vec![headerdep(1), headerdep(2), headerdep(3)]
+        );
+        assert_eq!(skeleton.witnesses[0].headerdep_index(), Some(0));
+        assert_eq!(skeleton.witnesses[1].headerdep_index(), Some(2));
+        // plain input_type is not a reference, so it's untouched
+        assert_eq!(
+            skeleton.witnesses[2].input_type,
+            2u64.to_le_bytes().to_vec()
+        );
+    }
+
+    #[test]
+    fn normalize_witnesses_aligns_with_lock_groups() {
+        let mut skeleton = TransactionSkeleton {
+            inputs: vec![input(1, 1), input(2, 2), input(3, 1)],
+            witnesses: vec![
+                WitnessEx::new_plain(vec![9]),
+                WitnessEx::default(),
+                WitnessEx::default(),
+                WitnessEx::new(vec![7], vec![], vec![]),
+            ],
+            ..Default::default()
+        };
+        skeleton.normalize_witnesses();
+        assert_eq!(skeleton.witnesses.len(), 4);
+        // the lock of the first group is moved to its first witness
+        assert_eq!(skeleton.witnesses[0].lock, vec![7]);
+        assert!(skeleton.witnesses[1].is_empty());
+        assert!(skeleton.witnesses[2].is_empty());
+        assert!(!skeleton.witnesses[3].traditional);
+
+        skeleton.witnesses.truncate(1);
+        skeleton.normalize_witnesses();
+        assert_eq!(skeleton.witnesses.len(), 3);
+        assert_eq!(skeleton.witnesses[0].lock, vec![7]);
+    }
+
+    #[test]
+    fn since_round_trip() {
+        let sinces = [
+            SinceEx::block_number(1000),
+            SinceEx::epoch(10, 1, 2).relative(),
+            SinceEx::timestamp(7 * 24 * 3600).relative(),
+            SinceEx::timestamp(1_700_000_000),
+        ];
+        for since in sinces {
+            let value = since.value().unwrap();
+            assert_eq!(SinceEx::from_value(value).unwrap(), since);
+            assert_eq!(SinceEx::from(value).value().unwrap(), value);
+        }
+        assert_eq!(
+            SinceEx::block_number(1000).relative().value().unwrap(),
+            0x8000_0000_0000_03e8
+        );
+        assert_eq!(
+            SinceEx::epoch(10, 1, 2).value().unwrap(),
+            0x2000_0200_0100_000a
+        );
+        assert!(SinceEx::Raw(0x4000_0000_0000_0064).relative().is_relative());
+    }
+
+    #[test]
+    fn since_rejects_invalid_values() {
+        assert!(SinceEx::from_value(0x0100_0000_0000_0000).is_err());
+        assert!(SinceEx::from_value(0x6000_0000_0000_0000).is_err());
+        assert!(SinceEx::Raw(0x0100_0000_0000_0000).value().is_err());
+        assert!(SinceEx::block_number(1 << 56).value().is_err());
+        assert!(SinceEx::epoch(1, 0, 0).value().is_err());
+        assert!(SinceEx::epoch(1, 2, 2).value().is_err());
+    }
+
+    #[test]
+    fn since_display_and_serde() {
+        assert_eq!(
+            SinceEx::epoch(10, 1, 2).relative().to_string(),
+            "relative epoch 10 1/2"
+        );
+        assert_eq!(
+            SinceEx::Raw(0x4000_0000_0000_0064).to_string(),
+            "absolute timestamp 100"
+        );
+        assert_eq!(
+            SinceEx::Raw(0x0100_0000_0000_0000).to_string(),
+            "raw 0x100000000000000"
+        );
+        let json = serde_json::to_string(&SinceEx::block_number(1000)).unwrap();
+        assert_eq!(json, r#"{"block_number":{"value":1000,"relative":false}}"#);
+        let since: SinceEx =
+            serde_json::from_str(r#"{"timestamp":{"value":100,"relative":true}}"#).unwrap();
+        assert_eq!(since, SinceEx::timestamp(100).relative());
+    }
+
+    /// Selector that ignores the needed capacity and takes only the first candidate
+    struct TakeOne;
+
+    #[async_trait::async_trait]
+    impl CoinSelector for TakeOne {
+        async fn select<T: RPC>(
+            &self,
+            candidates: &mut Candidates<'_, T>,
+            _: Capacity,
+        ) -> Result<Vec<CellInputEx>> {
+            candidates.collect(1).await
+        }
+    }
+
+    #[test]
+    fn balance_reports_short_selection() {
+        let mut rpc = FakeRpcClient::default();
+        let output = CellOutput::new_builder()
+            .capacity(Capacity::shannons(100).pack())
+            .lock(lock(1))
+            .build();
+        rpc.insert_fake_cell(out_point(1, 0), CellOutputEx::new(output, vec![]), None);
+        let mut skeleton = TransactionSkeleton::default();
+        skeleton.output(CellOutputEx::new(
+            CellOutput::new_builder()
+                .capacity(Capacity::shannons(500).pack())
+                .lock(lock(2))
+                .build(),
+            vec![],
+        ));
+        let err = block_on(skeleton.balance_with_selector(
+            &rpc,
+            Capacity::zero(),
+            lock(1).into(),
+            ChangeReceiver::Script(lock(1).into()),
+            &TakeOne,
+            &ChangePolicy::default(),
+        ))
+        .unwrap_err();
+        let err = err.downcast::<InsufficientCapacity>().unwrap();
+        assert_eq!((err.needed, err.available), (500, 100));
+    }
+
+    const CKB: u64 = 100_000_000;
+
+    fn capacity_cell(capacity: u64, lock_args: u8) -> CellOutputEx {
+        let output = CellOutput::new_builder()
+            .capacity(Capacity::shannons(capacity).pack())
+            .lock(lock(lock_args))
+            .build();
+        CellOutputEx::new(output, vec![])
+    }
+
+    fn payer(lock_args: u8, contribution: Contribution, change_policy: ChangePolicy) -> Payer {
+        Payer {
+            balancer: lock(lock_args).into(),
+            change_receiver: ChangeReceiver::Script(lock(lock_args).into()),
+            contribution,
+            change_policy,
+        }
+    }
+
+    /// Placeholder of a 65 bytes signature for any lock script
+    struct AnyLockEstimator;
+
+    impl WitnessEstimator for AnyLockEstimator {
+        fn lock_size(&self, _: &Script) -> Option<usize> {
+            Some(65)
+        }
+    }
+
+    #[test]
+    fn balance_under_fee_rate_reestimates_fee() {
+        let mut rpc = FakeRpcClient::default();
+        rpc.fake_provider.fake_feerate = 1000;
+        rpc.insert_fake_cell(out_point(1, 0), capacity_cell(1000 * CKB, 1), None);
+        let mut skeleton = TransactionSkeleton::default();
+        skeleton.output(capacity_cell(200 * CKB, 2));
+        let estimator = FeeEstimator::new().estimator(AnyLockEstimator);
+        let unbalanced_fee = block_on(skeleton.fee_with_estimator(&rpc, 0, &estimator)).unwrap();
+        let contributions = block_on(skeleton.balance_under_fee_rate(
+            &rpc,
+            0,
+            &estimator,
+            &FirstFit {},
+            &[payer(1, Contribution::Rest, ChangePolicy::default())],
+        ))
+        .unwrap();
+        let fee =
+            skeleton.total_inputs_capacity().as_u64() - skeleton.total_outputs_capacity().as_u64();
+        let estimated = block_on(skeleton.fee_with_estimator(&rpc, 0, &estimator)).unwrap();
+        // the added input, change cell and placeholder witness are charged in the second round
+        assert!(estimated > unbalanced_fee);
+        assert_eq!(fee, estimated.as_u64());
+        assert_eq!(skeleton.outputs.len(), 2);
+        assert_eq!(skeleton.witnesses.len(), 1);
+        assert_eq!(contributions[0].change, 800 * CKB - fee);
+    }
+
+    /// Occupied capacity of the change cell of `payer(1, ..)`
+    fn change_occupied() -> u64 {
+        let change_cell = CellOutput::new_builder()
+            .lock(lock(1))
+            .build_exact_capacity(Capacity::zero())
+            .unwrap();
+        change_cell.capacity().unpack()
+    }
+
+    /// Balance an output of 100 CKB by a payer who owns cells of `100 CKB + change` and 1000 CKB in order, then
+    /// return the capacities of outputs and the contribution
+    fn fund_change(policy: ChangePolicy, change: u64) -> Result<(Vec<u64>, PayerContribution)> {
+        let mut rpc = FakeRpcClient::default();
+        rpc.insert_fake_cell(out_point(1, 0), capacity_cell(100 * CKB + change, 1), None)
+            .insert_fake_cell(out_point(2, 0), capacity_cell(1000 * CKB, 1), None);
+        let mut skeleton = TransactionSkeleton::default();
+        skeleton.output(capacity_cell(100 * CKB, 2));
+        let mut contributions = block_on(skeleton.balance_with_payers(
+            &rpc,
+            Capacity::zero(),
+            &[payer(1, Contribution::Rest, policy)],
+            &FirstFit {},
+        ))?;
+        let outputs = skeleton
+            .outputs
+            .iter()
+            .map(|v| v.capacity().as_u64())
+            .collect();
+        Ok((outputs, contributions.remove(0)))
+    }
+
+    #[test]
+    fn fund_adds_inputs_for_small_change() {
+        let occupied = change_occupied();
+        let (outputs, contribution) = fund_change(ChangePolicy::AddInputs, occupied - 1).unwrap();
+        assert_eq!(outputs, vec![100 * CKB, 1000 * CKB + occupied - 1]);
+        assert_eq!(contribution.inputs, vec![0, 1]);
+        let (outputs, contribution) = fund_change(ChangePolicy::AddInputs, occupied).unwrap();
+        assert_eq!(outputs, vec![100 * CKB, occupied]);
+        assert_eq!(contribution.inputs, vec![0]);
+        let (outputs, _) = fund_change(ChangePolicy::AddInputs, occupied + 1).unwrap();
+        assert_eq!(outputs, vec![100 * CKB, occupied + 1]);
+    }
+
+    #[test]
+    fn fund_donates_dust_change() {
+        let occupied = change_occupied();
+        let (outputs, contribution) = fund_change(ChangePolicy::DonateDust, occupied - 1).unwrap();
+        assert_eq!(outputs, vec![100 * CKB]);
+        assert_eq!(
+            (contribution.change, contribution.donated),
+            (0, occupied - 1)
+        );
+        assert_eq!(contribution.contributed(), 100 * CKB + occupied - 1);
+        let (outputs, contribution) = fund_change(ChangePolicy::DonateDust, occupied).unwrap();
+        assert_eq!(outputs, vec![100 * CKB, occupied]);
+        assert_eq!((contribution.change, contribution.donated), (occupied, 0));
+        let (outputs, _) = fund_change(ChangePolicy::DonateDust, occupied + 1).unwrap();
+        assert_eq!(outputs, vec![100 * CKB, occupied + 1]);
+    }
+
+    #[test]
+    fn fund_folds_small_change_into_output() {
+        let occupied = change_occupied();
+        let (outputs, contribution) =
+            fund_change(ChangePolicy::FoldIntoOutput(0), occupied - 1).unwrap();
+        assert_eq!(outputs, vec![100 * CKB + occupied - 1]);
+        assert_eq!(
+            (contribution.change, contribution.donated),
+            (occupied - 1, 0)
+        );
+        let (outputs, _) = fund_change(ChangePolicy::FoldIntoOutput(0), occupied).unwrap();
+        assert_eq!(outputs, vec![100 * CKB, occupied]);
+        let (outputs, _) = fund_change(ChangePolicy::FoldIntoOutput(0), occupied + 1).unwrap();
+        assert_eq!(outputs, vec![100 * CKB, occupied + 1]);
+        assert!(fund_change(ChangePolicy::FoldIntoOutput(1), occupied - 1).is_err());
+    }
+
+    #[test]
+    fn fund_splits_change() {
+        let occupied = change_occupied();
+        let target = 300 * CKB;
+        let (outputs, contribution) =
+            fund_change(ChangePolicy::Split(target), occupied - 1).unwrap();
+        let change = 1000 * CKB + occupied - 1;
+        assert_eq!(
+            outputs,
+            vec![100 * CKB, target, target, change - target * 2]
+        );
+        assert_eq!(contribution.change, change);
+        let (outputs, _) = fund_change(ChangePolicy::Split(target), occupied).unwrap();
+        assert_eq!(outputs, vec![100 * CKB, occupied]);
+        let (outputs, _) = fund_change(ChangePolicy::Split(target), occupied + 1).unwrap();
+        assert_eq!(outputs, vec![100 * CKB, occupied + 1]);
+        assert!(fund_change(ChangePolicy::Split(occupied - 1), occupied).is_err());
+    }
+
+    fn capacity_input(tx: u8, capacity: u64, lock_args: u8) -> CellInputEx {
+        let input = CellInput::new_builder()
+            .previous_output(out_point(tx, 0))
+            .build();
+        CellInputEx::new(input, capacity_cell(capacity, lock_args).output, None)
+    }
+
+    /// Balance with fee of 1 CKB, the payer of lock args 1 owns a 300 CKB cell, and 2 owns a 1000 CKB cell
+    fn balance_payers(
+        skeleton: &mut TransactionSkeleton,
+        payers: &[Payer],
+        locker: &CellLocker,
+    ) -> Result<Vec<(u64, u64)>> {
+        let mut rpc = FakeRpcClient::default().with_cell_locker(locker.clone());
+        rpc.insert_fake_cell(out_point(1, 0), capacity_cell(300 * CKB, 1), None)
+            .insert_fake_cell(out_point(2, 0), capacity_cell(1000 * CKB, 2), None);
+        let contributions = block_on(skeleton.balance_with_payers(
+            &rpc,
+            Capacity::shannons(CKB),
+            payers,
+            &FirstFit {},
+        ))?;
+        assert_eq!(skeleton.exceeded_capacity().as_u64(), CKB);
+        Ok(contributions
+            .iter()
+            .map(|v| (v.contributed(), v.change))
+            .collect())
+    }
+
+    #[test]
+    fn balance_with_fee_and_rest_payers() {
+        // the existing input covers more than outputs, so the rest payer receives the excess
+        let mut skeleton = TransactionSkeleton::default();
+        skeleton
+            .input(capacity_input(3, 1000 * CKB, 3))
+            .unwrap()
+            .output(capacity_cell(500 * CKB, 4));
+        let payers = [
+            payer(1, Contribution::Fee, ChangePolicy::default()),
+            payer(2, Contribution::Rest, ChangePolicy::default()),
+        ];
+        let contributions = balance_payers(&mut skeleton, &payers, &CellLocker::default());
+        assert_eq!(
+            contributions.unwrap(),
+            vec![(CKB, 299 * CKB), (0, 500 * CKB)]
+        );
+        assert_eq!(skeleton.inputs.len(), 2);
+    }
+
+    #[test]
+    fn balance_with_capacity_and_rest_payers() {
+        let mut skeleton = TransactionSkeleton::default();
+        skeleton.output(capacity_cell(500 * CKB, 4));
+        let payers = [
+            payer(
+                1,
+                Contribution::Capacity(200 * CKB),
+                ChangePolicy::default(),
+            ),
+            payer(2, Contribution::Rest, ChangePolicy::default()),
+        ];
+        let contributions = balance_payers(&mut skeleton, &payers, &CellLocker::default());
+        assert_eq!(
+            contributions.unwrap(),
+            vec![(200 * CKB, 100 * CKB), (301 * CKB, 699 * CKB)]
+        );
+    }
+
+    #[test]
+    fn balance_with_over_covered_payers() {
+        let mut skeleton = TransactionSkeleton::default();
+        skeleton.output(capacity_cell(100 * CKB, 4));
+        let payers = [
+            payer(
+                1,
+                Contribution::Capacity(300 * CKB),
+                ChangePolicy::default(),
+            ),
+            payer(2, Contribution::Rest, ChangePolicy::default()),
+        ];
+        let contributions = balance_payers(&mut skeleton, &payers, &CellLocker::default());
+        assert_eq!(contributions.unwrap(), vec![(300 * CKB, 0), (0, 199 * CKB)]);
+        assert_eq!(skeleton.inputs.len(), 1);
+    }
+
+    #[test]
+    fn balance_with_payers_restores_on_error() {
+        let locker = CellLocker::default();
+        let mut skeleton = TransactionSkeleton::default();
+        skeleton
+            .output(capacity_cell(2000 * CKB, 4))
+            .witness(WitnessEx::new(vec![1], vec![], vec![]));
+        let fixed = |capacity| payer(1, Contribution::Capacity(capacity), ChangePolicy::default());
+        let rest = || payer(2, Contribution::Rest, ChangePolicy::default());
+        assert!(balance_payers(&mut skeleton, &[], &locker).is_err());
+        assert!(balance_payers(&mut skeleton, &[rest(), rest()], &locker).is_err());
+        // contributions must add up to the needed without the rest payer
+        assert!(balance_payers(&mut skeleton, &[fixed(100 * CKB)], &locker).is_err());
+        // the rest payer falls short after the first payer funded
+        let err = balance_payers(&mut skeleton, &[fixed(200 * CKB), rest()], &locker).unwrap_err();
+        assert!(err.downcast_ref::<InsufficientCapacity>().is_some());
+        assert!(skeleton.inputs.is_empty());
+        assert_eq!(skeleton.outputs.len(), 1);
+        assert_eq!(skeleton.witnesses.len(), 1);
+        assert!(skeleton.reservations.out_points().is_empty());
+        assert!(locker.reserved().is_empty());
+    }
+
+    #[test]
+    fn resolve_celldeps_and_dep_groups() {
+        let mut rpc = FakeRpcClient::default();
+        let code = |data: &[u8]| CellOutputEx::new(CellOutput::default(), data.to_vec());
+        let group = OutPointVec::new_builder()
+            .push(out_point(3, 0))
+            .push(out_point(4, 0))
+            .build();
+        rpc.insert_fake_cell(out_point(1, 0), code(b"code"), None)
+            .insert_fake_cell(out_point(2, 0), code(group.as_slice()), None)
+            .insert_fake_cell(out_point(3, 0), code(b"first"), None)
+            .insert_fake_cell(out_point(4, 0), code(b"second"), None);
+        let mut merged = celldep("code", 1, None);
+        merged.aliases = vec!["alias".to_string()];
+        let mut group = celldep("group", 2, Some(group.as_bytes().to_vec()));
+        group.celldep = group
+            .celldep
+            .as_builder()
+            .dep_type(DepType::DepGroup.into())
+            .build();
+        let skeleton = TransactionSkeleton {
+            celldeps: vec![merged, group],
+            ..Default::default()
+        };
+        let resolved = block_on(skeleton.into_resolved_transaction(&rpc)).unwrap();
+        let data = resolved
+            .resolved_cell_deps
+            .iter()
+            .map(|v| v.mem_cell_data.clone().unwrap().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            data,
+            vec![b"code".to_vec(), b"first".to_vec(), b"second".to_vec()]
+        );
+        assert_eq!(resolved.resolved_dep_groups.len(), 1);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut merged = celldep("first", 1, Some(b"code".to_vec()));
+        merged.aliases = vec!["second".to_string()];
+        let skeleton = TransactionSkeleton {
+            inputs: vec![input(1, 1), input(2, 2)],
+            outputs: vec![CellOutputEx::new(
+                CellOutput::new_builder().lock(lock(3)).build(),
+                vec![1, 2, 3],
+            )],
+            celldeps: vec![merged, celldep("other", 2, None)],
+            headerdeps: vec![headerdep(1)],
+            witnesses: vec![
+                WitnessEx::new(vec![7], vec![], vec![]),
+                WitnessEx::new_headerdep_reference(0),
+                WitnessEx::new_plain(vec![9]),
+            ],
+            ..Default::default()
+        };
+        let json = skeleton.to_json().unwrap();
+        let restored = TransactionSkeleton::from_json(&json).unwrap();
+        assert_eq!(restored.to_json().unwrap(), json);
+        assert_eq!(
+            restored.clone().into_transaction_view().hash(),
+            skeleton.clone().into_transaction_view().hash()
+        );
+        assert_eq!(restored.celldeps[0].aliases, vec!["second".to_string()]);
+        assert!(restored.celldeps[0].with_data);
+        assert!(!restored.celldeps[1].with_data);
+        assert!(!restored.inputs[0].with_data);
+        assert_eq!(restored.headerdeps, skeleton.headerdeps);
+        assert_eq!(restored.witnesses[1].headerdep_index(), Some(0));
+        assert!(!restored.witnesses[2].traditional);
+    }
+
+    #[test]
+    fn json_rejects_unknown_version() {
+        let json = TransactionSkeleton::default().to_json().unwrap();
+        let json = json.replace(
+            &format!("\"version\": {SKELETON_FORMAT_VERSION}"),
+            "\"version\": 0",
+        );
+        assert!(TransactionSkeleton::from_json(&json).is_err());
+    }
+}
@@ -0,0 +1,730 @@
+use std::fmt::Display;
+
+use ckb_chain_spec::consensus::MAX_BLOCK_BYTES;
+use ckb_sdk::constants::TYPE_ID_CODE_HASH;
+use ckb_types::{
+    core::{Capacity, DepType, EpochNumberWithFraction, HeaderView, ScriptHashType},
+    packed::{OutPoint, OutPointVec, Script},
+    prelude::{Entity, Unpack},
+    H256,
+};
+use eyre::eyre;
+
+use crate::{
+    rpc::RPC,
+    skeleton::{get_live_cells, CellOutputEx, ScriptEx, SinceEx, TransactionSkeleton},
+};
+
+/// A single structural problem found in transaction skeleton
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// Output capacity cannot cover its occupied capacity
+    InsufficientOutputCapacity {
+        output_index: usize,
+        capacity: u64,
+        occupied: u64,
+    },
+    /// Total inputs capacity cannot cover total outputs capacity plus fee
+    InsufficientInputCapacity { inputs: u64, outputs: u64, fee: u64 },
+    /// Less witnesses than inputs
+    MissingWitnesses { inputs: usize, witnesses: usize },
+    /// Two inputs point to the same out point
+    DuplicateInput {
+        first_index: usize,
+        second_index: usize,
+    },
+    /// Script of a cell cannot be resolved by any celldep
+    UnresolvedScript {
+        field: &'static str,
+        index: usize,
+        script_hash: H256,
+    },
+    /// Witness refers to a headerdep that doesn't exist
+    HeaderDepIndexOutOfRange {
+        witness_index: usize,
+        headerdep_index: usize,
+        headerdeps: usize,
+    },
+    /// Transaction is too large to be packed into block
+    OversizedTransaction { size: u64, limit: u64 },
+    /// Args of new created type-id cell mismatches the one calculated from the first input
+    TypeIdMismatch {
+        output_index: usize,
+        expected: H256,
+        actual: Vec<u8>,
+    },
+    /// Since field of input has invalid flags or value
+    InvalidSince { input_index: usize, since: u64 },
+    /// Since condition of input is not satisfied under the current tip
+    ImmatureSince { input_index: usize, since: SinceEx },
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationIssue::InsufficientOutputCapacity {
+                output_index,
+                capacity,
+                occupied,
+            } => write!(
+                f,
+                "output #{output_index} capacity {capacity} is less than occupied capacity {occupied}"
+            ),
+            ValidationIssue::InsufficientInputCapacity {
+                inputs,
+                outputs,
+                fee,
+            } => write!(
+                f,
+                "inputs capacity {inputs} is less than outputs capacity {outputs} plus fee {fee}"
+            ),
+            ValidationIssue::MissingWitnesses { inputs, witnesses } => {
+                write!(f, "{witnesses} witnesses are less than {inputs} inputs")
+            }
+            ValidationIssue::DuplicateInput {
+                first_index,
+                second_index,
+            } => write!(
+                f,
+                "input #{second_index} duplicates the out point of input #{first_index}"
+            ),
+            ValidationIssue::UnresolvedScript {
+                field,
+                index,
+                script_hash,
+            } => write!(
+                f,
+                "script {script_hash:#x} of {field} #{index} cannot be resolved by celldeps"
+            ),
+            ValidationIssue::HeaderDepIndexOutOfRange {
+                witness_index,
+                headerdep_index,
+                headerdeps,
+            } => write!(
+                f,
+                "witness #{witness_index} refers to headerdep #{headerdep_index}, but only {headerdeps} headerdeps"
+            ),
+            ValidationIssue::OversizedTransaction { size, limit } => {
+                write!(f, "transaction size {size} exceeds the limit {limit}")
+            }
+            ValidationIssue::TypeIdMismatch {
+                output_index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "output #{output_index} type-id args 0x{} mismatches the expected {expected:#x}",
+                hex::encode(actual)
+            ),
+            ValidationIssue::InvalidSince { input_index, since } => {
+                write!(f, "input #{input_index} has invalid since {since:#x}")
+            }
+            ValidationIssue::ImmatureSince { input_index, since } => {
+                write!(f, "input #{input_index} is immature under since: {since}")
+            }
+        }
+    }
+}
+
+/// Error that collects all of issues found in transaction skeleton
+#[derive(Debug, Clone)]
+pub struct ValidationError {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} issues found in transaction skeleton:",
+            self.issues.len()
+        )?;
+        for issue in &self.issues {
+            write!(f, "\n- {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl TransactionSkeleton {
+    /// Check the structural problems of transaction skeleton before sending, all of issues are reported in one pass
+    ///
+    /// # Parameters
+    /// - `fee`: the expected transaction fee, e.g. the result of `TransactionSkeleton::fee`
+    ///
+    /// note: if any dep group celldep exists, the scripts that cannot be resolved by other celldeps are skipped, because
+    /// the sub celldeps of dep group are unknown offline, use `validate_with` to check them as well
+    pub fn validate(&self, fee: Capacity) -> Result<(), ValidationError> {
+        self.validate_against(fee, None)
+    }
+
+    /// Same as `validate`, but dep group celldeps are expanded through rpc, so that all of scripts are checked
+    pub async fn validate_with<T: RPC>(&self, rpc: &T, fee: Capacity) -> eyre::Result<()> {
+        let sub_celldeps = self.expand_dep_groups(rpc).await?;
+        Ok(self.validate_against(fee, Some(&sub_celldeps))?)
+    }
+
+    fn validate_against(
+        &self,
+        fee: Capacity,
+        sub_celldeps: Option<&[CellOutputEx]>,
+    ) -> Result<(), ValidationError> {
+        let mut issues = vec![];
+        self.validate_capacities(fee, &mut issues);
+        self.validate_inputs(&mut issues);
+        self.validate_scripts(sub_celldeps, &mut issues);
+        self.validate_witnesses(&mut issues);
+        self.validate_type_ids(&mut issues);
+        let size = self
+            .clone()
+            .into_transaction_view()
+            .data()
+            .serialized_size_in_block() as u64;
+        if size > MAX_BLOCK_BYTES {
+            issues.push(ValidationIssue::OversizedTransaction {
+                size,
+                limit: MAX_BLOCK_BYTES,
+            });
+        }
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { issues })
+        }
+    }
+
+    /// Check the since fields of inputs against the tip header, the immature inputs are all reported in one pass
+    ///
+    /// note: absolute timestamp is compared with the tip timestamp instead of the median time, and relative timestamp
+    /// is not checked, so a pass doesn't guarantee acceptance, but a failure does mean rejection
+    pub async fn validate_since<T: RPC>(&self, rpc: &T) -> eyre::Result<()> {
+        let sinces = self
+            .inputs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, input)| match input.since() {
+                Ok(SinceEx::BlockNumber { value: 0, .. }) => None,
+                since => Some((index, since)),
+            })
+            .collect::<Vec<_>>();
+        if sinces.is_empty() {
+            return Ok(());
+        }
+        let tip: HeaderView = rpc.get_tip_header().await?.into();
+        let mut issues = vec![];
+        for (input_index, since) in sinces {
+            let Ok(since) = since else {
+                let since = self.inputs[input_index].input.since().unpack();
+                issues.push(ValidationIssue::InvalidSince { input_index, since });
+                continue;
+            };
+            let base = if since.is_relative() {
+                let out_point = self.inputs[input_index].input.previous_output();
+                committed_header(rpc, &out_point.tx_hash().unpack()).await?
+            } else {
+                None
+            };
+            if since.is_relative() && base.is_none() {
+                issues.push(ValidationIssue::ImmatureSince { input_index, since });
+                continue;
+            }
+            if !is_mature(&since, &tip, base.as_ref())? {
+                issues.push(ValidationIssue::ImmatureSince { input_index, since });
+            }
+        }
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { issues }.into())
+        }
+    }
+
+    fn validate_capacities(&self, fee: Capacity, issues: &mut Vec<ValidationIssue>) {
+        for (output_index, output) in self.outputs.iter().enumerate() {
+            let (capacity, occupied) = (output.capacity(), output.occupied_capacity());
+            if capacity < occupied {
+                issues.push(ValidationIssue::InsufficientOutputCapacity {
+                    output_index,
+                    capacity: capacity.as_u64(),
+                    occupied: occupied.as_u64(),
+                });
+            }
+        }
+        let inputs = self.total_inputs_capacity().as_u64();
+        let outputs = self.total_outputs_capacity().as_u64();
+        if inputs < outputs.saturating_add(fee.as_u64()) {
+            issues.push(ValidationIssue::InsufficientInputCapacity {
+                inputs,
+                outputs,
+                fee: fee.as_u64(),
+            });
+        }
+    }
+
+    fn validate_inputs(&self, issues: &mut Vec<ValidationIssue>) {
+        for (second_index, input) in self.inputs.iter().enumerate() {
+            let out_point = input.input.previous_output();
+            if let Some(first_index) = self.inputs[..second_index]
+                .iter()
+                .position(|v| v.input.previous_output().as_slice() == out_point.as_slice())
+            {
+                issues.push(ValidationIssue::DuplicateInput {
+                    first_index,
+                    second_index,
+                });
+            }
+            if input.since().is_err() {
+                issues.push(ValidationIssue::InvalidSince {
+                    input_index: second_index,
+                    since: input.input.since().unpack(),
+                });
+            }
+        }
+    }
+
+    /// Fetch the sub celldeps of all dep group celldeps, the data of dep group is fetched as well if not resolved
+    async fn expand_dep_groups<T: RPC>(&self, rpc: &T) -> eyre::Result<Vec<CellOutputEx>> {
+        let dep_groups = self
+            .celldeps
+            .iter()
+            .filter(|v| v.celldep.dep_type() == DepType::DepGroup.into())
+            .collect::<Vec<_>>();
+        if dep_groups.is_empty() {
+            return Ok(vec![]);
+        }
+        let unresolved = dep_groups
+            .iter()
+            .filter(|v| !v.with_data)
+            .map(|v| v.celldep.out_point())
+            .collect::<Vec<_>>();
+        let mut fetched = get_live_cells(rpc, &unresolved, true)
+            .await?
+            .into_iter()
+            .map(|(_, data)| data.unwrap_or_default());
+        let mut sub_out_points: Vec<OutPoint> = vec![];
+        for celldep in dep_groups {
+            let data = if celldep.with_data {
+                celldep.output.data.clone()
+            } else {
+                fetched.next().unwrap_or_default()
+            };
+            let out_points = OutPointVec::from_slice(&data)
+                .map_err(|_| eyre!("invalid dep group celldep {}", celldep.name))?;
+            sub_out_points.extend(out_points);
+        }
+        let sub_celldeps = get_live_cells(rpc, &sub_out_points, true)
+            .await?
+            .into_iter()
+            .map(|(output, data)| CellOutputEx::new(output, data.unwrap_or_default()))
+            .collect();
+        Ok(sub_celldeps)
+    }
+
+    /// Check if scripts can be resolved by celldeps, `sub_celldeps` is None if dep groups are not expanded
+    fn validate_scripts(
+        &self,
+        sub_celldeps: Option<&[CellOutputEx]>,
+        issues: &mut Vec<ValidationIssue>,
+    ) {
+        let unexpanded = sub_celldeps.is_none()
+            && self
+                .celldeps
+                .iter()
+                .any(|v| v.celldep.dep_type() == DepType::DepGroup.into());
+        let sub_celldeps = sub_celldeps.unwrap_or_default();
+        let mut check = |field: &'static str, index: usize, script: Script| {
+            if is_type_id(&script) {
+                return;
+            }
+            let resolved = sub_celldeps.iter().any(|v| is_resolved_by(&script, v));
+            let script = ScriptEx::from(script);
+            if resolved || self.find_celldep_by_script(&script).is_some() {
+                return;
+            }
+            // the script may be resolved by one of unknown sub celldeps
+            if !unexpanded {
+                issues.push(ValidationIssue::UnresolvedScript {
+                    field,
+                    index,
+                    script_hash: script.script_hash().unwrap_or_default(),
+                });
+            }
+        };
+        for (index, input) in self.inputs.iter().enumerate() {
+            check("input", index, input.output.lock_script());
+            if let Some(type_script) = input.output.type_script() {
+                check("input", index, type_script);
+            }
+        }
+        for (index, output) in self.outputs.iter().enumerate() {
+            if let Some(type_script) = output.type_script() {
+                check("output", index, type_script);
+            }
+        }
+    }
+
+    fn validate_witnesses(&self, issues: &mut Vec<ValidationIssue>) {
+        if self.witnesses.len() < self.inputs.len() {
+            issues.push(ValidationIssue::MissingWitnesses {
+                inputs: self.inputs.len(),
+                witnesses: self.witnesses.len(),
+            });
+        }
+        for (witness_index, witness) in self.witnesses.iter().enumerate() {
+            if let Some(headerdep_index) = witness.headerdep_index() {
+                if headerdep_index >= self.headerdeps.len() {
+                    issues.push(ValidationIssue::HeaderDepIndexOutOfRange {
+                        witness_index,
+                        headerdep_index,
+                        headerdeps: self.headerdeps.len(),
+                    });
+                }
+            }
+        }
+    }
+
+    fn validate_type_ids(&self, issues: &mut Vec<ValidationIssue>) {
+        for (output_index, output) in self.outputs.iter().enumerate() {
+            let Some(type_script) = output.type_script() else {
+                continue;
+            };
+            if !is_type_id(&type_script) {
+                continue;
+            }
+            // type-id cell that is transferred from inputs keeps its original args
+            let transferred = self
+                .inputs
+                .iter()
+                .any(|v| v.output.type_script().as_ref() == Some(&type_script));
+            if transferred {
+                continue;
+            }
+            let Ok(expected) = self.calc_type_id(output_index) else {
+                continue;
+            };
+            let actual = type_script.args().raw_data().to_vec();
+            if actual != expected.as_bytes() {
+                issues.push(ValidationIssue::TypeIdMismatch {
+                    output_index,
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+}
+
+fn is_resolved_by(script: &Script, celldep: &CellOutputEx) -> bool {
+    let code_hash: H256 = script.code_hash().unpack();
+    if script.hash_type() == ScriptHashType::Type.into() {
+        celldep.calc_type_hash() == Some(code_hash)
+    } else {
+        celldep.data_hash() == code_hash
+    }
+}
+
+fn is_type_id(script: &Script) -> bool {
+    let code_hash: H256 = script.code_hash().unpack();
+    code_hash == TYPE_ID_CODE_HASH && script.hash_type() == ScriptHashType::Type.into()
+}
+
+/// Header of the block that committed the transaction, None if not committed yet
+async fn committed_header<T: RPC>(rpc: &T, tx_hash: &H256) -> eyre::Result<Option<HeaderView>> {
+    let Some(block_hash) = rpc
+        .get_transaction(tx_hash)
+        .await?
+        .and_then(|tx| tx.tx_status.block_hash)
+    else {
+        return Ok(None);
+    };
+    Ok(rpc.get_header(&block_hash).await?.map(Into::into))
+}
+
+/// Check if since is satisfied under tip, `base` is the committed header of input cell for relative since
+fn is_mature(since: &SinceEx, tip: &HeaderView, base: Option<&HeaderView>) -> eyre::Result<bool> {
+    let since = SinceEx::from_value(since.value()?)?;
+    let mature = match since {
+        SinceEx::BlockNumber { value, .. } => {
+            let start = base.map(|v| v.number()).unwrap_or_default();
+            tip.number() >= start.saturating_add(value)
+        }
+        SinceEx::Epoch {
+            number,
+            index,
+            length,
+            ..
+        } => {
+            let target =
+                EpochNumberWithFraction::new_unchecked(number, index, length).to_rational();
+            let target = match base {
+                Some(base) => base.epoch().to_rational() + target,
+                None => target,
+            };
+            tip.epoch().to_rational() >= target
+        }
+        // relative timestamp counts from the median time of the committed block, which is not available here
+        SinceEx::Timestamp { .. } if base.is_some() => true,
+        // header timestamp is in milliseconds, while since timestamp is in seconds
+        SinceEx::Timestamp { value, .. } => tip.timestamp() / 1000 >= value,
+        SinceEx::Raw(_) => unreachable!("decoded"),
+    };
+    Ok(mature)
+}
+
+#[cfg(test)]
+mod tests {
+    use ckb_hash::blake2b_256;
+    use ckb_types::{
+        core::HeaderBuilder,
+        packed::{CellDep, CellInput, CellOutput},
+        prelude::{Builder, Pack},
+    };
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{
+        simulation::FakeRpcClient,
+        skeleton::{CellDepEx, CellInputEx, WitnessEx},
+    };
+
+    const CODE: &[u8] = b"lock code";
+
+    fn out_point(tx: u8) -> OutPoint {
+        OutPoint::new_builder().tx_hash([tx; 32].pack()).build()
+    }
+
+    fn code_lock(code: &[u8]) -> Script {
+        ScriptEx::new_code(blake2b_256(code).into(), vec![]).to_script_unchecked()
+    }
+
+    fn cell(capacity: u64, lock: Script) -> CellOutput {
+        CellOutput::new_builder()
+            .capacity(Capacity::shannons(capacity).pack())
+            .lock(lock)
+            .build()
+    }
+
+    fn input(tx: u8, capacity: u64) -> CellInputEx {
+        let input = CellInput::new_builder()
+            .previous_output(out_point(tx))
+            .build();
+        CellInputEx::new(input, cell(capacity, code_lock(CODE)), None)
+    }
+
+    fn celldep(tx: u8, dep_type: DepType, data: Option<Vec<u8>>) -> CellDepEx {
+        let celldep = CellDep::new_builder()
+            .out_point(out_point(tx))
+            .dep_type(dep_type.into())
+            .build();
+        CellDepEx::new(
+            format!("celldep-{tx}"),
+            celldep,
+            CellOutput::default(),
+            data,
+        )
+    }
+
+    fn skeleton() -> TransactionSkeleton {
+        TransactionSkeleton {
+            inputs: vec![input(1, 1000_0000_0000)],
+            outputs: vec![CellOutputEx::new(
+                cell(500_0000_0000, code_lock(CODE)),
+                vec![],
+            )],
+            celldeps: vec![celldep(10, DepType::Code, Some(CODE.to_vec()))],
+            witnesses: vec![WitnessEx::default()],
+            ..Default::default()
+        }
+    }
+
+    fn issues(skeleton: &TransactionSkeleton) -> Vec<ValidationIssue> {
+        skeleton
+            .validate(Capacity::shannons(1000))
+            .err()
+            .map(|v| v.issues)
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn validate_passes_well_formed_skeleton() {
+        assert_eq!(issues(&skeleton()), vec![]);
+    }
+
+    #[test]
+    fn validate_reports_all_issues_in_one_pass() {
+        let mut skeleton = skeleton();
+        skeleton.inputs.push(input(1, 1000_0000_0000));
+        skeleton.outputs.push(CellOutputEx::new(
+            cell(5000_0000_0000, code_lock(b"unknown")),
+            vec![],
+        ));
+        skeleton.outputs[1].output = skeleton.outputs[1]
+            .output
+            .clone()
+            .as_builder()
+            .type_(Some(code_lock(b"unknown")).pack())
+            .build();
+        skeleton.witnesses = vec![WitnessEx::new_headerdep_reference(0)];
+        let issues = issues(&skeleton);
+        assert_eq!(
+            issues,
+            vec![
+                ValidationIssue::InsufficientInputCapacity {
+                    inputs: 2000_0000_0000,
+                    outputs: 5500_0000_0000,
+                    fee: 1000,
+                },
+                ValidationIssue::DuplicateInput {
+                    first_index: 0,
+                    second_index: 1,
+                },
+                ValidationIssue::UnresolvedScript {
+                    field: "output",
+                    index: 1,
+                    script_hash: code_lock(b"unknown").calc_script_hash().unpack(),
+                },
+                ValidationIssue::MissingWitnesses {
+                    inputs: 2,
+                    witnesses: 1,
+                },
+                ValidationIssue::HeaderDepIndexOutOfRange {
+                    witness_index: 0,
+                    headerdep_index: 0,
+                    headerdeps: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_reports_insufficient_output_capacity() {
+        let mut skeleton = skeleton();
+        skeleton.outputs[0].output = cell(1, code_lock(CODE));
+        assert!(matches!(
+            issues(&skeleton)[..],
+            [ValidationIssue::InsufficientOutputCapacity {
+                output_index: 0,
+                capacity: 1,
+                ..
+            }]
+        ));
+    }
+
+    fn header(number: u64) -> HeaderView {
+        HeaderBuilder::default()
+            .number(number.pack())
+            .epoch(EpochNumberWithFraction::new(number / 10, number % 10, 10).pack())
+            .timestamp((number * 10_000).pack())
+            .build()
+    }
+
+    #[test]
+    fn validate_since_against_tip() {
+        let mut rpc = FakeRpcClient::default();
+        rpc.set_fake_tip(100, header(100).into());
+        // the first input is committed at block 60, the second one is not committed yet
+        let committed = CellOutputEx::new(CellOutput::default(), vec![]);
+        rpc.insert_fake_cell(out_point(1), committed, Some(header(60)));
+        let validate = |sinces: [SinceEx; 2]| {
+            let mut skeleton = skeleton();
+            skeleton.inputs = vec![input(1, 0), input(2, 0)]
+                .into_iter()
+                .zip(sinces)
+                .map(|(mut input, since)| {
+                    // invalid raw since is set as it is
+                    let since = match since {
+                        SinceEx::Raw(since) => since,
+                        since => since.value().unwrap(),
+                    };
+                    input.input = input.input.as_builder().since(since.pack()).build();
+                    input
+                })
+                .collect();
+            block_on(skeleton.validate_since(&rpc))
+                .err()
+                .map(|e| e.downcast::<ValidationError>().unwrap().issues)
+                .unwrap_or_default()
+        };
+        let mature = [
+            SinceEx::block_number(30).relative(),
+            SinceEx::epoch(9, 5, 10),
+        ];
+        assert_eq!(validate(mature), vec![]);
+        let mature = [SinceEx::timestamp(1000), SinceEx::block_number(100)];
+        assert_eq!(validate(mature), vec![]);
+        let immature = [
+            SinceEx::epoch(4, 1, 10).relative(),
+            SinceEx::block_number(1).relative(),
+        ];
+        assert_eq!(
+            validate(immature),
+            vec![
+                ValidationIssue::ImmatureSince {
+                    input_index: 0,
+                    since: immature[0],
+                },
+                ValidationIssue::ImmatureSince {
+                    input_index: 1,
+                    since: immature[1],
+                },
+            ]
+        );
+        let immature = [
+            SinceEx::timestamp(1001),
+            SinceEx::Raw(0x0100_0000_0000_0000),
+        ];
+        assert_eq!(
+            validate(immature),
+            vec![
+                ValidationIssue::ImmatureSince {
+                    input_index: 0,
+                    since: immature[0],
+                },
+                ValidationIssue::InvalidSince {
+                    input_index: 1,
+                    since: 0x0100_0000_0000_0000,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_with_expands_dep_groups() {
+        let mut skeleton = skeleton();
+        let sub_out_points = OutPointVec::new_builder().push(out_point(10)).build();
+        skeleton.celldeps = vec![celldep(20, DepType::DepGroup, None)];
+        skeleton.outputs[0].output = skeleton.outputs[0]
+            .output
+            .clone()
+            .as_builder()
+            .type_(Some(code_lock(b"unknown")).pack())
+            .build();
+        // offline, scripts that cannot be resolved are skipped if any dep group exists
+        assert_eq!(issues(&skeleton), vec![]);
+
+        let mut rpc = FakeRpcClient::default();
+        rpc.insert_fake_cell(
+            out_point(20),
+            CellOutputEx::new(CellOutput::default(), sub_out_points.as_bytes().to_vec()),
+            None,
+        )
+        .insert_fake_cell(
+            out_point(10),
+            CellOutputEx::new(CellOutput::default(), CODE.to_vec()),
+            None,
+        );
+        let err = block_on(skeleton.validate_with(&rpc, Capacity::shannons(1000))).unwrap_err();
+        let err = err.downcast::<ValidationError>().unwrap();
+        // the lock is resolved by the sub celldep, while the type is not
+        assert_eq!(
+            err.issues,
+            vec![ValidationIssue::UnresolvedScript {
+                field: "output",
+                index: 0,
+                script_hash: code_lock(b"unknown").calc_script_hash().unpack(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,807 @@
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use ckb_jsonrpc_types::{OutputsValidator, Status};
+use ckb_types::{packed::OutPoint, H256};
+use eyre::{eyre, Result};
+use futures::channel::mpsc::UnboundedSender;
+
+use crate::{
+    bump::FeeBump,
+    locker,
+    rpc::{RpcError, RPC},
+    skeleton::TransactionSkeleton,
+};
+
+/// Default interval of polling transaction status
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Default upper bound of polling interval while backing off
+pub const DEFAULT_MAX_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Progress of a sent transaction, which is reported while waiting for confirmation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxProgress {
+    /// The transaction is accepted by tx pool
+    Sent(H256),
+    /// The transaction is in tx pool and not proposed yet
+    Pending(H256),
+    /// The transaction is in tx pool and has been proposed
+    Proposed(H256),
+    /// The transaction is committed in block, which is on the canonical chain at the moment
+    Committed {
+        hash: H256,
+        block_number: u64,
+        block_hash: H256,
+    },
+    /// The number of blocks that built on top of the committed block
+    Confirmations {
+        hash: H256,
+        confirmed: u64,
+        required: u64,
+    },
+    /// The committed block is not on the canonical chain anymore, the transaction is waited again
+    ReorgedOut { hash: H256, block_number: u64 },
+    /// The transaction is replaced with higher fee, refer to `FeeBump`
+    Replaced { old: H256, new: H256 },
+    /// The transaction is removed from tx pool
+    Rejected { hash: H256, reason: String },
+}
+
+impl Display for TxProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TxProgress::Sent(hash) => write!(f, "{hash:#x} sent"),
+            TxProgress::Pending(hash) => write!(f, "{hash:#x} pending"),
+            TxProgress::Proposed(hash) => write!(f, "{hash:#x} proposed"),
+            TxProgress::Committed {
+                hash, block_number, ..
+            } => write!(f, "{hash:#x} committed at block {block_number}"),
+            TxProgress::Confirmations {
+                hash,
+                confirmed,
+                required,
+            } => write!(f, "{hash:#x} confirmations {confirmed}/{required}"),
+            TxProgress::ReorgedOut { hash, block_number } => {
+                write!(f, "{hash:#x} reorged out from block {block_number}")
+            }
+            TxProgress::Replaced { old, new } => write!(f, "{old:#x} replaced by {new:#x}"),
+            TxProgress::Rejected { hash, reason } => {
+                write!(f, "{hash:#x} rejected, reason: {reason}")
+            }
+        }
+    }
+}
+
+/// Options of sending transaction and waiting for its confirmation
+///
+/// e.g. report progress in UI:
+/// ```ignore
+/// let (sender, mut progress) = futures::channel::mpsc::unbounded();
+/// let options = WaitOptions::new(3).progress(sender).backoff(2);
+/// tokio::spawn(async move {
+///     while let Some(status) = progress.next().await {
+///         println!("{status}");
+///     }
+/// });
+/// skeleton.send_and_wait_with(&rpc, options).await?;
+/// ```
+pub struct WaitOptions<'a, T: RPC> {
+    confirm_count: u8,
+    timeout: Option<Duration>,
+    poll_interval: Duration,
+    max_poll_interval: Duration,
+    backoff: u32,
+    max_unknown_polls: Option<u32>,
+    validate_since: bool,
+    progress: Option<UnboundedSender<TxProgress>>,
+    fee_bump: Option<&'a FeeBump<T>>,
+}
+
+impl<'a, T: RPC> WaitOptions<'a, T> {
+    /// `confirm_count`: wait how many blocks to firm confirmation, if 0, return immidiently after sending
+    pub fn new(confirm_count: u8) -> Self {
+        WaitOptions {
+            confirm_count,
+            timeout: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            max_poll_interval: DEFAULT_MAX_POLL_INTERVAL,
+            backoff: 1,
+            max_unknown_polls: None,
+            validate_since: false,
+            progress: None,
+            fee_bump: None,
+        }
+    }
+
+    /// Wait how much time until throwing timeout error, if None, no timeout
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn max_poll_interval(mut self, max_poll_interval: Duration) -> Self {
+        self.max_poll_interval = max_poll_interval;
+        self
+    }
+
+    /// Multiply the polling interval by `backoff` while the status stays unchanged, up to `max_poll_interval`
+    ///
+    /// note: the interval is reset once the status changes, 1 means no backoff
+    pub fn backoff(mut self, backoff: u32) -> Self {
+        self.backoff = backoff.max(1);
+        self
+    }
+
+    /// Fail once the transaction stays unknown to the node for `max_unknown_polls` polls in a row, e.g. it's evicted
+    /// from tx pool or the node is restarted
+    ///
+    /// note: unknown status is polled until timeout by default
+    pub fn max_unknown_polls(mut self, max_unknown_polls: Option<u32>) -> Self {
+        self.max_unknown_polls = max_unknown_polls.map(|v| v.max(1));
+        self
+    }
+
+    /// Check the since fields of inputs against the tip header before sending, refer to
+    /// `TransactionSkeleton::validate_since`
+    ///
+    /// note: an input with relative since whose cell is not committed yet is reported as immature, so don't enable it
+    /// when spending the outputs of pending transactions
+    pub fn validate_since(mut self, validate_since: bool) -> Self {
+        self.validate_since = validate_since;
+        self
+    }
+
+    /// Report progress into the channel, the receiver end is a stream of `TxProgress`
+    pub fn progress(mut self, progress: UnboundedSender<TxProgress>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Replace the transaction with higher fee if it stays pending for a while
+    pub fn fee_bump(mut self, fee_bump: &'a FeeBump<T>) -> Self {
+        self.fee_bump = Some(fee_bump);
+        self
+    }
+
+    pub(crate) fn confirm_count(&self) -> u8 {
+        self.confirm_count
+    }
+
+    pub(crate) fn validates_since(&self) -> bool {
+        self.validate_since
+    }
+
+    pub(crate) fn report(&self, progress: TxProgress) {
+        if let Some(sender) = &self.progress {
+            // the receiver may be dropped if no one cares about progress anymore
+            let _ = sender.unbounded_send(progress);
+        }
+    }
+}
+
+/// Failure of waiting transaction, rejection is distinguished since the inputs become available again
+pub(crate) enum WaitError {
+    Rejected(eyre::Error),
+    Other(eyre::Error),
+}
+
+impl WaitError {
+    pub(crate) fn into_report(self) -> eyre::Error {
+        match self {
+            WaitError::Rejected(e) | WaitError::Other(e) => e,
+        }
+    }
+}
+
+impl From<eyre::Error> for WaitError {
+    fn from(e: eyre::Error) -> Self {
+        WaitError::Other(e)
+    }
+}
+
+impl From<RpcError> for WaitError {
+    fn from(e: RpcError) -> Self {
+        WaitError::Other(e.into())
+    }
+}
+
+/// Trace a sent transaction and its replacements until one of them is confirmed on the canonical chain
+pub(crate) struct Watcher<'a> {
+    pub skeleton: TransactionSkeleton,
+    pub hashes: Vec<H256>,
+    pub out_points: &'a mut Vec<OutPoint>,
+}
+
+impl Watcher<'_> {
+    pub async fn wait_for_confirmation<T: RPC>(
+        &mut self,
+        rpc: &T,
+        options: &WaitOptions<'_, T>,
+    ) -> std::result::Result<H256, WaitError> {
+        let mut hash = self.hashes[0].clone();
+        let required = options.confirm_count as u64;
+        let mut committed: Option<(u64, H256)> = None;
+        let mut last_progress: Option<TxProgress> = None;
+        let mut pending_since = match options.fee_bump {
+            Some(_) => u64::from(rpc.get_tip_block_number().await?),
+            None => 0,
+        };
+        let start = Instant::now();
+        let mut interval = options.poll_interval;
+        let mut unknown_polls = 0;
+        loop {
+            if let Some(timeout) = options.timeout {
+                if start.elapsed() > timeout {
+                    return Err(WaitError::Other(eyre!("timeout waiting tx: {hash:#x}")));
+                }
+            }
+            tokio::time::sleep(interval).await;
+            let tx = rpc
+                .get_transaction(&hash)
+                .await?
+                .ok_or(eyre!("no tx found: {hash:#x}"))?;
+            let status = tx.tx_status;
+            if status.status != Status::Unknown {
+                unknown_polls = 0;
+            }
+            // the committed block is not canonical anymore if the status or block hash changes
+            if let Some((block_number, block_hash)) = &committed {
+                if status.status != Status::Committed
+                    || status.block_hash.as_ref() != Some(block_hash)
+                {
+                    options.report(TxProgress::ReorgedOut {
+                        hash: hash.clone(),
+                        block_number: *block_number,
+                    });
+                    committed = None;
+                }
+            }
+            let progress = match status.status {
+                Status::Rejected => {
+                    // the replaced one may be committed before its replacement
+                    if let Some(ancestor) = self.committed_ancestor(rpc, &hash).await? {
+                        hash = ancestor;
+                        continue;
+                    }
+                    let reason = status.reason.unwrap_or_else(|| "unknown".to_string());
+                    options.report(TxProgress::Rejected {
+                        hash: hash.clone(),
+                        reason: reason.clone(),
+                    });
+                    return Err(WaitError::Rejected(eyre!(
+                        "tx {hash:#x} rejected, reason: {reason}"
+                    )));
+                }
+                Status::Pending | Status::Proposed => {
+                    if let Some(fee_bump) = options.fee_bump {
+                        let bumps = self.hashes.len() - 1;
+                        let tip_number = u64::from(rpc.get_tip_block_number().await?);
+                        if bumps < fee_bump.max_bumps
+                            && tip_number >= pending_since + fee_bump.pending_blocks
+                        {
+                            let new_hash = self.replace(rpc, &hash, fee_bump).await?;
+                            options.report(TxProgress::Replaced {
+                                old: hash,
+                                new: new_hash.clone(),
+                            });
+                            hash = new_hash;
+                            pending_since = tip_number;
+                        }
+                    }
+                    if status.status == Status::Pending {
+                        TxProgress::Pending(hash.clone())
+                    } else {
+                        TxProgress::Proposed(hash.clone())
+                    }
+                }
+                Status::Committed => {
+                    let (Some(block_number), Some(block_hash)) =
+                        (status.block_number, status.block_hash)
+                    else {
+                        continue;
+                    };
+                    let block_number = u64::from(block_number);
+                    if committed.is_none() {
+                        options.report(TxProgress::Committed {
+                            hash: hash.clone(),
+                            block_number,
+                            block_hash: block_hash.clone(),
+                        });
+                        committed = Some((block_number, block_hash.clone()));
+                    }
+                    let tip_number = u64::from(rpc.get_tip_block_number().await?);
+                    let confirmed = tip_number.saturating_sub(block_number).min(required);
+                    if confirmed >= required {
+                        // re-verify the inclusion at the final confirmation
+                        if rpc.get_block_hash(block_number.into()).await? == Some(block_hash) {
+                            options.report(TxProgress::Confirmations {
+                                hash: hash.clone(),
+                                confirmed,
+                                required,
+                            });
+                            return Ok(hash);
+                        }
+                        options.report(TxProgress::ReorgedOut {
+                            hash: hash.clone(),
+                            block_number,
+                        });
+                        committed = None;
+                        continue;
+                    }
+                    TxProgress::Confirmations {
+                        hash: hash.clone(),
+                        confirmed,
+                        required,
+                    }
+                }
+                Status::Unknown => {
+                    unknown_polls += 1;
+                    if options
+                        .max_unknown_polls
+                        .is_some_and(|max| unknown_polls >= max)
+                    {
+                        return Err(WaitError::Other(eyre!(
+                            "tx {hash:#x} stays unknown to the node after {unknown_polls} polls"
+                        )));
+                    }
+                    continue;
+                }
+            };
+            if last_progress.as_ref() == Some(&progress) {
+                interval = (interval * options.backoff).min(options.max_poll_interval);
+            } else {
+                interval = options.poll_interval;
+                options.report(progress.clone());
+                last_progress = Some(progress);
+            }
+        }
+    }
+
+    async fn replace<T: RPC>(
+        &mut self,
+        rpc: &T,
+        hash: &H256,
+        fee_bump: &FeeBump<T>,
+    ) -> Result<H256> {
+        let bumps = self.hashes.len() - 1;
+        let bumped = fee_bump.bump(rpc, &self.skeleton, hash, bumps).await?;
+        let new_out_points = locker::input_out_points(&bumped)
+            .into_iter()
+            .filter(|v| !self.out_points.contains(v))
+            .collect::<Vec<_>>();
+        if let Some(locker) = rpc.cell_locker() {
+            locker.lock_in_flight(new_out_points.clone());
+        }
+        bumped.keep_reservations();
+        self.out_points.extend(new_out_points);
+        let new_hash = rpc
+            .send_transaction(bumped.clone().into(), Some(OutputsValidator::Passthrough))
+            .await?;
+        self.skeleton = bumped;
+        self.hashes.push(new_hash.clone());
+        Ok(new_hash)
+    }
+
+    async fn committed_ancestor<T: RPC>(&self, rpc: &T, hash: &H256) -> Result<Option<H256>> {
+        for ancestor in self.hashes.iter().filter(|v| *v != hash) {
+            if let Some(tx) = rpc.get_transaction(ancestor).await? {
+                if tx.tx_status.status == Status::Committed {
+                    return Ok(Some(ancestor.clone()));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    };
+
+    use ckb_jsonrpc_types::{
+        BlockNumber, BlockView, CellWithStatus, HeaderView, JsonBytes, OutPoint as JsonOutPoint,
+        Transaction, TransactionWithStatusResponse, TxPoolInfo, TxStatus,
+    };
+    use ckb_sdk::rpc::ckb_indexer::{Cell, Pagination, SearchKey};
+    use ckb_types::{
+        core::Capacity,
+        packed::{self, CellInput, CellOutput, Script},
+        prelude::{Builder, Entity, Pack, Unpack},
+    };
+    use futures::channel::mpsc::unbounded;
+
+    use super::*;
+    use crate::{
+        instruction::Instruction,
+        locker::CellLocker,
+        rpc::Rpc,
+        simulation::FakeRpcClient,
+        skeleton::{CellInputEx, CellOutputEx, ScriptEx},
+    };
+
+    const CKB: u64 = 100_000_000;
+
+    /// Decide the status of a transaction by the number of `get_transaction` calls and the sent transactions
+    type StatusScript = fn(u64, &H256, &[H256]) -> TxStatus;
+
+    /// Chain that grows one block on each tip query, whose canonical block hashes are `block_hash(number, 0)`
+    #[derive(Clone)]
+    struct ScriptedRpc {
+        inner: FakeRpcClient,
+        tip: Arc<AtomicU64>,
+        polls: Arc<AtomicU64>,
+        sent: Arc<Mutex<Vec<packed::Transaction>>>,
+        script: StatusScript,
+    }
+
+    impl ScriptedRpc {
+        fn new(tip: u64, script: StatusScript) -> Self {
+            let mut inner = FakeRpcClient::default();
+            inner.fake_provider.fake_feerate = 1000;
+            ScriptedRpc {
+                inner,
+                tip: Arc::new(AtomicU64::new(tip)),
+                polls: Arc::new(AtomicU64::new(0)),
+                sent: Arc::new(Mutex::new(vec![])),
+                script,
+            }
+        }
+
+        fn sent(&self) -> Vec<packed::Transaction> {
+            self.sent.lock().unwrap().clone()
+        }
+
+        fn sent_hashes(&self) -> Vec<H256> {
+            self.sent()
+                .iter()
+                .map(|tx| tx.calc_tx_hash().unpack())
+                .collect()
+        }
+    }
+
+    impl RPC for ScriptedRpc {
+        fn url(&self) -> (String, String) {
+            self.inner.url()
+        }
+
+        fn cell_locker(&self) -> Option<&CellLocker> {
+            self.inner.cell_locker()
+        }
+
+        fn get_live_cell(&self, out_point: &JsonOutPoint, with_data: bool) -> Rpc<CellWithStatus> {
+            self.inner.get_live_cell(out_point, with_data)
+        }
+
+        fn get_cells(
+            &self,
+            search_key: SearchKey,
+            limit: u32,
+            cursor: Option<JsonBytes>,
+        ) -> Rpc<Pagination<Cell>> {
+            self.inner.get_cells(search_key, limit, cursor)
+        }
+
+        fn get_block_by_number(&self, number: BlockNumber) -> Rpc<Option<BlockView>> {
+            self.inner.get_block_by_number(number)
+        }
+
+        fn get_block(&self, hash: &H256) -> Rpc<Option<BlockView>> {
+            self.inner.get_block(hash)
+        }
+
+        fn get_header(&self, hash: &H256) -> Rpc<Option<HeaderView>> {
+            self.inner.get_header(hash)
+        }
+
+        fn get_header_by_number(&self, number: BlockNumber) -> Rpc<Option<HeaderView>> {
+            self.inner.get_header_by_number(number)
+        }
+
+        fn get_block_hash(&self, number: BlockNumber) -> Rpc<Option<H256>> {
+            let hash = block_hash(number.into(), 0);
+            Box::pin(async move { Ok(Some(hash)) })
+        }
+
+        fn get_tip_block_number(&self) -> Rpc<BlockNumber> {
+            let tip = self.tip.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move { Ok(tip.into()) })
+        }
+
+        fn get_tip_header(&self) -> Rpc<HeaderView> {
+            self.inner.get_tip_header()
+        }
+
+        fn tx_pool_info(&self) -> Rpc<TxPoolInfo> {
+            self.inner.tx_pool_info()
+        }
+
+        fn get_transaction(&self, hash: &H256) -> Rpc<Option<TransactionWithStatusResponse>> {
+            let polls = self.polls.fetch_add(1, Ordering::SeqCst) + 1;
+            let tx_status = (self.script)(polls, hash, &self.sent_hashes());
+            let min_replace_fee = match tx_status.status {
+                Status::Pending | Status::Proposed => Some(1.into()),
+                _ => None,
+            };
+            let tx = TransactionWithStatusResponse {
+                transaction: None,
+                cycles: None,
+                time_added_to_pool: None,
+                fee: None,
+                min_replace_fee,
+                tx_status,
+            };
+            Box::pin(async move { Ok(Some(tx)) })
+        }
+
+        fn send_transaction(
+            &self,
+            tx: Transaction,
+            _outputs_validator: Option<OutputsValidator>,
+        ) -> Rpc<H256> {
+            let tx = packed::Transaction::from(tx);
+            let hash = tx.calc_tx_hash().unpack();
+            self.sent.lock().unwrap().push(tx);
+            Box::pin(async move { Ok(hash) })
+        }
+    }
+
+    fn block_hash(number: u64, fork: u8) -> H256 {
+        let mut hash = [fork; 32];
+        hash[..8].copy_from_slice(&number.to_le_bytes());
+        H256(hash)
+    }
+
+    fn pending() -> TxStatus {
+        TxStatus {
+            status: Status::Pending,
+            block_hash: None,
+            block_number: None,
+            reason: None,
+        }
+    }
+
+    fn committed(block_number: u64, fork: u8) -> TxStatus {
+        TxStatus {
+            status: Status::Committed,
+            block_hash: Some(block_hash(block_number, fork)),
+            block_number: Some(block_number.into()),
+            reason: None,
+        }
+    }
+
+    fn lock(args: u8) -> Script {
+        ScriptEx::new_code(H256::default(), vec![args]).to_script_unchecked()
+    }
+
+    fn capacity_cell(capacity: u64, lock_args: u8) -> CellOutputEx {
+        let output = CellOutput::new_builder()
+            .capacity(Capacity::shannons(capacity).pack())
+            .lock(lock(lock_args))
+            .build();
+        CellOutputEx::new(output, vec![])
+    }
+
+    /// Transfer 500 CKB from lock args 1 to 2 with an underpaid fee of 100 shannons, the change goes back to lock args 1
+    fn transfer() -> TransactionSkeleton {
+        let input = CellInput::new_builder()
+            .previous_output(
+                packed::OutPoint::new_builder()
+                    .tx_hash([1u8; 32].pack())
+                    .build(),
+            )
+            .build();
+        let mut skeleton = TransactionSkeleton::default();
+        skeleton
+            .input(CellInputEx::new(
+                input,
+                capacity_cell(1000 * CKB, 1).output,
+                None,
+            ))
+            .unwrap()
+            .output(capacity_cell(500 * CKB, 2))
+            .output(capacity_cell(500 * CKB - 100, 1));
+        skeleton
+    }
+
+    /// Send `skeleton` under `options` and collect the reported progress
+    fn send_and_wait(
+        rpc: &ScriptedRpc,
+        skeleton: TransactionSkeleton,
+        options: WaitOptions<'_, ScriptedRpc>,
+    ) -> (Result<H256>, Vec<TxProgress>) {
+        let (sender, mut receiver) = unbounded();
+        let options = options
+            .poll_interval(Duration::from_millis(1))
+            .progress(sender);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let result = runtime.block_on(skeleton.send_and_wait_with(rpc, options));
+        let mut progress = vec![];
+        while let Ok(Some(value)) = receiver.try_next() {
+            progress.push(value);
+        }
+        (result, progress)
+    }
+
+    #[test]
+    fn fee_bump_stops_at_max_bumps() {
+        let rpc = ScriptedRpc::new(0, |polls, hash, sent| {
+            // keep pending until the last replacement is committed
+            if polls > 6 && sent.last() == Some(hash) {
+                committed(1, 0)
+            } else {
+                pending()
+            }
+        });
+        let fee_bump = FeeBump::new(lock(1).into(), || Instruction::new(vec![]))
+            .pending_blocks(1)
+            .max_bumps(2);
+        let options = WaitOptions::new(1).fee_bump(&fee_bump);
+        let (result, progress) = send_and_wait(&rpc, transfer(), options);
+        let hashes = rpc.sent_hashes();
+        // the original one and two replacements, though the last one stays pending for more blocks
+        assert_eq!(hashes.len(), 3);
+        assert_eq!(result.unwrap(), hashes[2]);
+        let replaced = progress
+            .iter()
+            .filter_map(|v| match v {
+                TxProgress::Replaced { old, new } => Some((old.clone(), new.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            replaced,
+            vec![
+                (hashes[0].clone(), hashes[1].clone()),
+                (hashes[1].clone(), hashes[2].clone())
+            ]
+        );
+        // each replacement raises the fee from the change cell only
+        let sent = rpc.sent();
+        for (old, new) in sent.iter().zip(sent.iter().skip(1)) {
+            let (old, new) = (old.raw(), new.raw());
+            assert_eq!(old.inputs().as_slice(), new.inputs().as_slice());
+            assert_eq!(
+                old.outputs().get(0).unwrap().as_slice(),
+                new.outputs().get(0).unwrap().as_slice()
+            );
+            let change = |outputs: packed::CellOutputVec| -> u64 {
+                outputs.get(1).unwrap().capacity().unpack()
+            };
+            assert!(change(new.outputs()) < change(old.outputs()));
+        }
+    }
+
+    #[test]
+    fn reorged_out_tx_is_waited_again() {
+        let rpc = ScriptedRpc::new(5, |polls, _, _| {
+            // the node reports block 5 of a stale fork, until the tx is committed again on the canonical chain
+            if polls <= 2 {
+                committed(5, 1)
+            } else {
+                committed(6, 0)
+            }
+        });
+        let (result, progress) = send_and_wait(&rpc, transfer(), WaitOptions::new(2));
+        let hash = rpc.sent_hashes()[0].clone();
+        assert_eq!(result.unwrap(), hash);
+        assert_eq!(
+            progress,
+            vec![
+                TxProgress::Sent(hash.clone()),
+                TxProgress::Committed {
+                    hash: hash.clone(),
+                    block_number: 5,
+                    block_hash: block_hash(5, 1),
+                },
+                TxProgress::Confirmations {
+                    hash: hash.clone(),
+                    confirmed: 1,
+                    required: 2,
+                },
+                // block hash of the final confirmation differs from the canonical one
+                TxProgress::ReorgedOut {
+                    hash: hash.clone(),
+                    block_number: 5,
+                },
+                TxProgress::Committed {
+                    hash: hash.clone(),
+                    block_number: 6,
+                    block_hash: block_hash(6, 0),
+                },
+                TxProgress::Confirmations {
+                    hash,
+                    confirmed: 2,
+                    required: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn wait_for_confirmation_depth() {
+        let rpc = ScriptedRpc::new(10, |_, _, _| committed(10, 0));
+        let (result, progress) = send_and_wait(&rpc, transfer(), WaitOptions::new(3));
+        assert!(result.is_ok());
+        let confirmations = progress
+            .iter()
+            .filter_map(|v| match v {
+                TxProgress::Confirmations {
+                    confirmed,
+                    required,
+                    ..
+                } => Some((*confirmed, *required)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(confirmations, vec![(1, 3), (2, 3), (3, 3)]);
+        // returned once the tip reaches 3 blocks on top of the committed one
+        assert_eq!(rpc.tip.load(Ordering::SeqCst), 13);
+
+        // no confirmation required, so return right after sending
+        let rpc = ScriptedRpc::new(10, |_, _, _| committed(10, 0));
+        let (result, progress) = send_and_wait(&rpc, transfer(), WaitOptions::new(0));
+        assert_eq!(result.unwrap(), rpc.sent_hashes()[0]);
+        assert_eq!(progress.len(), 1);
+        assert_eq!(rpc.polls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn unknown_status_is_polled_by_default() {
+        let rpc = ScriptedRpc::new(1, |polls, _, _| {
+            if polls <= 20 {
+                TxStatus {
+                    status: Status::Unknown,
+                    block_hash: None,
+                    block_number: None,
+                    reason: None,
+                }
+            } else {
+                committed(1, 0)
+            }
+        });
+        let (result, _) = send_and_wait(&rpc, transfer(), WaitOptions::new(1));
+        assert_eq!(result.unwrap(), rpc.sent_hashes()[0]);
+        assert_eq!(rpc.polls.load(Ordering::SeqCst), 21);
+    }
+
+    #[test]
+    fn unknown_status_fails_after_limit() {
+        let hash = H256::default();
+        let mut rpc = FakeRpcClient::default();
+        rpc.fake_provider.fake_transaction_status.insert(
+            hash.clone(),
+            TxStatus {
+                status: Status::Unknown,
+                block_hash: None,
+                block_number: None,
+                reason: None,
+            },
+        );
+        let options = WaitOptions::new(1)
+            .poll_interval(Duration::from_millis(1))
+            .max_unknown_polls(Some(3));
+        let mut out_points = vec![];
+        let mut watcher = Watcher {
+            skeleton: TransactionSkeleton::default(),
+            hashes: vec![hash],
+            out_points: &mut out_points,
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let result = runtime.block_on(watcher.wait_for_confirmation(&rpc, &options));
+        let Err(WaitError::Other(e)) = result else {
+            panic!("unknown status should fail");
+        };
+        assert!(e.to_string().contains("after 3 polls"));
+    }
+}
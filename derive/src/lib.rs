@@ -0,0 +1,170 @@
+//! Derive macro of `Operation` trait in `ckb-cinnabar-calculator`
+//!
+//! The derived `Operation::run` delegates to an inherent async method, by default named `execute`, then
+//! emits the declared log event and runs the declared celldep operations in sequence:
+//!
+//! ```ignore
+//! #[derive(Operation)]
+//! #[operation(log = Event::NewClusterId, celldep(AddClusterCelldep {}))]
+//! pub struct AddClusterOutputCell { ... }
+//!
+//! impl AddClusterOutputCell {
+//!     async fn execute<T: RPC>(
+//!         self,
+//!         rpc: &T,
+//!         skeleton: &mut TransactionSkeleton,
+//!         log: &mut Log,
+//!     ) -> Result<H256> {
+//!         ...
+//!     }
+//! }
+//! ```
+//!
+//! Supported arguments in `#[operation(...)]`, which can be repeated:
+//! - `method = name`: the inherent async method to delegate to
+//! - `log = expr`: a callable that turns the returned value of method into `Event`, e.g. `Event::NewSporeId`
+//! - `celldep(expr)`: an operation that runs after the method, e.g. `celldep(AddSporeCelldep {})`
+//! - `params`: summarize parameters by `serde::Serialize` implementation of the struct
+//! - `params = name`: summarize parameters by an inherent method `fn(&self) -> serde_json::Value`, e.g. to redact
+//!   secrets
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, Expr, Ident, Result};
+
+/// Arguments collected from `#[operation(...)]` attributes
+struct OperationArgs {
+    method: Ident,
+    log: Option<Expr>,
+    celldeps: Vec<Expr>,
+    params: Option<Params>,
+}
+
+/// How to summarize parameters in `Operation::params`
+enum Params {
+    Serialize,
+    Method(Ident),
+}
+
+impl OperationArgs {
+    fn parse(input: &DeriveInput) -> Result<Self> {
+        let mut args = OperationArgs {
+            method: Ident::new("execute", proc_macro2::Span::call_site()),
+            log: None,
+            celldeps: vec![],
+            params: None,
+        };
+        for attr in input
+            .attrs
+            .iter()
+            .filter(|v| v.path().is_ident("operation"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("method") {
+                    args.method = meta.value()?.parse()?;
+                } else if meta.path.is_ident("log") {
+                    if args.log.is_some() {
+                        return Err(meta.error("duplicated `log` argument"));
+                    }
+                    args.log = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("celldep") {
+                    let content;
+                    syn::parenthesized!(content in meta.input);
+                    args.celldeps.push(content.parse()?);
+                } else if meta.path.is_ident("params") {
+                    if args.params.is_some() {
+                        return Err(meta.error("duplicated `params` argument"));
+                    }
+                    args.params = if meta.input.peek(syn::Token![=]) {
+                        Some(Params::Method(meta.value()?.parse()?))
+                    } else {
+                        Some(Params::Serialize)
+                    };
+                } else {
+                    return Err(meta.error("expected `method`, `log`, `celldep` or `params`"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(args)
+    }
+}
+
+#[proc_macro_derive(Operation, attributes(operation))]
+pub fn derive_operation(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    let OperationArgs {
+        method,
+        log,
+        celldeps,
+        params,
+    } = OperationArgs::parse(&input)?;
+    let name = &input.ident;
+    let calculator = quote!(::ckb_cinnabar_calculator);
+
+    let mut generics = input.generics.clone();
+    generics
+        .params
+        .push(syn::parse_quote!(__T: #calculator::rpc::RPC));
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, type_generics, _) = input.generics.split_for_impl();
+
+    let execute = match log {
+        Some(log) => quote! {
+            let value = Self::#method(*self, rpc, skeleton, log).await?;
+            log.emit::<Self>(skeleton, (#log)(value));
+        },
+        None => quote! {
+            Self::#method(*self, rpc, skeleton, log).await?;
+        },
+    };
+    let celldeps = celldeps.iter().map(|celldep| {
+        quote! {
+            #calculator::operation::Operation::<__T>::run(
+                ::std::boxed::Box::new(#celldep),
+                rpc,
+                skeleton,
+                log,
+            )
+            .await?;
+        }
+    });
+    let params = params.map(|params| {
+        let summary = match params {
+            Params::Serialize => {
+                quote!(#calculator::re_exports::serde_json::to_value(self).unwrap_or_default())
+            }
+            Params::Method(method) => quote!(Self::#method(self)),
+        };
+        quote! {
+            fn params(&self) -> #calculator::re_exports::serde_json::Value {
+                #summary
+            }
+        }
+    });
+
+    Ok(quote! {
+        #[#calculator::re_exports::async_trait::async_trait]
+        impl #impl_generics #calculator::operation::Operation<__T> for #name #type_generics #where_clause {
+            #params
+
+            async fn run(
+                self: ::std::boxed::Box<Self>,
+                rpc: &__T,
+                skeleton: &mut #calculator::skeleton::TransactionSkeleton,
+                log: &mut #calculator::operation::Log,
+            ) -> #calculator::re_exports::eyre::Result<()> {
+                #execute
+                #(#celldeps)*
+                Ok(())
+            }
+        }
+    })
+}
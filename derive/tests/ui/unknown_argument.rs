@@ -0,0 +1,7 @@
+use ckb_cinnabar_calculator_derive::Operation;
+
+#[derive(Operation)]
+#[operation(methods = execute)]
+struct UnknownArgument {}
+
+fn main() {}
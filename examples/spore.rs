@@ -5,12 +5,7 @@ use ckb_cinnabar_calculator::{
         balance_and_sign_with_ckb_cli, burn_spores, mint_clusters, mint_spores, transfer_clusters,
         transfer_spores, Cluster, Spore,
     },
-    operation::spore::hookkey,
-    re_exports::{
-        ckb_sdk::Address,
-        ckb_types::{packed::Script, prelude::Entity, H256},
-        tokio,
-    },
+    re_exports::{ckb_sdk::Address, ckb_types::H256, tokio},
     rpc::{RpcClient, RPC},
     skeleton::ScriptEx,
     TransactionCalculator,
@@ -190,19 +185,17 @@ pub async fn main() {
         .new_skeleton(&rpc)
         .await
         .expect("spore calculate");
-    for (key, value) in log {
-        match key {
-            hookkey::NEW_SPORE_ID => println!("new spore_id: {}", hex::encode(value)),
-            hookkey::NEW_CLUSTER_ID => println!("new cluster_id: {}", hex::encode(value)),
-            hookkey::CLUSTER_CELL_OWNER_LOCK => {
-                let lock_script = Script::from_compatible_slice(&value).unwrap();
-                let address = ScriptEx::from(lock_script)
-                    .to_address(rpc.network())
-                    .unwrap();
-                signers.insert(address);
-            }
-            _ => {}
-        };
+    for spore_id in log.new_spore_ids() {
+        println!("new spore_id: {spore_id:#x}");
+    }
+    for cluster_id in log.new_cluster_ids() {
+        println!("new cluster_id: {cluster_id:#x}");
+    }
+    for lock_script in log.cluster_owner_locks() {
+        let address = ScriptEx::from(lock_script)
+            .to_address(rpc.network())
+            .unwrap();
+        signers.insert(address);
     }
     // TODO: there's a bug if signing more than once through ckb-cli, need to find out why
     let signs = signers
@@ -57,7 +57,7 @@ pub fn create_rpc_from_network(network: &Network) -> eyre::Result<RpcClient> {
         Network::Mainnet => Ok(RpcClient::new_mainnet()),
         Network::Testnet => Ok(RpcClient::new_testnet()),
         Network::Fake => Err(eyre::eyre!("fake network")),
-        Network::Custom(url) => Ok(RpcClient::new(url.as_str(), None)),
+        Network::Custom(url) => RpcClient::builder().endpoint(url.as_str(), None).build(),
     }
 }
 
@@ -95,7 +95,7 @@ pub async fn send_and_record_transaction<T: RPC>(
         occupied_capacity,
         payer_address: payer_address.into(),
         contract_owner_address: contract_owner_address.into(),
-        type_id: type_id.map(Into::into),
+        type_id,
         comment: None,
     };
     save_contract_deployment(tx_path, deployment_record)
@@ -47,11 +47,11 @@ pub async fn deploy_contract(
             data: contract_binary,
             add_type_id: type_id,
         }),
-        Box::new(BalanceTransaction {
-            balancer: payer_address.clone().into(),
-            change_receiver: ChangeReceiver::Address(payer_address.clone()),
-            additional_fee_rate: 2000,
-        }),
+        Box::new(BalanceTransaction::new(
+            payer_address.clone().into(),
+            ChangeReceiver::Address(payer_address.clone()),
+            2000,
+        )),
         Box::new(AddSecp256k1SighashSignaturesWithCkbCli {
             signer_address: payer_address.clone(),
             cache_path: format!("{deployment_path}/txs").into(),
@@ -134,11 +134,11 @@ pub async fn migrate_contract(
         }
     }
     migrate_contract.append(vec![
-        Box::new(BalanceTransaction {
-            balancer: payer_address.clone().into(),
-            change_receiver: ChangeReceiver::Address(payer_address.clone()),
-            additional_fee_rate: 2000,
-        }),
+        Box::new(BalanceTransaction::new(
+            payer_address.clone().into(),
+            ChangeReceiver::Address(payer_address.clone()),
+            2000,
+        )),
         Box::new(AddSecp256k1SighashSignaturesWithCkbCli {
             signer_address: payer_address.clone(),
             cache_path: format!("{deployment_path}/txs").into(),
@@ -184,11 +184,11 @@ pub async fn consume_contract(
             index: deployment.out_index,
             since: None,
         }),
-        Box::new(BalanceTransaction {
-            balancer: payer_address.payload().into(),
-            change_receiver: ChangeReceiver::Address(receiver_address),
-            additional_fee_rate: 2000,
-        }),
+        Box::new(BalanceTransaction::new(
+            payer_address.payload().into(),
+            ChangeReceiver::Address(receiver_address),
+            2000,
+        )),
         Box::new(AddSecp256k1SighashSignaturesWithCkbCli {
             signer_address: payer_address.clone(),
             cache_path: format!("{deployment_path}/txs").into(),
@@ -45,8 +45,10 @@ impl<T: Default> TransactionVerifier<T> {
 
 /// Examples:
 ///
-/// ```
-/// use ckb_cinnabar_verifier::{cinnabar_main, TREE_ROOT, define_errors, CUSTOM_ERROR_START};
+/// ```no_run
+/// use ckb_cinnabar_verifier::{
+///     cinnabar_main, define_errors, Result, Verification, CUSTOM_ERROR_START, TREE_ROOT,
+/// };
 ///
 /// // define custom errors
 /// define_errors!(CustomError, {
@@ -56,6 +58,7 @@ impl<T: Default> TransactionVerifier<T> {
 /// });
 ///
 /// // contains the global context data
+/// #[derive(Default)]
 /// struct GlobalContext {
 ///    // some fields
 /// }
@@ -77,7 +80,7 @@ impl<T: Default> TransactionVerifier<T> {
 /// struct BranchVerifier;
 ///
 /// // must implement Verification trait
-/// impl Verification<GlobalContext> for RootVerifier {
+/// impl Verification<GlobalContext> for BranchVerifier {
 ///    fn verify(&mut self, verifier_name: &str, ctx: &mut GlobalContext) -> Result<Option<&str>> {
 ///         // ...
 ///         Ok(None) // end of the verification